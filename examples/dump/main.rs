@@ -3,20 +3,22 @@ use std::fs::File;
 use std::io::{self, Write};
 use std::path::PathBuf;
 use std::str::{self, FromStr};
+use std::sync::Arc;
 
 use anyhow::{bail, Error};
-use log::debug;
-use memmap::Mmap;
+use btf::Btf;
+use log::{debug, warn};
 use serde::Serialize;
 use structopt::StructOpt;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum Format {
     Text,
-    JSON,
-    PrettyJSON,
-    YAML,
+    Json,
+    PrettyJson,
+    Yaml,
     Rust,
+    C,
 }
 
 impl FromStr for Format {
@@ -25,9 +27,10 @@ impl FromStr for Format {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
             "text" => Ok(Format::Text),
-            "json" => Ok(Format::JSON),
-            "yaml" => Ok(Format::YAML),
+            "json" => Ok(Format::Json),
+            "yaml" => Ok(Format::Yaml),
             "rust" => Ok(Format::Rust),
+            "c" => Ok(Format::C),
             _ => bail!("unknown format: {}", s),
         }
     }
@@ -56,7 +59,11 @@ struct Opt {
     #[structopt(short, long)]
     rust: bool,
 
-    /// Output format (text, json, yaml or rust)
+    /// Generate C header output.
+    #[structopt(short, long)]
+    c: bool,
+
+    /// Output format (text, json, yaml, rust or c)
     #[structopt(short, long, default_value = "text")]
     format: Format,
 
@@ -68,6 +75,10 @@ struct Opt {
     #[structopt(short, long, parse(from_os_str))]
     base_btf: Option<PathBuf>,
 
+    /// Recover from malformed types instead of stopping at the first one.
+    #[structopt(short, long)]
+    lenient: bool,
+
     /// Files to process
     #[structopt(name = "FILE", parse(from_os_str))]
     file: PathBuf,
@@ -78,13 +89,15 @@ impl Opt {
         if self.text {
             Format::Text
         } else if self.pretty {
-            Format::PrettyJSON
+            Format::PrettyJson
         } else if self.json {
-            Format::JSON
+            Format::Json
         } else if self.yaml {
-            Format::YAML
+            Format::Yaml
         } else if self.rust {
             Format::Rust
+        } else if self.c {
+            Format::C
         } else {
             self.format
         }
@@ -99,11 +112,18 @@ struct Types<'a> {
 }
 
 impl<'a> Types<'a> {
-    pub fn new(types: btf::Types<'a>) -> Result<Types<'a>, Error> {
+    pub fn new(types: &mut btf::Types<'a>) -> Result<Types<'a>, Error> {
+        let start_id = types.start_id() as usize;
+
         Ok(Types {
             types: types
                 .enumerate()
-                .map(|(idx, res)| res.map(|ty| Type { id: idx + 1, ty }))
+                .map(|(idx, res)| {
+                    res.map(|ty| Type {
+                        id: start_id + idx,
+                        ty,
+                    })
+                })
                 .collect::<Result<Vec<_>, btf::Error>>()?,
         })
     }
@@ -123,7 +143,7 @@ impl<'a> fmt::Display for TextFmt<'a> {
         write!(f, "[{}] ", self.0.id)?;
 
         match &self.0.ty {
-            btf::Type::Void => write!(f, "VOID\n"),
+            btf::Type::Void => writeln!(f, "VOID"),
             btf::Type::Int {
                 name,
                 size,
@@ -131,23 +151,23 @@ impl<'a> fmt::Display for TextFmt<'a> {
                 nr_bits,
                 encoding,
             } => {
-                write!(
+                writeln!(
                     f,
-                    "INT '{}' size={} bits_offset={} nr_bits={} encoding={:?}\n",
+                    "INT '{}' size={} bits_offset={} nr_bits={} encoding={:?}",
                     name, size, bits_offset, nr_bits, encoding
                 )
             }
             btf::Type::Ptr { type_id } => {
-                write!(f, "PTR '{}' type_id={}\n", ANON, type_id)
+                writeln!(f, "PTR '{}' type_id={}", ANON, type_id)
             }
             btf::Type::Array {
                 type_id,
                 index_type_id,
                 nr_elems,
             } => {
-                write!(
+                writeln!(
                     f,
-                    "ARRAY '{}' type_id={} index_type_id={} nr_elems={}\n",
+                    "ARRAY '{}' type_id={} index_type_id={} nr_elems={}",
                     ANON, type_id, index_type_id, nr_elems
                 )
             }
@@ -156,10 +176,10 @@ impl<'a> fmt::Display for TextFmt<'a> {
                 size,
                 members,
             } => {
-                write!(
+                writeln!(
                     f,
-                    "STRUCT '{}' size={} vlen={}\n",
-                    name.unwrap_or(ANON),
+                    "STRUCT '{}' size={} vlen={}",
+                    name.as_deref().unwrap_or(ANON),
                     size,
                     members.len()
                 )?;
@@ -168,7 +188,7 @@ impl<'a> fmt::Display for TextFmt<'a> {
                     write!(
                         f,
                         "\t'{}' type_id={} bits_offset={}",
-                        m.name.unwrap_or(ANON),
+                        m.name.as_deref().unwrap_or(ANON),
                         m.type_id,
                         m.bits_offset
                     )?;
@@ -187,10 +207,10 @@ impl<'a> fmt::Display for TextFmt<'a> {
                 size,
                 members,
             } => {
-                write!(
+                writeln!(
                     f,
-                    "UNION '{}' size={} vlen={}\n",
-                    name.unwrap_or(ANON),
+                    "UNION '{}' size={} vlen={}",
+                    name.as_deref().unwrap_or(ANON),
                     size,
                     members.len()
                 )?;
@@ -199,7 +219,7 @@ impl<'a> fmt::Display for TextFmt<'a> {
                     write!(
                         f,
                         "\t'{}' type_id={} bits_offset={}",
-                        m.name.unwrap_or(ANON),
+                        m.name.as_deref().unwrap_or(ANON),
                         m.type_id,
                         m.bits_offset
                     )?;
@@ -213,66 +233,84 @@ impl<'a> fmt::Display for TextFmt<'a> {
 
                 Ok(())
             }
-            btf::Type::Enum { name, size, values } => {
-                write!(
+            btf::Type::Enum {
+                name,
+                size,
+                signed,
+                values,
+            }
+            | btf::Type::Enum64 {
+                name,
+                size,
+                signed,
+                values,
+            } => {
+                writeln!(
                     f,
-                    "ENUM '{}' size={} vlen={}\n",
-                    name.unwrap_or(ANON),
+                    "{} '{}' encoding={} size={} vlen={}",
+                    if self.0.ty.is_enum_64() {
+                        "ENUM64"
+                    } else {
+                        "ENUM"
+                    },
+                    name.as_deref().unwrap_or(ANON),
+                    if *signed { "SIGNED" } else { "UNSIGNED" },
                     size,
                     values.len()
                 )?;
 
                 for v in values {
-                    write!(f, "\t'{}' val={}\n", v.name.unwrap_or(ANON), v.val)?;
+                    writeln!(f, "\t'{}' val={}", v.name.as_deref().unwrap_or(ANON), v.val)?;
                 }
 
                 Ok(())
             }
             btf::Type::Fwd { name, fwd_kind } => {
-                write!(
+                writeln!(
                     f,
-                    "FWD '{}' fwd_kind={}\n",
+                    "FWD '{}' fwd_kind={}",
                     name,
                     fwd_kind.to_string().to_lowercase()
                 )
             }
             btf::Type::Typedef { name, type_id } => {
-                write!(f, "TYPEDEF '{}' type_id={}\n", name, type_id)
+                writeln!(f, "TYPEDEF '{}' type_id={}", name, type_id)
             }
             btf::Type::Volatile { type_id } => {
-                write!(f, "VOLATILE '{}' type_id={}\n", ANON, type_id)
+                writeln!(f, "VOLATILE '{}' type_id={}", ANON, type_id)
             }
             btf::Type::Const { type_id } => {
-                write!(f, "CONST '{}' type_id={}\n", ANON, type_id)
+                writeln!(f, "CONST '{}' type_id={}", ANON, type_id)
             }
             btf::Type::Restrict { type_id } => {
-                write!(f, "RESTRICT '{}' type_id={}\n", ANON, type_id)
+                writeln!(f, "RESTRICT '{}' type_id={}", ANON, type_id)
             }
             btf::Type::Func {
                 name,
                 type_id,
                 linkage,
             } => {
-                write!(
-                    f,
-                    "FUNC '{}' type_id={} linkage={}\n",
-                    name, type_id, linkage
-                )
+                writeln!(f, "FUNC '{}' type_id={} linkage={}", name, type_id, linkage)
             }
             btf::Type::FuncProto {
                 ret_type_id,
                 params,
             } => {
-                write!(
+                writeln!(
                     f,
-                    "FUNC_PROTO '{}' ret_type_id={} vlen={}\n",
+                    "FUNC_PROTO '{}' ret_type_id={} vlen={}",
                     ANON,
                     ret_type_id,
                     params.len()
                 )?;
 
                 for p in params {
-                    write!(f, "\t'{}' type_id={}\n", p.name.unwrap_or(ANON), p.type_id)?;
+                    writeln!(
+                        f,
+                        "\t'{}' type_id={}",
+                        p.name.as_deref().unwrap_or(ANON),
+                        p.type_id
+                    )?;
                 }
 
                 Ok(())
@@ -282,34 +320,34 @@ impl<'a> fmt::Display for TextFmt<'a> {
                 type_id,
                 linkage,
             } => {
-                write!(
-                    f,
-                    "VAR '{}' type_id={}, linkage={}\n",
-                    name, type_id, linkage
-                )
+                writeln!(f, "VAR '{}' type_id={}, linkage={}", name, type_id, linkage)
             }
             btf::Type::DataSec {
                 name,
                 size,
                 sections,
             } => {
-                write!(
+                writeln!(
                     f,
-                    "DATASEC '{}' size={} vlen={}\n",
+                    "DATASEC '{}' size={} vlen={}",
                     name,
                     size,
                     sections.len()
                 )?;
 
                 for s in sections {
-                    write!(
+                    writeln!(
                         f,
-                        "\ttype_id={} offset={} size={} (VAR '{}')\n",
+                        "\ttype_id={} offset={} size={} (VAR '{}')",
                         s.type_id,
                         s.offset,
                         s.size,
-                        if let btf::Type::Variable { name, .. } =
-                            self.1[(s.type_id - 1) as usize].ty
+                        if let Some(btf::Type::Variable { name, .. }) = self
+                            .1
+                            .first()
+                            .and_then(|t| (s.type_id as usize).checked_sub(t.id))
+                            .and_then(|idx| self.1.get(idx))
+                            .map(|t| &t.ty)
                         {
                             name
                         } else {
@@ -321,21 +359,21 @@ impl<'a> fmt::Display for TextFmt<'a> {
                 Ok(())
             }
             btf::Type::Float { name, size } => {
-                write!(f, "FLOAT '{}' size={}\n", name, size)
+                writeln!(f, "FLOAT '{}' size={}", name, size)
             }
             btf::Type::DeclTag {
                 name,
                 type_id,
                 component_idx,
             } => {
-                write!(
+                writeln!(
                     f,
-                    "DECL_TAG '{}' type_id={} component_idx={}\n",
+                    "DECL_TAG '{}' type_id={} component_idx={}",
                     name, type_id, component_idx
                 )
             }
             btf::Type::TypeTag { name, type_id } => {
-                write!(f, "TYPE_TAG '{}' type_id={}\n", name, type_id)
+                writeln!(f, "TYPE_TAG '{}' type_id={}", name, type_id)
             }
         }
     }
@@ -355,47 +393,59 @@ fn main() -> Result<(), Error> {
         either::Right(io::stdout().lock())
     };
 
-    let f = File::open(&opt.file)?;
-    let mm = unsafe { Mmap::map(&f)? };
-    let types = btf::parse(&mm)?;
+    let mut btf = Btf::from_path(&opt.file)?;
 
-    let base_btf = opt
-        .base_btf
-        .map(|file| -> Result<_, Error> {
-            let f = File::open(file)?;
-            let mm = unsafe { Mmap::map(&f)? };
-            Ok(mm)
-        })
-        .transpose()?;
-    let base_types = base_btf
-        .as_ref()
-        .map(|mm| btf::parse(&mm)?.collect())
+    if let Some(ref path) = opt.base_btf {
+        btf = btf.with_base(Arc::new(Btf::from_path(path)?));
+    }
+
+    let base_types = btf
+        .base()
+        .map(|base| base.types().collect::<Result<Vec<_>, _>>())
         .transpose()?;
 
+    let mut types = btf.types();
+
+    if opt.lenient {
+        types = types.lenient();
+    }
+
     match format {
-        Format::JSON => {
-            serde_json::to_writer(&mut w, &Types::new(types)?)?;
+        Format::Json => {
+            serde_json::to_writer(&mut w, &Types::new(&mut types)?)?;
         }
-        Format::PrettyJSON => {
-            serde_json::to_writer_pretty(&mut w, &Types::new(types)?)?;
+        Format::PrettyJson => {
+            serde_json::to_writer_pretty(&mut w, &Types::new(&mut types)?)?;
         }
-        Format::YAML => {
-            serde_yaml::to_writer(&mut w, &Types::new(types)?)?;
+        Format::Yaml => {
+            serde_yaml::to_writer(&mut w, &Types::new(&mut types)?)?;
         }
         Format::Text => {
-            let types = Types::new(types)?;
+            let types = Types::new(&mut types)?;
 
             for res in &types.types {
                 write!(&mut w, "{}", TextFmt(res, &types.types))?;
             }
         }
         Format::Rust => {
-            let types = types.collect::<Result<Vec<_>, btf::Error>>()?;
+            let types = types.by_ref().collect::<Result<Vec<_>, btf::Error>>()?;
 
-            let src = btf::rust::dump(base_types.as_ref().map(Vec::as_slice), types.as_slice());
+            let src = btf::rust::dump(base_types.as_deref(), types.as_slice());
 
             w.write_all(src.as_bytes())?;
         }
+        Format::C => {
+            let src = btf::c::Dumper::new(&btf.table())
+                .preserve_access_index(true)
+                .header_guard("__VMLINUX_H__")
+                .dump()?;
+
+            w.write_all(src.as_bytes())?;
+        }
+    }
+
+    for diag in types.diagnostics() {
+        warn!("{}", diag);
     }
 
     Ok(())
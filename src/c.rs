@@ -0,0 +1,1003 @@
+use core::mem;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "std")] {
+        use std::collections::BTreeMap;
+    } else {
+        use alloc::{
+            collections::BTreeMap,
+            format,
+            string::{String, ToString},
+            vec,
+            vec::Vec,
+        };
+    }
+}
+
+use crate::{
+    file::Kind,
+    layout::{self, Layout},
+    table::TypeTable,
+    ty::{EnumValue, Type},
+    Error::{self, *},
+};
+
+/// Render the type the way C declares it, e.g. `const struct sk_buff *` or `char [16]`.
+pub fn type_name(types: &TypeTable, type_id: u32) -> Result<String, Error> {
+    type_decl(types, type_id, "")
+}
+
+/// Render the declaration of `name` with the type, e.g. `int (*handler)(int)`.
+pub fn type_decl(types: &TypeTable, type_id: u32, name: &str) -> Result<String, Error> {
+    Printer::new(types, None).decl(type_id, String::from(name), 0)
+}
+
+/// Render the type as the cast of a value, without modifiers and with bare anonymous types, e.g. `struct`.
+pub(crate) fn type_cast(types: &TypeTable, type_id: u32) -> Result<String, Error> {
+    let mut p = Printer::new(types, None);
+
+    p.cast = true;
+    p.decl(type_id, String::new(), 0)
+}
+
+/// Generate a C header declaring all the types, like `bpftool btf dump format c`.
+pub fn dump(types: &TypeTable) -> Result<String, Error> {
+    Dumper::new(types).dump()
+}
+
+/// A generator of C headers, such as `vmlinux.h`, following the algorithm of libbpf's `btf_dump`.
+///
+/// The types are declared in dependency order, with forward declarations
+/// where the types refer to each other through pointers. Anonymous structs,
+/// unions and enums are defined inline where they are used, and explicit
+/// padding keeps the layout of the structs the same as in BTF.
+pub struct Dumper<'t, 'a> {
+    printer: Printer<'t, 'a>,
+    preserve_access_index: bool,
+    header_guard: Option<String>,
+    states: Vec<State>,
+    queue: Vec<u32>,
+    out: String,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct State {
+    order: Order,
+    emit: Emit,
+    fwd_emitted: bool,
+    referenced: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Order {
+    #[default]
+    NotOrdered,
+    Ordering,
+    Ordered,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Emit {
+    #[default]
+    NotEmitted,
+    Emitting,
+    Emitted,
+}
+
+impl<'t, 'a> Dumper<'t, 'a> {
+    /// Declare the types of `types`, and those of its base for split BTF.
+    pub fn new(types: &'t TypeTable<'a>) -> Self {
+        Dumper {
+            printer: Printer::new(types, Some(Names::default())),
+            preserve_access_index: false,
+            header_guard: None,
+            states: vec![State::default(); types.end_id() as usize],
+            queue: Vec::new(),
+            out: String::new(),
+        }
+    }
+
+    /// Lay out the structs for a target whose pointers are `ptr_size` bytes.
+    ///
+    /// By default, the pointer size is guessed from the types, see [`layout::guess_ptr_size`].
+    pub fn ptr_size(mut self, ptr_size: usize) -> Self {
+        self.printer.ptr_size = ptr_size;
+        self
+    }
+
+    /// Wrap the declarations in the `preserve_access_index` attribute, for CO-RE.
+    ///
+    /// The attribute can be disabled by defining `BPF_NO_PRESERVE_ACCESS_INDEX`.
+    pub fn preserve_access_index(mut self, enabled: bool) -> Self {
+        self.preserve_access_index = enabled;
+        self
+    }
+
+    /// Guard the header against multiple inclusion with the macro, e.g. `__VMLINUX_H__`.
+    pub fn header_guard<S: Into<String>>(mut self, guard: S) -> Self {
+        self.header_guard = Some(guard.into());
+        self
+    }
+
+    pub fn dump(mut self) -> Result<String, Error> {
+        if let Some(ref guard) = self.header_guard {
+            self.out
+                .push_str(&format!("#ifndef {0}\n#define {0}\n\n", guard));
+        }
+
+        if self.preserve_access_index {
+            self.out.push_str(concat!(
+                "#ifndef BPF_NO_PRESERVE_ACCESS_INDEX\n",
+                "#pragma clang attribute push (__attribute__((preserve_access_index)), apply_to = record)\n",
+                "#endif\n\n"
+            ));
+        }
+
+        self.mark_referenced()?;
+
+        for type_id in 1..self.types().end_id() {
+            self.order(type_id, false)?;
+
+            for type_id in mem::take(&mut self.queue) {
+                self.emit(type_id, 0)?;
+            }
+        }
+
+        if self.preserve_access_index {
+            self.out.push_str(concat!(
+                "#ifndef BPF_NO_PRESERVE_ACCESS_INDEX\n",
+                "#pragma clang attribute pop\n",
+                "#endif\n\n"
+            ));
+        }
+
+        if let Some(ref guard) = self.header_guard {
+            self.out.push_str(&format!("#endif /* {} */\n", guard));
+        }
+
+        Ok(self.out)
+    }
+
+    fn types(&self) -> &'t TypeTable<'a> {
+        self.printer.types
+    }
+
+    fn state(&mut self, type_id: u32) -> &mut State {
+        &mut self.states[type_id as usize]
+    }
+
+    /// Mark the types referred to by other types, an anonymous enum is only declared on its own if it isn't.
+    fn mark_referenced(&mut self) -> Result<(), Error> {
+        for type_id in 1..self.types().end_id() {
+            let refs = match self.types().get(type_id)? {
+                Type::Ptr { type_id }
+                | Type::Typedef { type_id, .. }
+                | Type::Volatile { type_id }
+                | Type::Const { type_id }
+                | Type::Restrict { type_id }
+                | Type::Func { type_id, .. }
+                | Type::Variable { type_id, .. }
+                | Type::DeclTag { type_id, .. }
+                | Type::TypeTag { type_id, .. } => vec![type_id],
+                Type::Array {
+                    type_id,
+                    index_type_id,
+                    ..
+                } => vec![type_id, index_type_id],
+                Type::Struct { members, .. } | Type::Union { members, .. } => {
+                    members.iter().map(|m| m.type_id).collect()
+                }
+                Type::FuncProto {
+                    ret_type_id,
+                    params,
+                } => params
+                    .iter()
+                    .map(|p| p.type_id)
+                    .chain(Some(ret_type_id))
+                    .collect(),
+                Type::DataSec { sections, .. } => sections.iter().map(|s| s.type_id).collect(),
+                _ => continue,
+            };
+
+            for type_id in refs {
+                if let Some(state) = self.states.get_mut(type_id as usize) {
+                    state.referenced = true;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Queue the named types to declare before `type_id`, returns `true` if it must be fully defined first.
+    ///
+    /// A type only referred to through a pointer only needs a forward declaration.
+    fn order(&mut self, type_id: u32, through_ptr: bool) -> Result<bool, Error> {
+        let ty = self.types().get(type_id)?;
+
+        match self.state(type_id).order {
+            Order::Ordered => return Ok(true),
+            Order::Ordering => {
+                // a loop through a pointer is resolved by a forward declaration
+                return match ty {
+                    Type::Struct { name: Some(_), .. } | Type::Union { name: Some(_), .. }
+                        if through_ptr =>
+                    {
+                        Ok(false)
+                    }
+                    Type::Typedef { .. } if through_ptr => Ok(false),
+                    _ => Err(Cycle(type_id)),
+                };
+            }
+            Order::NotOrdered => {}
+        }
+
+        self.state(type_id).order = Order::Ordering;
+
+        let res = self.order_type(type_id, &ty, through_ptr);
+
+        let state = self.state(type_id);
+
+        match res {
+            Ok(true) => state.order = Order::Ordered,
+            Ok(false) if state.order == Order::Ordering => state.order = Order::NotOrdered,
+            Ok(false) => {}
+            Err(_) => state.order = Order::NotOrdered,
+        }
+
+        res
+    }
+
+    fn order_type(&mut self, type_id: u32, ty: &Type, through_ptr: bool) -> Result<bool, Error> {
+        match *ty {
+            Type::Void | Type::Int { .. } | Type::Float { .. } => {
+                self.state(type_id).order = Order::Ordered;
+
+                Ok(false)
+            }
+            Type::Ptr { type_id } => self.order(type_id, true),
+            Type::Array { type_id, .. } => self.order(type_id, false),
+            Type::Struct {
+                ref name,
+                ref members,
+                ..
+            }
+            | Type::Union {
+                ref name,
+                ref members,
+                ..
+            } => {
+                // a named struct or union only needs a forward declaration through a pointer
+                if through_ptr && name.is_some() {
+                    return Ok(false);
+                }
+
+                for m in members {
+                    self.order(m.type_id, false)?;
+                }
+
+                if name.is_some() {
+                    self.queue.push(type_id);
+                }
+
+                Ok(true)
+            }
+            Type::Enum { ref name, .. } | Type::Enum64 { ref name, .. } => {
+                // an anonymous enum is only declared on its own if nothing refers to it
+                if name.is_some() || !self.state(type_id).referenced {
+                    self.queue.push(type_id);
+                }
+
+                Ok(true)
+            }
+            Type::Fwd { .. } => {
+                self.queue.push(type_id);
+
+                Ok(true)
+            }
+            Type::Typedef {
+                type_id: target, ..
+            } => {
+                let strong = self.order(target, through_ptr)?;
+
+                if through_ptr && !strong {
+                    return Ok(false);
+                }
+
+                self.queue.push(type_id);
+
+                Ok(true)
+            }
+            Type::Volatile { type_id }
+            | Type::Const { type_id }
+            | Type::Restrict { type_id }
+            | Type::TypeTag { type_id, .. } => self.order(type_id, through_ptr),
+            Type::FuncProto {
+                ret_type_id,
+                ref params,
+            } => {
+                self.order(ret_type_id, through_ptr)?;
+
+                for p in params {
+                    self.order(p.type_id, through_ptr)?;
+                }
+
+                Ok(false)
+            }
+            Type::Func { .. }
+            | Type::Variable { .. }
+            | Type::DataSec { .. }
+            | Type::DeclTag { .. } => {
+                self.state(type_id).order = Order::Ordered;
+
+                Ok(false)
+            }
+        }
+    }
+
+    /// Declare `type_id` and the types it depends on, as a part of the declaration of `cont_id`.
+    fn emit(&mut self, type_id: u32, cont_id: u32) -> Result<(), Error> {
+        let state = *self.state(type_id);
+
+        if state.emit == Emit::Emitted {
+            return Ok(());
+        }
+
+        let top_level = cont_id == 0;
+        let ty = self.types().get(type_id)?;
+
+        if state.emit == Emit::Emitting {
+            // the type depends on itself, declare it ahead if possible
+            if state.fwd_emitted {
+                return Ok(());
+            }
+
+            match ty {
+                Type::Struct { ref name, .. } | Type::Union { ref name, .. } => {
+                    if type_id == cont_id {
+                        return Ok(());
+                    }
+                    if name.is_none() {
+                        return Err(Cycle(type_id));
+                    }
+
+                    let fwd = self.printer.struct_fwd(type_id, &ty);
+
+                    self.push_decl(fwd);
+                }
+                // a typedef is its own forward declaration
+                Type::Typedef { ref name, .. } if !is_builtin(name) => {
+                    let def = self.printer.typedef_def(type_id, &ty, 0)?;
+
+                    self.push_decl(def);
+                }
+                _ => {}
+            }
+
+            self.state(type_id).fwd_emitted = true;
+
+            return Ok(());
+        }
+
+        match ty {
+            Type::Enum { .. } | Type::Enum64 { .. } => {
+                if top_level {
+                    let def = self.printer.enum_def(type_id, &ty, 0);
+
+                    self.push_decl(def);
+                }
+
+                self.state(type_id).emit = Emit::Emitted;
+            }
+            Type::Ptr { type_id }
+            | Type::Volatile { type_id }
+            | Type::Const { type_id }
+            | Type::Restrict { type_id }
+            | Type::TypeTag { type_id, .. }
+            | Type::Array { type_id, .. } => self.emit(type_id, cont_id)?,
+            Type::Fwd { .. } => {
+                let def = self.printer.struct_fwd(type_id, &ty);
+
+                self.push_decl(def);
+                self.state(type_id).emit = Emit::Emitted;
+            }
+            Type::Typedef {
+                ref name,
+                type_id: target,
+            } => {
+                self.state(type_id).emit = Emit::Emitting;
+                self.emit(target, type_id)?;
+
+                // someone may have depended on the typedef through a pointer, and declared it already
+                if !self.state(type_id).fwd_emitted && !is_builtin(name) {
+                    let def = self.printer.typedef_def(type_id, &ty, 0)?;
+
+                    self.push_decl(def);
+                }
+
+                self.state(type_id).emit = Emit::Emitted;
+            }
+            Type::Struct {
+                ref name,
+                ref members,
+                ..
+            }
+            | Type::Union {
+                ref name,
+                ref members,
+                ..
+            } => {
+                self.state(type_id).emit = Emit::Emitting;
+
+                // the members of a definition and of an anonymous type are declared inline
+                if top_level || name.is_none() {
+                    let cont_id = if name.is_none() { cont_id } else { type_id };
+
+                    for m in members {
+                        self.emit(m.type_id, cont_id)?;
+                    }
+                } else if !self.state(type_id).fwd_emitted && type_id != cont_id {
+                    let fwd = self.printer.struct_fwd(type_id, &ty);
+
+                    self.push_decl(fwd);
+                    self.state(type_id).fwd_emitted = true;
+                }
+
+                if top_level {
+                    let def = self.printer.struct_def(type_id, &ty, 0)?;
+
+                    self.push_decl(def);
+                    self.state(type_id).emit = Emit::Emitted;
+                } else {
+                    self.state(type_id).emit = Emit::NotEmitted;
+                }
+            }
+            Type::FuncProto {
+                ret_type_id,
+                ref params,
+            } => {
+                self.emit(ret_type_id, cont_id)?;
+
+                for p in params {
+                    self.emit(p.type_id, cont_id)?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn push_decl(&mut self, decl: String) {
+        self.out.push_str(&decl);
+        self.out.push_str(";\n\n");
+    }
+}
+
+/// `__builtin_va_list` is defined by the compiler, and may differ between compilers.
+fn is_builtin(name: &str) -> bool {
+    name == "__builtin_va_list"
+}
+
+/// The names of the declarations, made unique by appending `___N` like libbpf.
+#[derive(Debug, Default)]
+struct Names {
+    cached: BTreeMap<u32, String>,
+    /// The names of structs, unions, enums and forward declarations.
+    tags: BTreeMap<String, usize>,
+    /// The names of typedefs and enumerators.
+    idents: BTreeMap<String, usize>,
+}
+
+impl Names {
+    fn resolve(&mut self, type_id: u32, name: &str, ident: bool) -> String {
+        if let Some(name) = self.cached.get(&type_id) {
+            return name.clone();
+        }
+
+        let dups = if ident {
+            dup(&mut self.idents, name)
+        } else {
+            dup(&mut self.tags, name)
+        };
+        let unique = unique_name(name, dups);
+
+        self.cached.insert(type_id, unique.clone());
+
+        unique
+    }
+
+    fn enumerator(&mut self, name: &str) -> String {
+        unique_name(name, dup(&mut self.idents, name))
+    }
+}
+
+fn dup(names: &mut BTreeMap<String, usize>, name: &str) -> usize {
+    let n = names.entry(name.to_string()).or_default();
+
+    *n += 1;
+    *n
+}
+
+fn unique_name(name: &str, dups: usize) -> String {
+    if dups > 1 {
+        format!("{}___{}", name, dups)
+    } else {
+        String::from(name)
+    }
+}
+
+struct Printer<'t, 'a> {
+    types: &'t TypeTable<'a>,
+    ptr_size: usize,
+    /// The unique names of a header, `None` to render type expressions.
+    names: Option<Names>,
+    /// The function prototypes being rendered, to detect a parameter referring back to them.
+    protos: Vec<u32>,
+    /// Render the type of a cast, like libbpf's `strip_mods` and `skip_anon_defs`.
+    cast: bool,
+}
+
+impl<'t, 'a> Printer<'t, 'a> {
+    fn new(types: &'t TypeTable<'a>, names: Option<Names>) -> Self {
+        Printer {
+            types,
+            ptr_size: layout::guess_ptr_size(types).unwrap_or(mem::size_of::<usize>()),
+            names,
+            protos: Vec::new(),
+            cast: false,
+        }
+    }
+
+    fn layout(&self) -> Layout<'t, 'a> {
+        Layout::new(self.types, self.ptr_size)
+    }
+
+    /// Wrap the declarator around `inner`, from the outermost type to the base type.
+    fn decl(&mut self, type_id: u32, mut inner: String, lvl: usize) -> Result<String, Error> {
+        let mut qualifiers = String::new();
+        let mut id = type_id;
+
+        for _ in 0..=self.types.end_id() {
+            let ty = self.types.get(id)?;
+
+            let base = match ty {
+                Type::Void => String::from("void"),
+                Type::Int { ref name, .. } | Type::Float { ref name, .. } => name.to_string(),
+                Type::Typedef { ref name, .. } => match self.names {
+                    Some(ref mut names) => names.resolve(id, name, true),
+                    None => name.to_string(),
+                },
+                Type::Struct { ref name, .. } | Type::Union { ref name, .. } => {
+                    match (self.names.is_some(), name.is_some()) {
+                        (true, false) => self.struct_def(id, &ty, lvl)?,
+                        _ => self.struct_fwd(id, &ty),
+                    }
+                }
+                Type::Enum { ref name, .. } | Type::Enum64 { ref name, .. } => {
+                    match (self.names.is_some(), name.is_some()) {
+                        (true, false) => self.enum_def(id, &ty, lvl),
+                        _ => self.struct_fwd(id, &ty),
+                    }
+                }
+                Type::Fwd { .. } => self.struct_fwd(id, &ty),
+                Type::Ptr { type_id } => {
+                    inner.insert(0, '*');
+                    id = type_id;
+                    continue;
+                }
+                Type::Const { type_id } => {
+                    self.qualify("const", type_id, &mut qualifiers, &mut inner)?;
+                    id = type_id;
+                    continue;
+                }
+                Type::Volatile { type_id } => {
+                    self.qualify("volatile", type_id, &mut qualifiers, &mut inner)?;
+                    id = type_id;
+                    continue;
+                }
+                Type::Restrict { type_id } => {
+                    self.qualify("restrict", type_id, &mut qualifiers, &mut inner)?;
+                    id = type_id;
+                    continue;
+                }
+                Type::TypeTag { type_id, .. } | Type::Variable { type_id, .. } => {
+                    id = type_id;
+                    continue;
+                }
+                Type::Func { name, type_id, .. } => {
+                    if inner.is_empty() {
+                        inner = name.to_string();
+                    }
+
+                    id = type_id;
+                    continue;
+                }
+                Type::Array {
+                    type_id, nr_elems, ..
+                } => {
+                    inner = format!("{}[{}]", parenthesize(inner), nr_elems);
+                    id = type_id;
+                    continue;
+                }
+                Type::FuncProto {
+                    ret_type_id,
+                    params,
+                } => {
+                    if self.protos.contains(&id) {
+                        return Err(Cycle(id));
+                    }
+
+                    self.protos.push(id);
+
+                    let params = params
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, p)| {
+                            if p.type_id != 0 {
+                                let name = match self.names {
+                                    Some(_) => p.name.as_deref().unwrap_or_default(),
+                                    None => "",
+                                };
+
+                                self.decl(p.type_id, String::from(name), lvl)
+                            } else if idx + 1 == params.len() && idx > 0 {
+                                Ok(String::from("..."))
+                            } else {
+                                // clang emits a single void param for a function without params
+                                Ok(String::from("void"))
+                            }
+                        })
+                        .collect::<Result<Vec<_>, Error>>();
+
+                    self.protos.pop();
+
+                    let params = params?;
+
+                    inner = format!(
+                        "{}({})",
+                        parenthesize(inner),
+                        if params.is_empty() {
+                            String::from("void")
+                        } else {
+                            params.join(", ")
+                        }
+                    );
+                    id = ret_type_id;
+                    continue;
+                }
+                Type::DataSec { .. } | Type::DeclTag { .. } => {
+                    return Err(Unexpected("type without a C declaration"))
+                }
+            };
+
+            // the array of a cast has no space before it, like libbpf's, e.g. `char[16]`
+            if self.cast && inner.starts_with('[') {
+                qualifiers.push_str(&base);
+                qualifiers.push_str(&inner);
+            } else {
+                qualifiers.push_str(&join(&base, &inner));
+            }
+
+            return Ok(qualifiers);
+        }
+
+        Err(Cycle(type_id))
+    }
+
+    /// The qualifier of a pointer follows the `*`, the others precede the base type.
+    fn qualify(
+        &self,
+        qualifier: &str,
+        target: u32,
+        qualifiers: &mut String,
+        inner: &mut String,
+    ) -> Result<(), Error> {
+        if self.cast {
+            return Ok(());
+        }
+
+        if self.types.kind(target)? == Kind::Pointer {
+            *inner = join(qualifier, inner);
+        } else {
+            qualifiers.push_str(qualifier);
+            qualifiers.push(' ');
+        }
+
+        Ok(())
+    }
+
+    /// The name of a struct, union, enum or forward declaration, unique in a header.
+    fn tag_name(&mut self, type_id: u32, name: Option<&str>) -> String {
+        match (name, self.names.as_mut()) {
+            (Some(name), Some(names)) => names.resolve(type_id, name, false),
+            (Some(name), None) => String::from(name),
+            (None, Some(_)) => String::new(),
+            (None, None) if self.cast => String::new(),
+            (None, None) => String::from("{...}"),
+        }
+    }
+
+    /// Refer to a struct, union or enum by its name, e.g. `struct sk_buff`.
+    fn struct_fwd(&mut self, type_id: u32, ty: &Type) -> String {
+        let tag = match *ty {
+            Type::Union { .. } => "union",
+            Type::Enum { .. } | Type::Enum64 { .. } => "enum",
+            Type::Fwd { fwd_kind, .. } if fwd_kind.is_union() => "union",
+            _ => "struct",
+        };
+
+        join(tag, &self.tag_name(type_id, ty.name()))
+    }
+
+    fn typedef_def(&mut self, type_id: u32, ty: &Type, lvl: usize) -> Result<String, Error> {
+        let (name, target) = match *ty {
+            Type::Typedef { ref name, type_id } => (name, type_id),
+            _ => return Err(Expected("typedef")),
+        };
+
+        let name = match self.names {
+            Some(ref mut names) => names.resolve(type_id, name, true),
+            None => name.to_string(),
+        };
+
+        // old GCC versions emit an invalid typedef of `__gnuc_va_list` to void
+        if target == 0 && name == "__gnuc_va_list" {
+            return Ok(String::from("typedef __builtin_va_list __gnuc_va_list"));
+        }
+
+        Ok(format!("typedef {}", self.decl(target, name, lvl)?))
+    }
+
+    fn struct_def(&mut self, type_id: u32, ty: &Type, lvl: usize) -> Result<String, Error> {
+        let (is_struct, size, members) = match *ty {
+            Type::Struct {
+                size, ref members, ..
+            } => (true, size, members),
+            Type::Union {
+                size, ref members, ..
+            } => (false, size, members),
+            _ => return Err(Expected("struct or union")),
+        };
+
+        let layout = self.layout();
+        let align = layout.align_of(type_id).unwrap_or(1);
+        let packed = is_struct && layout.is_packed(type_id).unwrap_or(false);
+
+        let mut s = self.struct_fwd(type_id, ty);
+        let mut off = 0;
+        let mut prev_bitfield = false;
+
+        s.push_str(" {");
+
+        for m in members {
+            let m_off = m.bits_offset as usize;
+            let m_align = if packed {
+                1
+            } else {
+                layout.align_of(m.type_id).unwrap_or(1)
+            };
+            let in_bitfield = prev_bitfield && m.bitfield_size != 0;
+
+            self.bit_padding(&mut s, off, m_off, m_align, in_bitfield, lvl + 1);
+
+            let decl = self.decl(
+                m.type_id,
+                m.name.as_deref().unwrap_or_default().to_string(),
+                lvl + 1,
+            )?;
+
+            s.push('\n');
+            s.push_str(&indent(lvl + 1));
+            s.push_str(&decl);
+
+            if m.bitfield_size != 0 {
+                s.push_str(&format!(": {}", m.bitfield_size));
+                off = m_off + m.bitfield_size as usize;
+                prev_bitfield = true;
+            } else {
+                off = m_off + layout.resolve_size(m.type_id).unwrap_or(0) * 8;
+                prev_bitfield = false;
+            }
+
+            s.push(';');
+        }
+
+        if is_struct {
+            self.bit_padding(&mut s, off, size * 8, align, false, lvl + 1);
+        }
+
+        // keep an empty struct on a single line
+        if !members.is_empty() || size != 0 {
+            s.push('\n');
+            s.push_str(&indent(lvl));
+        }
+
+        s.push('}');
+
+        if packed {
+            s.push_str(" __attribute__((packed))");
+        }
+
+        Ok(s)
+    }
+
+    /// Fill the gap between `cur_off` and `next_off` bits with anonymous bitfields.
+    ///
+    /// The natural alignment of `long`, `int`, `short` and `char` is used to
+    /// keep the padding short, a zero width bitfield forces the alignment.
+    fn bit_padding(
+        &self,
+        s: &mut String,
+        mut cur_off: usize,
+        next_off: usize,
+        next_align: usize,
+        in_bitfield: bool,
+        lvl: usize,
+    ) {
+        let pads = [
+            ("long", self.ptr_size * 8),
+            ("int", 32),
+            ("short", 16),
+            ("char", 8),
+        ];
+
+        if cur_off >= next_off {
+            return;
+        }
+
+        let mut pad = pads[pads.len() - 1];
+        let mut new_off = cur_off;
+
+        for &p in &pads {
+            pad = p;
+            new_off = cur_off.next_multiple_of(p.1);
+
+            if new_off <= next_off {
+                break;
+            }
+        }
+
+        if new_off > cur_off && new_off <= next_off {
+            // an explicit alignment mark is needed if the compiler won't align the next field by itself,
+            // or if the following padding would fit in the hole
+            if in_bitfield
+                || (new_off == next_off && cur_off.next_multiple_of(next_align * 8) != new_off)
+                || (new_off != next_off && next_off - new_off <= new_off - cur_off)
+            {
+                s.push_str(&format!(
+                    "\n{}{}: {};",
+                    indent(lvl),
+                    pad.0,
+                    if in_bitfield { new_off - cur_off } else { 0 }
+                ));
+            }
+
+            cur_off = new_off;
+        }
+
+        while cur_off != next_off {
+            let bits = (next_off - cur_off).min(pad.1);
+
+            // the remainder uses the smallest type covering it
+            let name = if bits == pad.1 {
+                pad.0
+            } else {
+                pads.iter()
+                    .rev()
+                    .find(|p| p.1 >= bits)
+                    .map_or(pad.0, |p| p.0)
+            };
+
+            s.push_str(&format!("\n{}{}: {};", indent(lvl), name, bits));
+            cur_off += bits;
+        }
+    }
+
+    fn enum_def(&mut self, type_id: u32, ty: &Type, lvl: usize) -> String {
+        let (is_enum64, size, values) = match *ty {
+            Type::Enum {
+                size, ref values, ..
+            } => (false, size, values),
+            Type::Enum64 {
+                size, ref values, ..
+            } => (true, size, values),
+            _ => return String::new(),
+        };
+
+        let mut s = self.struct_fwd(type_id, ty);
+
+        if values.is_empty() {
+            return s;
+        }
+
+        s.push_str(" {");
+
+        for v in values {
+            let name = v.name.as_deref().unwrap_or_default();
+            let name = match self.names {
+                Some(ref mut names) => names.enumerator(name),
+                None => String::from(name),
+            };
+            let val = match (is_enum64, v.val) {
+                (false, EnumValue::Signed(n)) => format!("{}", n as i32),
+                (false, EnumValue::Unsigned(n)) => format!("{}", n as u32),
+                (true, EnumValue::Signed(n)) => format!("{}LL", n),
+                (true, EnumValue::Unsigned(n)) => format!("{}ULL", n),
+            };
+
+            s.push_str(&format!("\n{}{} = {},", indent(lvl + 1), name, val));
+        }
+
+        s.push('\n');
+        s.push_str(&indent(lvl));
+        s.push('}');
+
+        // an enum is as large as an int, unless its values don't fit
+        if size == 1 {
+            s.push_str(" __attribute__((mode(byte)))");
+        } else if size == 8
+            && self.ptr_size == 8
+            && (!is_enum64 || values.iter().all(|v| v.val.as_u64() >> 32 == 0))
+        {
+            s.push_str(" __attribute__((mode(word)))");
+        }
+
+        s
+    }
+}
+
+fn indent(lvl: usize) -> String {
+    "\t".repeat(lvl)
+}
+
+fn join(base: &str, inner: &str) -> String {
+    if inner.is_empty() {
+        String::from(base)
+    } else {
+        format!("{} {}", base, inner)
+    }
+}
+
+/// A pointer declarator must be parenthesized before an array or function suffix.
+fn parenthesize(inner: String) -> String {
+    if inner.starts_with('*') {
+        format!("({})", inner)
+    } else {
+        inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::LittleEndian;
+
+    use super::*;
+    use crate::{file::IntEncoding, writer};
+
+    #[test]
+    fn array_names() {
+        let b = writer::write::<LittleEndian>(&[
+            Type::Int {
+                name: "char".into(),
+                size: 1,
+                bits_offset: 0,
+                nr_bits: 8,
+                encoding: IntEncoding::CHAR,
+            },
+            Type::Array {
+                type_id: 1,
+                index_type_id: 1,
+                nr_elems: 16,
+            },
+            Type::Const { type_id: 2 },
+        ])
+        .unwrap();
+        let types = TypeTable::parse(untrusted::Input::from(&b)).unwrap();
+
+        assert_eq!(type_name(&types, 2).unwrap(), "char [16]");
+        assert_eq!(type_name(&types, 3).unwrap(), "const char [16]");
+        assert_eq!(type_decl(&types, 2, "buf").unwrap(), "char buf[16]");
+        assert_eq!(type_cast(&types, 3).unwrap(), "char[16]");
+    }
+}
@@ -0,0 +1,905 @@
+cfg_if::cfg_if! {
+    if #[cfg(feature = "std")] {
+        use std::borrow::Cow;
+    } else {
+        use alloc::{
+            borrow::Cow,
+            format,
+            string::{String, ToString},
+        };
+    }
+}
+
+use crate::{
+    c,
+    file::{Kind, Linkage},
+    table::TypeTable,
+    ty::{EnumValue, Member, Type},
+    value::{Decoder, Value},
+    Error::{self, *},
+};
+
+/// Render the value of `type_id` at the start of `data` as a C initializer, see [`Dumper`].
+pub fn dump(types: &TypeTable, type_id: u32, data: &[u8]) -> Result<String, Error> {
+    Dumper::new(types).dump(type_id, data)
+}
+
+/// A renderer of raw memory as C initializers, following libbpf's `btf_dump__dump_type_data`.
+///
+/// The output is the same as libbpf's, e.g. in the compact mode
+///
+/// ```text
+/// (struct task_info){.pid = (int)42,.comm = (char[16])['b','a','s','h',],}
+/// ```
+///
+/// The members which are zero are skipped, unless [`Dumper::emit_zeroes`] is set.
+#[derive(Clone, Debug)]
+pub struct Dumper<'t, 'a> {
+    decoder: Decoder<'t, 'a>,
+    compact: bool,
+    skip_names: bool,
+    emit_zeroes: bool,
+    indent_level: usize,
+    indent_str: Cow<'static, str>,
+}
+
+/// The state of a dump, like libbpf's `btf_dump_data`.
+#[derive(Debug, Default)]
+struct State {
+    out: String,
+    depth: usize,
+    is_array_member: bool,
+    is_array_char: bool,
+    is_array_terminated: bool,
+}
+
+/// Where the value is, a bitfield is read from the data of its struct or union.
+#[derive(Clone, Copy)]
+enum At<'m, 'a> {
+    Data,
+    Bitfield(&'m Member<'a>),
+}
+
+impl<'t, 'a> Dumper<'t, 'a> {
+    pub fn new(types: &'t TypeTable<'a>) -> Self {
+        Dumper {
+            decoder: Decoder::new(types),
+            compact: false,
+            skip_names: false,
+            emit_zeroes: false,
+            indent_level: 0,
+            indent_str: Cow::Borrowed("\t"),
+        }
+    }
+
+    /// Read the pointers of a target whose pointers are `ptr_size` bytes.
+    pub fn ptr_size(mut self, ptr_size: usize) -> Self {
+        self.decoder = self.decoder.ptr_size(ptr_size);
+        self
+    }
+
+    /// Render the value on a single line.
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
+    }
+
+    /// Render the values without the names of the members and the type casts.
+    pub fn skip_names(mut self, skip_names: bool) -> Self {
+        self.skip_names = skip_names;
+        self
+    }
+
+    /// Render the members which are zero.
+    pub fn emit_zeroes(mut self, emit_zeroes: bool) -> Self {
+        self.emit_zeroes = emit_zeroes;
+        self
+    }
+
+    /// Indent all the lines by `level` indentations.
+    pub fn indent_level(mut self, level: usize) -> Self {
+        self.indent_level = level;
+        self
+    }
+
+    /// Indent the lines with the string, a tab by default.
+    pub fn indent_str<S: Into<Cow<'static, str>>>(mut self, indent: S) -> Self {
+        self.indent_str = indent.into();
+        self
+    }
+
+    pub fn dump(&self, type_id: u32, data: &[u8]) -> Result<String, Error> {
+        let mut st = State::default();
+
+        self.data(&mut st, None, type_id, data, At::Data)?;
+
+        Ok(st.out)
+    }
+
+    fn types(&self) -> &'t TypeTable<'a> {
+        self.decoder.types
+    }
+
+    fn data(
+        &self,
+        st: &mut State,
+        name: Option<&str>,
+        type_id: u32,
+        data: &[u8],
+        at: At,
+    ) -> Result<(), Error> {
+        if !self.emit_zeroes
+            && st.depth > 0
+            && (!st.is_array_member || st.is_array_char)
+            && self.is_zero(type_id, data, at)?
+        {
+            return Ok(());
+        }
+
+        self.pfx(st);
+
+        if !self.skip_names {
+            if let Some(name) = name.filter(|name| !name.is_empty()) {
+                st.out.push_str(&format!(".{} = ", name));
+            }
+
+            self.cast(st, type_id, true)?;
+        }
+
+        let type_id = self.skip_mods(type_id)?;
+        let ty = self.types().get(type_id)?;
+
+        match ty {
+            Type::Int { size, encoding, .. } => match at {
+                At::Bitfield(m) => {
+                    let raw = bitfield_bits(self.decoder.bitfield(m, data)?, m);
+
+                    self.values(st, &format!("0x{:x}", raw));
+                }
+                At::Data => {
+                    let v = self.decoder.decode(type_id, data)?;
+
+                    if size == 1 && st.is_array_char {
+                        let c = data[0];
+
+                        if st.is_array_terminated {
+                            return Ok(());
+                        }
+                        if c == 0 {
+                            self.values(st, "'\\0'");
+                            st.is_array_terminated = true;
+                            return Ok(());
+                        }
+                        if c.is_ascii_graphic() || c == b' ' {
+                            self.values(st, &format!("'{}'", c as char));
+                            return Ok(());
+                        }
+                    }
+
+                    let s = match v {
+                        Value::Int(n) if size == 16 => format!("0x{:x}", n as u128),
+                        Value::Uint(n) if size == 16 => format!("0x{:x}", n),
+                        Value::Int(n) => n.to_string(),
+                        Value::Uint(n) => n.to_string(),
+                        Value::Bool(b) if encoding.is_signed() => (b as i8).to_string(),
+                        Value::Bool(b) => (b as u8).to_string(),
+                        _ => return Err(Unexpected("integer size")),
+                    };
+
+                    self.values(st, &s);
+                }
+            },
+            Type::Float { .. } => match self.decoder.decode(type_id, data)? {
+                Value::Float(n) => self.values(st, &format!("{:.6}", n)),
+                _ => return Err(Unexpected("float size")),
+            },
+            Type::Ptr { .. } => match self.decoder.decode(type_id, data)? {
+                Value::Ptr(addr) => self.values(st, &format!("0x{:x}", addr)),
+                _ => return Err(Expected("pointer")),
+            },
+            Type::Enum { .. } | Type::Enum64 { .. } => {
+                let v = match at {
+                    At::Bitfield(m) => self.decoder.bitfield(m, data)?,
+                    At::Data => self.decoder.decode(type_id, data)?,
+                };
+
+                let s = match v {
+                    Value::Enum {
+                        name: Some(name), ..
+                    } => name.to_string(),
+                    Value::Enum { value, .. } => match (matches!(ty, Type::Enum64 { .. }), value) {
+                        (false, EnumValue::Signed(n)) => format!("{}", n as i32),
+                        (false, EnumValue::Unsigned(n)) => format!("{}", n as u32),
+                        (true, EnumValue::Signed(n)) => format!("{}LL", n),
+                        (true, EnumValue::Unsigned(n)) => format!("{}ULL", n),
+                    },
+                    _ => return Err(Expected("enum")),
+                };
+
+                self.values(st, &s);
+            }
+            Type::Array {
+                type_id: elem_type_id,
+                nr_elems,
+                ..
+            } => self.array_data(st, elem_type_id, nr_elems, data)?,
+            Type::Struct { members, .. } | Type::Union { members, .. } => {
+                st.depth += 1;
+                st.out.push('{');
+                st.out.push_str(self.newline(st));
+
+                for m in &members {
+                    let name = m.name.as_deref();
+
+                    if m.bitfield_size == 0 {
+                        let data = data.get(m.bits_offset as usize / 8..).ok_or(EndOfInput)?;
+
+                        self.data(st, name, m.type_id, data, At::Data)?;
+                    } else {
+                        self.data(st, name, m.type_id, data, At::Bitfield(m))?;
+                    }
+                }
+
+                st.depth -= 1;
+                self.pfx(st);
+                self.values(st, "}");
+            }
+            Type::Variable {
+                name,
+                type_id,
+                linkage,
+            } => {
+                st.out.push_str(match linkage {
+                    Linkage::Static => "static ",
+                    Linkage::Extern => "extern ",
+                    _ => "",
+                });
+
+                self.cast(st, type_id, false)?;
+                st.out.push_str(&format!(" {} = ", name));
+                self.data(st, None, type_id, data, At::Data)?;
+            }
+            Type::DataSec { name, sections, .. } => {
+                self.values(st, &format!("SEC(\"{}\") ", name));
+
+                for s in sections {
+                    let data = data.get(s.offset as usize..).ok_or(EndOfInput)?;
+
+                    self.data(st, None, s.type_id, data, At::Data)?;
+                    st.out.push(';');
+                }
+            }
+            _ => {
+                let kind = self.types().kind(type_id)?;
+
+                st.out
+                    .push_str(&format!("<unsupported kind:{}>", u8::from(kind)));
+
+                return Err(Unsized(kind));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn array_data(
+        &self,
+        st: &mut State,
+        elem_type_id: u32,
+        nr_elems: u32,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        let elem_size = self.decoder.layout.resolve_size(elem_type_id)?;
+
+        if elem_size == 0 {
+            return Err(Unsized(self.types().kind(elem_type_id)?));
+        }
+
+        // the char arrays are rendered as characters, up to the NUL
+        let is_array_char = st.is_array_char;
+
+        if elem_size == 1 {
+            st.is_array_char = self.types().kind(self.skip_mods(elem_type_id)?)? == Kind::Integer;
+        }
+
+        st.depth += 1;
+        st.out.push('[');
+        st.out.push_str(self.newline(st));
+
+        let is_array_member = st.is_array_member;
+        let is_array_terminated = st.is_array_terminated;
+
+        st.is_array_member = true;
+        st.is_array_terminated = false;
+
+        for i in 0..nr_elems as usize {
+            if st.is_array_terminated {
+                break;
+            }
+
+            let data = data.get(i * elem_size..).ok_or(EndOfInput)?;
+
+            self.data(st, None, elem_type_id, data, At::Data)?;
+        }
+
+        st.is_array_member = is_array_member;
+        st.is_array_terminated = is_array_terminated;
+        st.is_array_char = is_array_char;
+        st.depth -= 1;
+
+        self.pfx(st);
+        self.values(st, "]");
+
+        Ok(())
+    }
+
+    /// Returns `true` if the value is zero, a char array is zero if it starts with a NUL.
+    fn is_zero(&self, type_id: u32, data: &[u8], at: At) -> Result<bool, Error> {
+        let type_id = self.skip_mods(type_id)?;
+
+        match self.types().get(type_id)? {
+            Type::Int { .. } | Type::Enum { .. } | Type::Enum64 { .. } => {
+                let v = match at {
+                    At::Bitfield(m) => self.decoder.bitfield(m, data)?,
+                    At::Data => self.decoder.decode(type_id, data)?,
+                };
+
+                Ok(match v {
+                    Value::Bool(b) => !b,
+                    Value::Int(n) => n == 0,
+                    Value::Uint(n) => n == 0,
+                    Value::Enum { value, .. } => value.as_u64() == 0,
+                    _ => false,
+                })
+            }
+            Type::Float { size, .. } => {
+                Ok(data.get(..size).ok_or(EndOfInput)?.iter().all(|&b| b == 0))
+            }
+            Type::Ptr { .. } => Ok(self.decoder.decode(type_id, data)? == Value::Ptr(0)),
+            Type::Array {
+                type_id: elem_type_id,
+                nr_elems,
+                ..
+            } => {
+                let elem_size = self.decoder.layout.resolve_size(elem_type_id)?;
+
+                if elem_size == 0 {
+                    return Err(Unsized(self.types().kind(elem_type_id)?));
+                }
+
+                let is_char = elem_size == 1
+                    && self.types().kind(self.skip_mods(elem_type_id)?)? == Kind::Integer;
+
+                for i in 0..nr_elems as usize {
+                    let data = data.get(i * elem_size..).ok_or(EndOfInput)?;
+
+                    if i == 0 && is_char && data.first() == Some(&0) {
+                        return Ok(true);
+                    }
+                    if !self.is_zero(elem_type_id, data, At::Data)? {
+                        return Ok(false);
+                    }
+                }
+
+                Ok(true)
+            }
+            Type::Struct { members, .. } | Type::Union { members, .. } => {
+                for m in &members {
+                    let zero = if m.bitfield_size == 0 {
+                        let data = data.get(m.bits_offset as usize / 8..).ok_or(EndOfInput)?;
+
+                        self.is_zero(m.type_id, data, At::Data)?
+                    } else {
+                        self.is_zero(m.type_id, data, At::Bitfield(m))?
+                    };
+
+                    if !zero {
+                        return Ok(false);
+                    }
+                }
+
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// The type cast of a value, e.g. `(struct task_struct *)`, not repeated for the elements of an array.
+    fn cast(&self, st: &mut State, type_id: u32, toplevel: bool) -> Result<(), Error> {
+        if st.is_array_member {
+            return Ok(());
+        }
+
+        // the variables and sections are rendered with the types of their values
+        if matches!(
+            self.types().kind(type_id)?,
+            Kind::Variable | Kind::DataSection
+        ) {
+            return Ok(());
+        }
+
+        let cast = c::type_cast(self.types(), type_id)?;
+
+        if toplevel {
+            st.out.push_str(&format!("({})", cast));
+        } else {
+            st.out.push_str(&cast);
+        }
+
+        Ok(())
+    }
+
+    /// Follow the typedefs and modifiers, like libbpf's `skip_mods_and_typedefs`.
+    fn skip_mods(&self, type_id: u32) -> Result<u32, Error> {
+        let mut id = type_id;
+
+        for _ in 0..=self.types().end_id() {
+            match self.types().get(id)? {
+                Type::Typedef { type_id, .. }
+                | Type::Volatile { type_id }
+                | Type::Const { type_id }
+                | Type::Restrict { type_id }
+                | Type::TypeTag { type_id, .. } => id = type_id,
+                _ => return Ok(id),
+            }
+        }
+
+        Err(Cycle(type_id))
+    }
+
+    fn pfx(&self, st: &mut State) {
+        if !self.compact {
+            for _ in 0..self.indent_level + st.depth {
+                st.out.push_str(&self.indent_str);
+            }
+        }
+    }
+
+    /// A value followed by its delimiter, like libbpf's `btf_dump_type_values`.
+    fn values(&self, st: &mut State, s: &str) {
+        st.out.push_str(s);
+
+        if st.depth > 0 {
+            st.out.push(',');
+        }
+
+        st.out.push_str(self.newline(st));
+    }
+
+    fn newline(&self, st: &State) -> &'static str {
+        if self.compact || st.depth == 0 {
+            ""
+        } else {
+            "\n"
+        }
+    }
+}
+
+/// The raw bits of a bitfield, rendered in hexadecimal.
+fn bitfield_bits(v: Value, m: &Member) -> u128 {
+    let mask = match m.bitfield_size {
+        n if n >= 128 => u128::MAX,
+        n => (1 << n) - 1,
+    };
+
+    match v {
+        Value::Bool(b) => b as u128,
+        Value::Int(n) => n as u128 & mask,
+        Value::Uint(n) => n,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::LittleEndian;
+
+    use super::*;
+    use crate::{
+        file::{IntEncoding, VarSectInfo},
+        ty::Enum,
+        writer,
+    };
+
+    fn int(name: &'static str, size: usize, encoding: IntEncoding) -> Type<'static> {
+        Type::Int {
+            name: name.into(),
+            size,
+            bits_offset: 0,
+            nr_bits: size * 8,
+            encoding,
+        }
+    }
+
+    fn typedef(name: &'static str, type_id: u32) -> Type<'static> {
+        Type::Typedef {
+            name: name.into(),
+            type_id,
+        }
+    }
+
+    fn member(
+        name: &'static str,
+        type_id: u32,
+        bits_offset: u32,
+        bitfield_size: u32,
+    ) -> Member<'static> {
+        Member {
+            name: Some(name.into()),
+            type_id,
+            bits_offset,
+            bitfield_size,
+        }
+    }
+
+    fn strukt(name: &'static str, size: usize, members: Vec<Member<'static>>) -> Type<'static> {
+        Type::Struct {
+            name: Some(name.into()),
+            size,
+            members,
+        }
+    }
+
+    fn enum64(name: &'static str, signed: bool) -> Type<'static> {
+        Type::Enum64 {
+            name: Some(name.into()),
+            size: 8,
+            signed,
+            values: vec![Enum {
+                name: Some("A".into()),
+                val: EnumValue::new(1, signed),
+            }],
+        }
+    }
+
+    fn var(name: &'static str, linkage: Linkage) -> Type<'static> {
+        Type::Variable {
+            name: name.into(),
+            type_id: INT,
+            linkage,
+        }
+    }
+
+    const INT: u32 = 1;
+    const STRUCT_PROG_INFO: u32 = 4;
+    const STRUCT_SK_BUFF: u32 = 8;
+    const STRUCT_INSN: u32 = 14;
+    const ENUM_CMD: u32 = 15;
+    const ENUM64_U: u32 = 16;
+    const ENUM64_S: u32 = 17;
+    const DATASEC: u32 = 20;
+    const STRUCT_ENUM: u32 = 21;
+    const STRUCT_TASK_INFO: u32 = 22;
+
+    /// The types of libbpf's `btf_dump` selftests.
+    fn types() -> Vec<u8> {
+        writer::write::<LittleEndian>(&[
+            int("int", 4, IntEncoding::SIGNED),
+            int("char", 1, IntEncoding::SIGNED),
+            Type::Array {
+                type_id: 2,
+                index_type_id: INT,
+                nr_elems: 16,
+            },
+            strukt("bpf_prog_info", 16, vec![member("name", 3, 0, 0)]),
+            int("unsigned int", 4, IntEncoding::empty()),
+            typedef("__u32", 5),
+            Type::Array {
+                type_id: 6,
+                index_type_id: INT,
+                nr_elems: 5,
+            },
+            strukt("__sk_buff", 20, vec![member("cb", 7, 0, 0)]),
+            int("unsigned char", 1, IntEncoding::empty()),
+            typedef("__u8", 9),
+            int("short", 2, IntEncoding::SIGNED),
+            typedef("__s16", 11),
+            typedef("__s32", INT),
+            strukt(
+                "bpf_insn",
+                8,
+                vec![
+                    member("code", 10, 0, 0),
+                    member("dst_reg", 10, 8, 4),
+                    member("src_reg", 10, 12, 4),
+                    member("off", 12, 16, 0),
+                    member("imm", 13, 32, 0),
+                ],
+            ),
+            Type::Enum {
+                name: Some("bpf_cmd".into()),
+                size: 4,
+                signed: false,
+                values: vec![
+                    Enum {
+                        name: Some("BPF_MAP_CREATE".into()),
+                        val: EnumValue::Unsigned(0),
+                    },
+                    Enum {
+                        name: Some("BPF_MAP_LOOKUP_ELEM".into()),
+                        val: EnumValue::Unsigned(1),
+                    },
+                ],
+            },
+            enum64("e64", false),
+            enum64("s64", true),
+            var("cpu_number", Linkage::Global),
+            var("cpu_profile_flip", Linkage::Static),
+            Type::DataSec {
+                name: ".data".into(),
+                size: 8,
+                sections: vec![
+                    VarSectInfo {
+                        type_id: 18,
+                        offset: 0,
+                        size: 4,
+                    },
+                    VarSectInfo {
+                        type_id: 19,
+                        offset: 4,
+                        size: 4,
+                    },
+                ],
+            },
+            strukt(
+                "btf_enum",
+                8,
+                vec![member("name_off", 6, 0, 0), member("val", 13, 32, 0)],
+            ),
+            strukt(
+                "task_info",
+                20,
+                vec![member("pid", INT, 0, 0), member("comm", 3, 32, 0)],
+            ),
+        ])
+        .unwrap()
+    }
+
+    fn check<F>(type_id: u32, data: &[u8], configure: F, expected: &str)
+    where
+        F: for<'t, 'a> FnOnce(Dumper<'t, 'a>) -> Dumper<'t, 'a>,
+    {
+        let b = types();
+        let types = TypeTable::parse(untrusted::Input::from(&b)).unwrap();
+        let dumper = configure(Dumper::new(&types));
+
+        assert_eq!(dumper.dump(type_id, data).unwrap(), expected);
+    }
+
+    fn compact<'t, 'a>(d: Dumper<'t, 'a>) -> Dumper<'t, 'a> {
+        d.compact(true)
+    }
+
+    fn multiline<'t, 'a>(d: Dumper<'t, 'a>) -> Dumper<'t, 'a> {
+        d
+    }
+
+    fn char16(s: &str) -> [u8; 16] {
+        let mut b = [0; 16];
+
+        b[..s.len()].copy_from_slice(s.as_bytes());
+        b
+    }
+
+    fn words(words: &[i32]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn int_data() {
+        check(INT, &words(&[1234]), compact, "(int)1234");
+        check(
+            INT,
+            &words(&[1234]),
+            |d| d.compact(true).skip_names(true),
+            "1234",
+        );
+        check(INT, &words(&[1234]), multiline, "(int)1234");
+        // a zero is rendered at the top level
+        check(INT, &words(&[0]), compact, "(int)0");
+    }
+
+    #[test]
+    fn struct_data() {
+        let data = words(&[3, -1]);
+
+        check(
+            STRUCT_ENUM,
+            &data,
+            compact,
+            "(struct btf_enum){.name_off = (__u32)3,.val = (__s32)-1,}",
+        );
+        check(
+            STRUCT_ENUM,
+            &data,
+            |d| d.compact(true).skip_names(true),
+            "{3,-1,}",
+        );
+        check(
+            STRUCT_ENUM,
+            &data,
+            multiline,
+            "(struct btf_enum){\n\t.name_off = (__u32)3,\n\t.val = (__s32)-1,\n}",
+        );
+        check(
+            STRUCT_ENUM,
+            &data,
+            |d| d.skip_names(true),
+            "{\n\t3,\n\t-1,\n}",
+        );
+
+        let zero = words(&[0, 0]);
+
+        check(STRUCT_ENUM, &zero, compact, "(struct btf_enum){}");
+        check(
+            STRUCT_ENUM,
+            &zero,
+            |d| d.compact(true).emit_zeroes(true),
+            "(struct btf_enum){.name_off = (__u32)0,.val = (__s32)0,}",
+        );
+        check(
+            STRUCT_ENUM,
+            &zero,
+            |d| d.emit_zeroes(true),
+            "(struct btf_enum){\n\t.name_off = (__u32)0,\n\t.val = (__s32)0,\n}",
+        );
+    }
+
+    #[test]
+    fn char_array_data() {
+        let foo = char16("foo");
+
+        check(
+            STRUCT_PROG_INFO,
+            &foo,
+            compact,
+            "(struct bpf_prog_info){.name = (char[16])['f','o','o',],}",
+        );
+        check(
+            STRUCT_PROG_INFO,
+            &foo,
+            |d| d.compact(true).skip_names(true),
+            "{['f','o','o',],}",
+        );
+        check(
+            STRUCT_PROG_INFO,
+            &foo,
+            multiline,
+            "(struct bpf_prog_info){\n\t.name = (char[16])[\n\t\t'f',\n\t\t'o',\n\t\t'o',\n\t],\n}",
+        );
+
+        // the NUL is only rendered with the zeroes, and ends the array
+        check(
+            STRUCT_PROG_INFO,
+            &foo,
+            |d| d.compact(true).emit_zeroes(true),
+            "(struct bpf_prog_info){.name = (char[16])['f','o','o','\\0',],}",
+        );
+        check(
+            STRUCT_PROG_INFO,
+            &foo,
+            |d| d.emit_zeroes(true),
+            "(struct bpf_prog_info){\n\t.name = (char[16])[\n\t\t'f',\n\t\t'o',\n\t\t'o',\n\t\t'\\0',\n\t],\n}",
+        );
+
+        // an empty string is a zero member
+        check(
+            STRUCT_PROG_INFO,
+            &[0; 16],
+            compact,
+            "(struct bpf_prog_info){}",
+        );
+
+        let mut comm = words(&[42]);
+        comm.extend_from_slice(&char16("bash"));
+
+        check(
+            STRUCT_TASK_INFO,
+            &comm,
+            compact,
+            "(struct task_info){.pid = (int)42,.comm = (char[16])['b','a','s','h',],}",
+        );
+    }
+
+    #[test]
+    fn array_data() {
+        check(
+            STRUCT_SK_BUFF,
+            &words(&[1, 2, 3, 4, 5]),
+            compact,
+            "(struct __sk_buff){.cb = (__u32[5])[1,2,3,4,5,],}",
+        );
+        // the zero elements of an array are kept, unlike the zero members
+        check(
+            STRUCT_SK_BUFF,
+            &words(&[1, 0, 3, 0, 0]),
+            compact,
+            "(struct __sk_buff){.cb = (__u32[5])[1,0,3,0,0,],}",
+        );
+        check(
+            STRUCT_SK_BUFF,
+            &words(&[1, 0, 3, 0, 0]),
+            multiline,
+            "(struct __sk_buff){\n\t.cb = (__u32[5])[\n\t\t1,\n\t\t0,\n\t\t3,\n\t\t0,\n\t\t0,\n\t],\n}",
+        );
+        check(STRUCT_SK_BUFF, &[0; 20], compact, "(struct __sk_buff){}");
+    }
+
+    #[test]
+    fn bitfield_data() {
+        let insn = [1, 0x32, 4, 0, 5, 0, 0, 0];
+
+        check(
+            STRUCT_INSN,
+            &insn,
+            compact,
+            "(struct bpf_insn){.code = (__u8)1,.dst_reg = (__u8)0x2,.src_reg = (__u8)0x3,.off = (__s16)4,.imm = (__s32)5,}",
+        );
+        check(
+            STRUCT_INSN,
+            &[0, 0x30, 0, 0, 0, 0, 0, 0],
+            compact,
+            "(struct bpf_insn){.src_reg = (__u8)0x3,}",
+        );
+        check(
+            STRUCT_INSN,
+            &[0; 8],
+            |d| d.compact(true).emit_zeroes(true),
+            "(struct bpf_insn){.code = (__u8)0,.dst_reg = (__u8)0x0,.src_reg = (__u8)0x0,.off = (__s16)0,.imm = (__s32)0,}",
+        );
+    }
+
+    #[test]
+    fn enum_data() {
+        check(
+            ENUM_CMD,
+            &words(&[0]),
+            compact,
+            "(enum bpf_cmd)BPF_MAP_CREATE",
+        );
+        check(
+            ENUM_CMD,
+            &words(&[1]),
+            |d| d.compact(true).skip_names(true),
+            "BPF_MAP_LOOKUP_ELEM",
+        );
+        check(ENUM_CMD, &words(&[2000]), compact, "(enum bpf_cmd)2000");
+
+        // the values of an enum64 without enumerator have a suffix
+        check(ENUM64_U, &1u64.to_le_bytes(), compact, "(enum e64)A");
+        check(
+            ENUM64_U,
+            &u64::MAX.to_le_bytes(),
+            compact,
+            "(enum e64)18446744073709551615ULL",
+        );
+        check(ENUM64_S, &(-2i64).to_le_bytes(), compact, "(enum s64)-2LL");
+    }
+
+    #[test]
+    fn var_data() {
+        check(18, &words(&[100]), compact, "int cpu_number = (int)100");
+        check(
+            19,
+            &words(&[2]),
+            compact,
+            "static int cpu_profile_flip = (int)2",
+        );
+        check(
+            DATASEC,
+            &words(&[100, 2]),
+            compact,
+            "SEC(\".data\") int cpu_number = (int)100;static int cpu_profile_flip = (int)2;",
+        );
+    }
+
+    #[test]
+    fn indent() {
+        check(
+            STRUCT_ENUM,
+            &words(&[3, -1]),
+            |d| d.indent_level(1).indent_str("  "),
+            "  (struct btf_enum){\n    .name_off = (__u32)3,\n    .val = (__s32)-1,\n  }",
+        );
+    }
+}
@@ -0,0 +1,645 @@
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::vec;
+
+use serde::de::{
+    self,
+    value::{BorrowedStrDeserializer, StringDeserializer, U32Deserializer},
+    DeserializeSeed, IntoDeserializer, Visitor,
+};
+use serde::forward_to_deserialize_any;
+
+use crate::{
+    file::Kind,
+    table::TypeTable,
+    ty::{EnumValue, Type},
+    value::{Decoder, Value},
+    Error::{self, *},
+};
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+/// Deserialize a `T` from the value of `type_id` at the start of `data`, see [`Deserializer`].
+pub fn from_bytes<'a, T>(types: &TypeTable<'a>, type_id: u32, data: &'a [u8]) -> Result<T, Error>
+where
+    T: de::Deserialize<'a>,
+{
+    T::deserialize(Deserializer::new(Decoder::new(types), type_id, data))
+}
+
+/// A deserializer of Rust types from raw memory laid out as a BTF type.
+///
+/// The fields of a Rust struct are matched by name with the members of the
+/// BTF struct, wherever they are, and the members of anonymous structs and
+/// unions are accessed as if they belonged to the parent, like in C. A `char`
+/// array deserializes into a string, up to the first NUL.
+pub struct Deserializer<'t, 'a> {
+    decoder: Decoder<'t, 'a>,
+    slot: Slot<'a>,
+}
+
+/// What is left to deserialize, a bitfield has already been decoded from its struct.
+enum Slot<'a> {
+    Data(u32, &'a [u8]),
+    Value(Value<'a>),
+}
+
+impl<'t, 'a> Deserializer<'t, 'a> {
+    pub fn new(decoder: Decoder<'t, 'a>, type_id: u32, data: &'a [u8]) -> Self {
+        Deserializer {
+            decoder,
+            slot: Slot::Data(type_id, data),
+        }
+    }
+
+    /// Decode the value, unless it is a struct, a union, a data section or an array that is not a string.
+    fn resolve(self) -> Result<Resolved<'t, 'a>, Error> {
+        let (type_id, data) = match self.slot {
+            Slot::Data(type_id, data) => (type_id, data),
+            Slot::Value(v) => return Ok(Resolved::Value(v)),
+        };
+
+        let decoder = self.decoder;
+        let type_id = decoder.layout.resolve_type(type_id)?;
+
+        match decoder.types.kind(type_id)? {
+            Kind::Struct | Kind::Union | Kind::DataSection => {
+                Fields::new(decoder, type_id, data).map(Resolved::Fields)
+            }
+            Kind::Array if !is_string(&decoder, type_id)? => {
+                Elements::new(decoder, type_id, data).map(Resolved::Elements)
+            }
+            _ => decoder.decode(type_id, data).map(Resolved::Value),
+        }
+    }
+}
+
+/// Returns `true` if the type is an array of characters.
+fn is_string(decoder: &Decoder, type_id: u32) -> Result<bool, Error> {
+    match decoder.types.get(type_id)? {
+        Type::Array { type_id, .. } => {
+            Ok(decoder.layout.resolve_size(type_id)? == 1 && decoder.is_char(type_id)?)
+        }
+        _ => Ok(false),
+    }
+}
+
+enum Resolved<'t, 'a> {
+    Value(Value<'a>),
+    Fields(Fields<'t, 'a>),
+    Elements(Elements<'t, 'a>),
+}
+
+impl<'t, 'de> de::Deserializer<'de> for Deserializer<'t, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.resolve()? {
+            Resolved::Value(v) => visit_value(v, visitor),
+            Resolved::Fields(fields) => visitor.visit_map(fields),
+            Resolved::Elements(elems) => visitor.visit_seq(elems),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.resolve()? {
+            Resolved::Value(Value::Bytes(b)) => {
+                let len = b.iter().position(|&c| c == 0).unwrap_or(b.len());
+
+                match String::from_utf8_lossy(&b[..len]) {
+                    Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+                    Cow::Owned(s) => visitor.visit_string(s),
+                }
+            }
+            Resolved::Value(Value::Enum {
+                name: Some(name), ..
+            }) => visit_str(name, visitor),
+            Resolved::Value(v) => visit_value(v, visitor),
+            Resolved::Fields(fields) => visitor.visit_map(fields),
+            Resolved::Elements(elems) => visitor.visit_seq(elems),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.resolve()? {
+            Resolved::Value(Value::Bytes(b)) => visitor.visit_borrowed_bytes(b),
+            Resolved::Value(v) => visit_value(v, visitor),
+            Resolved::Fields(fields) => visitor.visit_map(fields),
+            Resolved::Elements(elems) => visitor.visit_seq(elems),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    /// A null pointer deserializes into `None`.
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if let Slot::Data(type_id, data) = self.slot {
+            let type_id = self.decoder.layout.resolve_type(type_id)?;
+
+            if self.decoder.types.kind(type_id)? == Kind::Pointer
+                && self.decoder.decode(type_id, data)? == Value::Ptr(0)
+            {
+                return visitor.visit_none();
+            }
+        }
+
+        visitor.visit_some(self)
+    }
+
+    /// A char array deserializes into a sequence of characters, like any other array.
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if let Slot::Data(type_id, data) = self.slot {
+            let type_id = self.decoder.layout.resolve_type(type_id)?;
+
+            if self.decoder.types.kind(type_id)? == Kind::Array {
+                return visitor.visit_seq(Elements::new(self.decoder, type_id, data)?);
+            }
+        }
+
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    /// A C enum deserializes into the unit variant named after its enumerator, or numbered by its value.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.resolve()? {
+            Resolved::Value(Value::Enum {
+                name: Some(Cow::Borrowed(name)),
+                ..
+            }) => visitor.visit_enum(BorrowedStrDeserializer::new(name)),
+            Resolved::Value(Value::Enum {
+                name: Some(Cow::Owned(name)),
+                ..
+            }) => visitor.visit_enum(StringDeserializer::new(name)),
+            Resolved::Value(Value::Enum { value, .. }) => {
+                let idx = u32::try_from(value.as_i64())
+                    .map_err(|_| OutOfRange("variant index", value.as_u64()))?;
+
+                visitor.visit_enum(U32Deserializer::new(idx))
+            }
+            Resolved::Value(Value::Uint(n)) => {
+                let idx = u32::try_from(n).map_err(|_| OutOfRange("variant index", n as u64))?;
+
+                visitor.visit_enum(U32Deserializer::new(idx))
+            }
+            _ => Err(Expected("enum")),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Skip the value without decoding it.
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        unit unit_struct map struct identifier
+    }
+}
+
+fn visit_value<'a, V: Visitor<'a>>(v: Value<'a>, visitor: V) -> Result<V::Value, Error> {
+    match v {
+        Value::Bool(b) => visitor.visit_bool(b),
+        Value::Int(n) => match i64::try_from(n) {
+            Ok(n) => visitor.visit_i64(n),
+            Err(_) => visitor.visit_i128(n),
+        },
+        Value::Uint(n) => match u64::try_from(n) {
+            Ok(n) => visitor.visit_u64(n),
+            Err(_) => visitor.visit_u128(n),
+        },
+        Value::Float(n) => visitor.visit_f64(n),
+        Value::Enum {
+            value: EnumValue::Signed(n),
+            ..
+        } => visitor.visit_i64(n),
+        Value::Enum {
+            value: EnumValue::Unsigned(n),
+            ..
+        } => visitor.visit_u64(n),
+        Value::Ptr(addr) => visitor.visit_u64(addr),
+        Value::Bytes(b) => match v.as_str() {
+            Some(s) => visitor.visit_borrowed_str(s),
+            None => visitor.visit_borrowed_bytes(b),
+        },
+        Value::Struct(_) | Value::Array(_) => Err(Unexpected("decoded struct or array")),
+    }
+}
+
+fn visit_str<'a, V: Visitor<'a>>(s: Cow<'a, str>, visitor: V) -> Result<V::Value, Error> {
+    match s {
+        Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+        Cow::Owned(s) => visitor.visit_string(s),
+    }
+}
+
+/// The members of a struct or union, or the variables of a data section, by name.
+struct Fields<'t, 'a> {
+    decoder: Decoder<'t, 'a>,
+    fields: vec::IntoIter<(Cow<'a, str>, Slot<'a>)>,
+    value: Option<Slot<'a>>,
+}
+
+impl<'t, 'a> Fields<'t, 'a> {
+    fn new(decoder: Decoder<'t, 'a>, type_id: u32, data: &'a [u8]) -> Result<Self, Error> {
+        let mut fields = Vec::new();
+
+        collect_fields(&decoder, type_id, data, &mut fields, decoder.types.end_id())?;
+
+        Ok(Fields {
+            decoder,
+            fields: fields.into_iter(),
+            value: None,
+        })
+    }
+}
+
+/// Collect the named fields, flattening the anonymous structs and unions, up to `depth` levels deep.
+fn collect_fields<'a>(
+    decoder: &Decoder<'_, 'a>,
+    type_id: u32,
+    data: &'a [u8],
+    fields: &mut Vec<(Cow<'a, str>, Slot<'a>)>,
+    depth: u32,
+) -> Result<(), Error> {
+    let depth = depth.checked_sub(1).ok_or(Cycle(type_id))?;
+
+    match decoder.types.get(type_id)? {
+        Type::Struct { size, members, .. } | Type::Union { size, members, .. } => {
+            let data = data.get(..size).ok_or(EndOfInput)?;
+
+            for m in members {
+                let off = m.bits_offset as usize / 8;
+
+                if m.bitfield_size != 0 {
+                    if let Some(name) = m.name.clone() {
+                        fields.push((name, Slot::Value(decoder.bitfield(&m, data)?)));
+                    }
+                    continue;
+                }
+
+                if !(m.bits_offset as usize).is_multiple_of(8) {
+                    return Err(Malformed("member not aligned on a byte"));
+                }
+
+                let member_data = data.get(off..).ok_or(EndOfInput)?;
+
+                match m.name {
+                    Some(name) => fields.push((name, Slot::Data(m.type_id, member_data))),
+                    None => {
+                        let member_type_id = decoder.layout.resolve_type(m.type_id)?;
+
+                        if matches!(
+                            decoder.types.kind(member_type_id)?,
+                            Kind::Struct | Kind::Union
+                        ) {
+                            collect_fields(decoder, member_type_id, member_data, fields, depth)?;
+                        }
+                    }
+                }
+            }
+        }
+        Type::DataSec { size, sections, .. } => {
+            let data = data.get(..size).ok_or(EndOfInput)?;
+
+            for s in sections {
+                let var_data = data
+                    .get(s.offset as usize..(s.offset as usize).saturating_add(s.size as usize))
+                    .ok_or(EndOfInput)?;
+
+                if let Some(name) = decoder.types.name(s.type_id)? {
+                    fields.push((Cow::Borrowed(name), Slot::Data(s.type_id, var_data)));
+                }
+            }
+        }
+        _ => return Err(Expected("struct, union or data section")),
+    }
+
+    Ok(())
+}
+
+impl<'t, 'a> de::MapAccess<'a> for Fields<'t, 'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'a>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.fields.next() {
+            Some((name, slot)) => {
+                self.value = Some(slot);
+
+                match name {
+                    Cow::Borrowed(name) => seed.deserialize(BorrowedStrDeserializer::new(name)),
+                    Cow::Owned(name) => seed.deserialize(name.into_deserializer()),
+                }
+                .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'a>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let slot = self
+            .value
+            .take()
+            .ok_or(Unexpected("value before its key"))?;
+
+        seed.deserialize(Deserializer {
+            decoder: self.decoder,
+            slot,
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
+}
+
+/// The elements of an array.
+struct Elements<'t, 'a> {
+    decoder: Decoder<'t, 'a>,
+    type_id: u32,
+    data: &'a [u8],
+    elem_size: usize,
+    remaining: usize,
+}
+
+impl<'t, 'a> Elements<'t, 'a> {
+    fn new(decoder: Decoder<'t, 'a>, type_id: u32, data: &'a [u8]) -> Result<Self, Error> {
+        match decoder.types.get(type_id)? {
+            Type::Array {
+                type_id, nr_elems, ..
+            } => {
+                let elem_size = decoder.layout.resolve_size(type_id)?;
+                let len = elem_size
+                    .checked_mul(nr_elems as usize)
+                    .ok_or(OutOfRange("array size", nr_elems as u64))?;
+
+                Ok(Elements {
+                    decoder,
+                    type_id,
+                    data: data.get(..len).ok_or(EndOfInput)?,
+                    elem_size,
+                    remaining: nr_elems as usize,
+                })
+            }
+            _ => Err(Expected("array")),
+        }
+    }
+}
+
+impl<'t, 'a> de::SeqAccess<'a> for Elements<'t, 'a> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'a>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        let (elem, rest) = self.data.split_at(self.elem_size);
+
+        self.data = rest;
+        self.remaining -= 1;
+
+        seed.deserialize(Deserializer::new(self.decoder, self.type_id, elem))
+            .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::LittleEndian;
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::{file::IntEncoding, ty::Member, writer};
+
+    fn int(name: &'static str, size: usize, encoding: IntEncoding) -> Type<'static> {
+        Type::Int {
+            name: name.into(),
+            size,
+            bits_offset: 0,
+            nr_bits: size * 8,
+            encoding,
+        }
+    }
+
+    fn member(
+        name: Option<&'static str>,
+        type_id: u32,
+        bits_offset: u32,
+        bitfield_size: u32,
+    ) -> Member<'static> {
+        Member {
+            name: name.map(Into::into),
+            type_id,
+            bits_offset,
+            bitfield_size,
+        }
+    }
+
+    const EVENT: u32 = 5;
+    const OUTER: u32 = 8;
+    const FLAGS: u32 = 9;
+
+    fn types() -> Vec<u8> {
+        writer::write::<LittleEndian>(&[
+            int("int", 4, IntEncoding::SIGNED),
+            int("unsigned int", 4, IntEncoding::empty()),
+            int("char", 1, IntEncoding::SIGNED),
+            Type::Array {
+                type_id: 3,
+                index_type_id: 1,
+                nr_elems: 16,
+            },
+            // struct event { char comm[16]; int pad; unsigned int pid; };
+            Type::Struct {
+                name: Some("event".into()),
+                size: 24,
+                members: vec![
+                    member(Some("comm"), 4, 0, 0),
+                    member(Some("pad"), 1, 128, 0),
+                    member(Some("pid"), 2, 160, 0),
+                ],
+            },
+            Type::Struct {
+                name: None,
+                size: 4,
+                members: vec![member(Some("a"), 1, 0, 0)],
+            },
+            Type::Union {
+                name: None,
+                size: 4,
+                members: vec![member(Some("b"), 2, 0, 0), member(Some("c"), 1, 0, 0)],
+            },
+            // struct outer { int x; struct { int a; }; union { unsigned int b; int c; }; };
+            Type::Struct {
+                name: Some("outer".into()),
+                size: 12,
+                members: vec![
+                    member(Some("x"), 1, 0, 0),
+                    member(None, 6, 32, 0),
+                    member(None, 7, 64, 0),
+                ],
+            },
+            // struct flags { unsigned int lo:3; unsigned int hi:5; };
+            Type::Struct {
+                name: Some("flags".into()),
+                size: 4,
+                members: vec![member(Some("lo"), 2, 0, 3), member(Some("hi"), 2, 3, 5)],
+            },
+        ])
+        .unwrap()
+    }
+
+    fn event() -> Vec<u8> {
+        let mut data = b"bash\0garbage\0\0\0\0".to_vec();
+
+        data.extend_from_slice(&(-1i32).to_le_bytes());
+        data.extend_from_slice(&42u32.to_le_bytes());
+        data
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Event {
+        pid: u32,
+        comm: String,
+    }
+
+    #[test]
+    fn struct_by_member_name() {
+        let b = types();
+        let types = TypeTable::parse(untrusted::Input::from(&b)).unwrap();
+        let data = event();
+
+        // the members are found at their BTF offsets, and the string stops at the NUL
+        assert_eq!(
+            from_bytes::<Event>(&types, EVENT, &data).unwrap(),
+            Event {
+                pid: 42,
+                comm: "bash".into()
+            }
+        );
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Borrowed<'a> {
+            comm: &'a str,
+        }
+
+        assert_eq!(
+            from_bytes::<Borrowed>(&types, EVENT, &data).unwrap(),
+            Borrowed { comm: "bash" }
+        );
+    }
+
+    #[test]
+    fn anonymous_members_are_flattened() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Outer {
+            x: i32,
+            a: i32,
+            b: u32,
+            c: i32,
+        }
+
+        let b = types();
+        let types = TypeTable::parse(untrusted::Input::from(&b)).unwrap();
+        let data = [1i32, 2, -3]
+            .iter()
+            .flat_map(|n| n.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            from_bytes::<Outer>(&types, OUTER, &data).unwrap(),
+            Outer {
+                x: 1,
+                a: 2,
+                b: 0xffff_fffd,
+                c: -3
+            }
+        );
+    }
+
+    #[test]
+    fn bitfields() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Flags {
+            lo: u8,
+            hi: u8,
+        }
+
+        let b = types();
+        let types = TypeTable::parse(untrusted::Input::from(&b)).unwrap();
+
+        assert_eq!(
+            from_bytes::<Flags>(&types, FLAGS, &[0b1010_1110, 0, 0, 0]).unwrap(),
+            Flags { lo: 6, hi: 21 }
+        );
+    }
+
+    #[test]
+    fn missing_member() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Task {
+            pid: u32,
+            uid: u32,
+        }
+
+        let b = types();
+        let types = TypeTable::parse(untrusted::Input::from(&b)).unwrap();
+        let data = event();
+
+        match from_bytes::<Task>(&types, EVENT, &data) {
+            Err(Error::Message(msg)) => assert_eq!(msg, "missing field `uid`"),
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+}
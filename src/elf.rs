@@ -0,0 +1,424 @@
+use core::ops::Range;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::{
+    file::{self, ReadBytesExt},
+    Error::{self, *},
+};
+
+pub const MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+const EI_CLASS: usize = 4;
+const EI_DATA: usize = 5;
+const EI_NIDENT: usize = 16;
+
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+
+const ELFDATA2LSB: u8 = 1;
+const ELFDATA2MSB: u8 = 2;
+
+const SHN_UNDEF: u16 = 0;
+const SHN_XINDEX: u16 = 0xffff;
+
+pub const SHT_NOBITS: u32 = 8;
+pub const SHF_COMPRESSED: u64 = 0x800;
+
+pub const BTF_SECTION: &str = ".BTF";
+pub const BTF_EXT_SECTION: &str = ".BTF.ext";
+
+/// Returns `true` if `b` starts with the ELF magic.
+pub fn is_elf(b: &[u8]) -> bool {
+    b.starts_with(&MAGIC)
+}
+
+#[derive(Clone, Debug)]
+pub struct SectionHeader {
+    pub name: u32,
+    pub ty: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+    pub info: u32,
+    pub addralign: u64,
+    pub entsize: u64,
+}
+
+impl SectionHeader {
+    fn read<O: ByteOrder>(r: &mut untrusted::Reader, is_64: bool) -> Result<Self, Error> {
+        if is_64 {
+            Ok(SectionHeader {
+                name: r.read_u32::<O>()?,
+                ty: r.read_u32::<O>()?,
+                flags: r.read_u64::<O>()?,
+                addr: r.read_u64::<O>()?,
+                offset: r.read_u64::<O>()?,
+                size: r.read_u64::<O>()?,
+                link: r.read_u32::<O>()?,
+                info: r.read_u32::<O>()?,
+                addralign: r.read_u64::<O>()?,
+                entsize: r.read_u64::<O>()?,
+            })
+        } else {
+            Ok(SectionHeader {
+                name: r.read_u32::<O>()?,
+                ty: r.read_u32::<O>()?,
+                flags: r.read_u32::<O>()? as u64,
+                addr: r.read_u32::<O>()? as u64,
+                offset: r.read_u32::<O>()? as u64,
+                size: r.read_u32::<O>()? as u64,
+                link: r.read_u32::<O>()?,
+                info: r.read_u32::<O>()?,
+                addralign: r.read_u32::<O>()? as u64,
+                entsize: r.read_u32::<O>()? as u64,
+            })
+        }
+    }
+
+    fn range(&self) -> Option<Range<usize>> {
+        if self.ty == SHT_NOBITS {
+            Some(0..0)
+        } else {
+            let start = usize::try_from(self.offset).ok()?;
+            let len = usize::try_from(self.size).ok()?;
+
+            Some(start..start.checked_add(len)?)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Section<'a> {
+    pub name: &'a str,
+    pub header: SectionHeader,
+    pub data: &'a [u8],
+}
+
+impl<'a> Section<'a> {
+    pub fn is_compressed(&self) -> bool {
+        self.header.flags & SHF_COMPRESSED != 0
+    }
+}
+
+/// An ELF object, such as a BPF object, a kernel module or a vmlinux image.
+///
+/// The name and the data of a section are only checked when the section is looked up,
+/// a malformed section doesn't prevent reading the others.
+#[derive(Clone, Debug)]
+pub struct Object<'a> {
+    pub is_64: bool,
+    pub is_le: bool,
+    pub headers: Vec<SectionHeader>,
+    data: &'a [u8],
+    shstrtab: Option<untrusted::Input<'a>>,
+}
+
+impl<'a> Object<'a> {
+    pub fn parse(b: &'a [u8]) -> Result<Object<'a>, Error> {
+        if b.len() < EI_NIDENT || !is_elf(b) {
+            return Err(Malformed("invalid ELF magic"));
+        }
+
+        let is_64 = match b[EI_CLASS] {
+            ELFCLASS32 => false,
+            ELFCLASS64 => true,
+            _ => return Err(Unexpected("ELF class")),
+        };
+
+        match b[EI_DATA] {
+            ELFDATA2LSB => Self::read::<LittleEndian>(b, is_64, true),
+            ELFDATA2MSB => Self::read::<BigEndian>(b, is_64, false),
+            _ => Err(Unexpected("ELF data encoding")),
+        }
+    }
+
+    fn read<O: ByteOrder>(b: &'a [u8], is_64: bool, is_le: bool) -> Result<Object<'a>, Error> {
+        let input = untrusted::Input::from(b);
+
+        let (shoff, shentsize, mut shnum, mut shstrndx) = input.read_all(EndOfInput, |r| {
+            r.skip(EI_NIDENT)?;
+            r.skip(2 + 2 + 4)?; // e_type, e_machine, e_version
+
+            let shoff = if is_64 {
+                r.skip(8 + 8)?; // e_entry, e_phoff
+                r.read_u64::<O>()?
+            } else {
+                r.skip(4 + 4)?; // e_entry, e_phoff
+                r.read_u32::<O>()? as u64
+            };
+
+            r.skip(4 + 2 + 2 + 2)?; // e_flags, e_ehsize, e_phentsize, e_phnum
+
+            let shentsize = r.read_u16::<O>()? as usize;
+            let shnum = r.read_u16::<O>()? as usize;
+            let shstrndx = r.read_u16::<O>()? as usize;
+
+            r.skip_to_end();
+
+            Ok((shoff, shentsize, shnum, shstrndx))
+        })?;
+
+        if shoff == 0 {
+            return Ok(Object {
+                is_64,
+                is_le,
+                headers: Vec::new(),
+                data: b,
+                shstrtab: None,
+            });
+        }
+
+        let shoff = usize::try_from(shoff).map_err(|_| OutOfRange("e_shoff", shoff))?;
+        let min_shentsize = if is_64 { 64 } else { 40 };
+
+        if shentsize < min_shentsize {
+            return Err(Malformed("ELF section header too small"));
+        }
+
+        let read_shdr = |idx: usize| -> Result<SectionHeader, Error> {
+            let start = idx
+                .checked_mul(shentsize)
+                .and_then(|off| off.checked_add(shoff))
+                .ok_or(OutOfRange("section header", idx as u64))?;
+            let end = start
+                .checked_add(shentsize)
+                .ok_or(OutOfRange("section header", idx as u64))?;
+            let hdr = b
+                .get(start..end)
+                .ok_or(OutOfRange("section header", idx as u64))?;
+
+            untrusted::Input::from(hdr).read_all(EndOfInput, |r| {
+                let shdr = SectionHeader::read::<O>(r, is_64)?;
+                r.skip_to_end();
+                Ok(shdr)
+            })
+        };
+
+        // The real number of sections and the section name string table index
+        // are stored in the first section header if they don't fit in the ELF header.
+        if shnum == 0 || shstrndx == SHN_XINDEX as usize {
+            let shdr = read_shdr(0)?;
+
+            if shnum == 0 {
+                shnum = shdr.size as usize;
+            }
+            if shstrndx == SHN_XINDEX as usize {
+                shstrndx = shdr.link as usize;
+            }
+        }
+
+        let shdrs = (0..shnum).map(read_shdr).collect::<Result<Vec<_>, _>>()?;
+
+        let shstrtab = if shstrndx == SHN_UNDEF as usize {
+            None
+        } else {
+            let shdr = shdrs
+                .get(shstrndx)
+                .ok_or(OutOfRange("e_shstrndx", shstrndx as u64))?;
+
+            Some(untrusted::Input::from(section_data(b, shdr)?))
+        };
+
+        Ok(Object {
+            is_64,
+            is_le,
+            headers: shdrs,
+            data: b,
+            shstrtab,
+        })
+    }
+
+    /// The name of a section, empty if the object has no section name string table.
+    pub fn name(&self, header: &SectionHeader) -> Result<&'a str, Error> {
+        match self.shstrtab {
+            Some(ref strs) => Ok(file::read_str(strs, header.name)?.unwrap_or_default()),
+            None => Ok(""),
+        }
+    }
+
+    /// Read the sections in order, failing on the malformed ones.
+    pub fn sections(&self) -> impl Iterator<Item = Result<Section<'a>, Error>> + '_ {
+        self.headers
+            .iter()
+            .map(move |header| self.read_section(header))
+    }
+
+    fn read_section(&self, header: &SectionHeader) -> Result<Section<'a>, Error> {
+        Ok(Section {
+            name: self.name(header)?,
+            data: section_data(self.data, header)?,
+            header: header.clone(),
+        })
+    }
+
+    /// Find a section by name, skipping the sections whose name can't be read.
+    pub fn section(&self, name: &str) -> Result<Option<Section<'a>>, Error> {
+        self.headers
+            .iter()
+            .find(|header| self.name(header).is_ok_and(|s| s == name))
+            .map(|header| self.read_section(header))
+            .transpose()
+    }
+
+    pub fn section_data(&self, name: &str) -> Result<Option<&'a [u8]>, Error> {
+        match self.section(name)? {
+            Some(s) if s.is_compressed() => Err(Unexpected("compressed section")),
+            Some(s) => Ok(Some(s.data)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn section_data<'a>(b: &'a [u8], shdr: &SectionHeader) -> Result<&'a [u8], Error> {
+    shdr.range()
+        .and_then(|r| b.get(r))
+        .ok_or(OutOfRange("section data", shdr.offset))
+}
+
+/// The raw `.BTF` and `.BTF.ext` data found in a file.
+#[derive(Clone, Copy, Debug)]
+pub struct BtfSections<'a> {
+    pub btf: &'a [u8],
+    pub btf_ext: Option<&'a [u8]>,
+}
+
+/// Locate the BTF data in a raw BTF file or an ELF object.
+pub fn btf_sections(b: &[u8]) -> Result<BtfSections<'_>, Error> {
+    if is_elf(b) {
+        let obj = Object::parse(b)?;
+
+        Ok(BtfSections {
+            btf: obj
+                .section_data(BTF_SECTION)?
+                .ok_or(Expected(".BTF section"))?,
+            btf_ext: obj.section_data(BTF_EXT_SECTION)?,
+        })
+    } else {
+        Ok(BtfSections {
+            btf: b,
+            btf_ext: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn section_headers_out_of_range() {
+        let mut b = [0u8; 64];
+
+        b[..4].copy_from_slice(&MAGIC);
+        b[EI_CLASS] = ELFCLASS64;
+        b[EI_DATA] = ELFDATA2LSB;
+        LittleEndian::write_u64(&mut b[0x28..], 0xffff_ffff_ffff_ffd0); // e_shoff
+        LittleEndian::write_u16(&mut b[0x3a..], 64); // e_shentsize
+        LittleEndian::write_u16(&mut b[0x3c..], 1); // e_shnum
+
+        assert!(matches!(
+            Object::parse(&b),
+            Err(OutOfRange("section header", 0))
+        ));
+        assert!(crate::parse(&b).is_err());
+    }
+
+    const SHT_STRTAB: u32 = 3;
+
+    fn shdr(name: u32, ty: u32, offset: u64, size: u64, link: u32) -> SectionHeader {
+        SectionHeader {
+            name,
+            ty,
+            flags: 0,
+            addr: 0,
+            offset,
+            size,
+            link,
+            info: 0,
+            addralign: 0,
+            entsize: 0,
+        }
+    }
+
+    /// A little endian ELF64 object, with `data` right after its header and followed by `shdrs`.
+    fn object(data: &[u8], shdrs: &[SectionHeader], shnum: u16, shstrndx: u16) -> Vec<u8> {
+        let mut b = vec![0u8; 64];
+
+        b[..4].copy_from_slice(&MAGIC);
+        b[EI_CLASS] = ELFCLASS64;
+        b[EI_DATA] = ELFDATA2LSB;
+        LittleEndian::write_u64(&mut b[0x28..], 64 + data.len() as u64); // e_shoff
+        LittleEndian::write_u16(&mut b[0x3a..], 64); // e_shentsize
+        LittleEndian::write_u16(&mut b[0x3c..], shnum); // e_shnum
+        LittleEndian::write_u16(&mut b[0x3e..], shstrndx); // e_shstrndx
+        b.extend_from_slice(data);
+
+        for shdr in shdrs {
+            let mut h = [0u8; 64];
+
+            LittleEndian::write_u32(&mut h[0..], shdr.name);
+            LittleEndian::write_u32(&mut h[4..], shdr.ty);
+            LittleEndian::write_u64(&mut h[24..], shdr.offset);
+            LittleEndian::write_u64(&mut h[32..], shdr.size);
+            LittleEndian::write_u32(&mut h[40..], shdr.link);
+            b.extend_from_slice(&h);
+        }
+
+        b
+    }
+
+    const STRS: &[u8] = b"\0.shstrtab\0.BTF\0bad\0";
+    const BTF: &[u8] = b"BTFDATA";
+
+    #[test]
+    fn malformed_sections_are_checked_when_looked_up() {
+        let data = [STRS, BTF].concat();
+        let btf_off = 64 + STRS.len() as u64;
+        let b = object(
+            &data,
+            &[
+                shdr(0, 0, 0, 0, 0),
+                shdr(1, SHT_STRTAB, 64, STRS.len() as u64, 0),
+                // data out of range
+                shdr(16, 1, 0xffff_0000, 16, 0),
+                // name out of range
+                shdr(1000, 1, btf_off, 1, 0),
+                shdr(11, 1, btf_off, BTF.len() as u64, 0),
+            ],
+            5,
+            1,
+        );
+
+        assert_eq!(btf_sections(&b).unwrap().btf, BTF);
+
+        let obj = Object::parse(&b).unwrap();
+
+        assert!(matches!(
+            obj.section("bad"),
+            Err(OutOfRange("section data", 0xffff_0000))
+        ));
+        assert_eq!(obj.sections().filter(Result::is_err).count(), 2);
+    }
+
+    #[test]
+    fn extended_section_name_table_index() {
+        // more sections than fit in e_shnum, the names are in the last one
+        let nr_sections = 0x1_0002;
+        let data = [STRS, BTF].concat();
+        let mut shdrs = vec![shdr(0, 0, 0, 0, 0); nr_sections];
+
+        shdrs[0] = shdr(0, 0, 0, nr_sections as u64, nr_sections as u32 - 1);
+        shdrs[1] = shdr(11, 1, 64 + STRS.len() as u64, BTF.len() as u64, 0);
+        shdrs[nr_sections - 1] = shdr(1, SHT_STRTAB, 64, STRS.len() as u64, 0);
+
+        let b = object(&data, &shdrs, 0, SHN_XINDEX);
+
+        assert_eq!(btf_sections(&b).unwrap().btf, BTF);
+    }
+}
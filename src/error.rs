@@ -1,5 +1,14 @@
-#[cfg(feature = "std")]
-use thiserror::Error;
+use core::fmt;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "std")] {
+        use thiserror::Error;
+    } else if #[cfg(feature = "alloc")] {
+        use alloc::{boxed::Box, vec::Vec};
+    }
+}
+
+use crate::file::Kind;
 
 #[cfg_attr(feature = "std", derive(Error))]
 #[derive(Debug)]
@@ -13,7 +22,7 @@ pub enum Error {
     #[cfg_attr(feature = "std", error("malformed BTF file, {0}"))]
     Malformed(&'static str),
 
-    #[cfg_attr(feature = "std", error("offset {0} out of range"))]
+    #[cfg_attr(feature = "std", error("{0} {1} out of range"))]
     OutOfRange(&'static str, u64),
 
     #[cfg_attr(feature = "std", error("unexpected {0}"))]
@@ -22,6 +31,26 @@ pub enum Error {
     #[cfg_attr(feature = "std", error("expected {0}"))]
     Expected(&'static str),
 
+    #[cfg_attr(feature = "std", error("unknown kind {0}"))]
+    UnknownKind(u8),
+
+    #[cfg_attr(feature = "std", error("type of kind {} has no size", .0.as_str()))]
+    Unsized(Kind),
+
+    #[cfg_attr(feature = "std", error("type reference loop at type_id {0}"))]
+    Cycle(u32),
+
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(feature = "std", error("{source}, at {location}"))]
+    Decode {
+        location: Location,
+        source: Box<Error>,
+    },
+
+    #[cfg(all(feature = "serde", feature = "std"))]
+    #[error("{0}")]
+    Message(String),
+
     #[cfg_attr(feature = "std", error(transparent))]
     FmtError(#[cfg_attr(feature = "std", from)] core::fmt::Error),
 
@@ -38,3 +67,162 @@ impl From<untrusted::EndOfInput> for Error {
         Error::EndOfInput
     }
 }
+
+impl Error {
+    /// Attach a location to this error, or refine the location it already carries.
+    #[cfg(feature = "alloc")]
+    pub fn locate<F: FnOnce(&mut Location)>(self, f: F) -> Error {
+        match self {
+            Error::Decode {
+                mut location,
+                source,
+            } => {
+                f(&mut location);
+
+                Error::Decode { location, source }
+            }
+            err => {
+                let mut location = Location::default();
+
+                f(&mut location);
+
+                Error::Decode {
+                    location,
+                    source: Box::new(err),
+                }
+            }
+        }
+    }
+
+    /// Without the `alloc` feature the location can't be kept, the error is left as is.
+    #[cfg(not(feature = "alloc"))]
+    pub fn locate<F: FnOnce(&mut Location)>(self, _f: F) -> Error {
+        self
+    }
+
+    /// The location of the error, if known.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            #[cfg(feature = "alloc")]
+            Error::Decode { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// The error without its location.
+    pub fn root_cause(&self) -> &Error {
+        match self {
+            #[cfg(feature = "alloc")]
+            Error::Decode { source, .. } => source.root_cause(),
+            err => err,
+        }
+    }
+}
+
+/// The part of the file an offset refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section {
+    Header,
+    Types,
+    Strings,
+    FuncInfo,
+    LineInfo,
+    CoreRelo,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Section::Header => write!(f, "header"),
+            Section::Types => write!(f, "type section"),
+            Section::Strings => write!(f, "string section"),
+            Section::FuncInfo => write!(f, "func_info section"),
+            Section::LineInfo => write!(f, "line_info section"),
+            Section::CoreRelo => write!(f, "core_relo section"),
+        }
+    }
+}
+
+/// The component of a record being decoded, innermost first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Context {
+    Member(usize),
+    Param(usize),
+    Value(usize),
+    Entry(usize),
+    Record(usize),
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Context::Member(idx) => write!(f, "member {}", idx),
+            Context::Param(idx) => write!(f, "param {}", idx),
+            Context::Value(idx) => write!(f, "value {}", idx),
+            Context::Entry(idx) => write!(f, "entry {}", idx),
+            Context::Record(idx) => write!(f, "record {}", idx),
+        }
+    }
+}
+
+/// Where a decoding error happened.
+///
+/// Until `section` is known, `offset` is relative to the start of the record being decoded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+    pub section: Option<Section>,
+    pub offset: Option<usize>,
+    pub type_id: Option<u32>,
+    pub kind: Option<Kind>,
+    #[cfg(feature = "alloc")]
+    pub context: Vec<Context>,
+}
+
+impl Location {
+    /// Place a relative offset in `section`, with the current record starting at `start`.
+    pub fn rebase(&mut self, section: Section, start: usize) {
+        if self.section.is_none() {
+            self.section = Some(section);
+            self.offset = Some(start + self.offset.unwrap_or_default());
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[cfg(feature = "alloc")]
+        let context = &self.context[..];
+        #[cfg(not(feature = "alloc"))]
+        let context: &[Context] = &[];
+        let mut sep = "";
+
+        match (self.section, self.offset) {
+            (Some(section), Some(offset)) => {
+                write!(f, "{} offset {:#x}", section, offset)?;
+                sep = ", ";
+            }
+            (Some(section), None) => {
+                write!(f, "{}", section)?;
+                sep = ", ";
+            }
+            _ => {}
+        }
+
+        if context.is_empty() && self.type_id.is_none() && self.kind.is_none() {
+            return Ok(());
+        }
+
+        f.write_str(sep)?;
+
+        for ctx in context {
+            write!(f, "{} of ", ctx)?;
+        }
+
+        match (self.kind, self.type_id) {
+            (Some(kind), Some(type_id)) => write!(f, "{} {}", kind.as_str(), type_id),
+            (Some(kind), None) => write!(f, "{}", kind.as_str()),
+            (None, Some(type_id)) => write!(f, "type {}", type_id),
+            (None, None) => write!(f, "record"),
+        }
+    }
+}
@@ -0,0 +1,521 @@
+use core::mem;
+use core::ops::Range;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    error::{Context, Section},
+    file::{self, ReadBytesExt, ReadExt},
+    Error::{self, *},
+};
+
+#[repr(C)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub magic: u16,
+    pub version: u8,
+    pub flags: u8,
+    pub len: u32,
+
+    /* All offsets are in bytes relative to the end of this header */
+    pub func_info_off: u32,
+    pub func_info_len: u32,
+    pub line_info_off: u32,
+    pub line_info_len: u32,
+
+    /* optional part of .BTF.ext header */
+    pub core_relo_off: u32,
+    pub core_relo_len: u32,
+}
+
+impl Header {
+    pub const MAGIC: u16 = file::Header::MAGIC;
+
+    /// The size of the header without the optional CO-RE relocation part.
+    pub const MIN_LEN: usize = 24;
+
+    pub fn is_le(&self) -> bool {
+        self.magic == Self::MAGIC
+    }
+
+    pub fn read<O: ByteOrder>(r: &mut untrusted::Reader) -> Result<Self, Error> {
+        let mut hdr = Header {
+            magic: r.read_u16::<LittleEndian>()?,
+            version: r.read_byte()?,
+            flags: r.read_byte()?,
+            len: r.read_u32::<O>()?,
+            func_info_off: r.read_u32::<O>()?,
+            func_info_len: r.read_u32::<O>()?,
+            line_info_off: r.read_u32::<O>()?,
+            line_info_len: r.read_u32::<O>()?,
+            core_relo_off: 0,
+            core_relo_len: 0,
+        };
+
+        let len = hdr.len as usize;
+        let mut read = Self::MIN_LEN;
+
+        if len < Self::MIN_LEN {
+            return Err(Malformed("BTF.ext header too short"));
+        }
+
+        if len >= mem::size_of::<Self>() {
+            hdr.core_relo_off = r.read_u32::<O>()?;
+            hdr.core_relo_len = r.read_u32::<O>()?;
+            read = mem::size_of::<Self>();
+        }
+
+        if len > read {
+            r.skip(len - read)?;
+        }
+
+        Ok(hdr)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FuncInfo {
+    pub insn_off: u32,
+    pub type_id: u32,
+}
+
+impl<'a> ReadExt<'a> for FuncInfo {
+    type Error = Error;
+
+    fn read<O: ByteOrder>(r: &mut untrusted::Reader) -> Result<Self, Error> {
+        Ok(FuncInfo {
+            insn_off: r.read_u32::<O>()?,
+            type_id: r.read_u32::<O>()?,
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineInfo {
+    pub insn_off: u32,
+    pub file_name_off: u32,
+    pub line_off: u32,
+    pub line_col: u32,
+}
+
+impl LineInfo {
+    const LINE_SHIFT: u32 = 10;
+    const COL_MASK: u32 = 0x3ff;
+
+    pub fn line_num(&self) -> u32 {
+        self.line_col >> Self::LINE_SHIFT
+    }
+
+    pub fn line_col(&self) -> u32 {
+        self.line_col & Self::COL_MASK
+    }
+}
+
+impl<'a> ReadExt<'a> for LineInfo {
+    type Error = Error;
+
+    fn read<O: ByteOrder>(r: &mut untrusted::Reader) -> Result<Self, Error> {
+        Ok(LineInfo {
+            insn_off: r.read_u32::<O>()?,
+            file_name_off: r.read_u32::<O>()?,
+            line_off: r.read_u32::<O>()?,
+            line_col: r.read_u32::<O>()?,
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CoreRelo {
+    pub insn_off: u32,
+    pub type_id: u32,
+    pub access_str_off: u32,
+    pub kind: u32,
+}
+
+impl<'a> ReadExt<'a> for CoreRelo {
+    type Error = Error;
+
+    fn read<O: ByteOrder>(r: &mut untrusted::Reader) -> Result<Self, Error> {
+        Ok(CoreRelo {
+            insn_off: r.read_u32::<O>()?,
+            type_id: r.read_u32::<O>()?,
+            access_str_off: r.read_u32::<O>()?,
+            kind: r.read_u32::<O>()?,
+        })
+    }
+}
+
+/// The kind of a CO-RE relocation, see `enum bpf_core_relo_kind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum RelocKind {
+    FieldByteOffset,
+    FieldByteSize,
+    FieldExists,
+    FieldSigned,
+    FieldLShiftU64,
+    FieldRShiftU64,
+    TypeIdLocal,
+    TypeIdTarget,
+    TypeExists,
+    TypeSize,
+    EnumValueExists,
+    EnumValue,
+    TypeMatches,
+    Other(u32),
+}
+
+impl RelocKind {
+    pub fn is_field_based(&self) -> bool {
+        matches!(
+            self,
+            RelocKind::FieldByteOffset
+                | RelocKind::FieldByteSize
+                | RelocKind::FieldExists
+                | RelocKind::FieldSigned
+                | RelocKind::FieldLShiftU64
+                | RelocKind::FieldRShiftU64
+        )
+    }
+
+    pub fn is_type_based(&self) -> bool {
+        matches!(
+            self,
+            RelocKind::TypeIdLocal
+                | RelocKind::TypeIdTarget
+                | RelocKind::TypeExists
+                | RelocKind::TypeSize
+                | RelocKind::TypeMatches
+        )
+    }
+
+    pub fn is_enum_value_based(&self) -> bool {
+        matches!(self, RelocKind::EnumValueExists | RelocKind::EnumValue)
+    }
+}
+
+impl From<u32> for RelocKind {
+    fn from(v: u32) -> Self {
+        match v {
+            0 => RelocKind::FieldByteOffset,
+            1 => RelocKind::FieldByteSize,
+            2 => RelocKind::FieldExists,
+            3 => RelocKind::FieldSigned,
+            4 => RelocKind::FieldLShiftU64,
+            5 => RelocKind::FieldRShiftU64,
+            6 => RelocKind::TypeIdLocal,
+            7 => RelocKind::TypeIdTarget,
+            8 => RelocKind::TypeExists,
+            9 => RelocKind::TypeSize,
+            10 => RelocKind::EnumValueExists,
+            11 => RelocKind::EnumValue,
+            12 => RelocKind::TypeMatches,
+            _ => RelocKind::Other(v),
+        }
+    }
+}
+
+impl From<RelocKind> for u32 {
+    fn from(k: RelocKind) -> Self {
+        match k {
+            RelocKind::FieldByteOffset => 0,
+            RelocKind::FieldByteSize => 1,
+            RelocKind::FieldExists => 2,
+            RelocKind::FieldSigned => 3,
+            RelocKind::FieldLShiftU64 => 4,
+            RelocKind::FieldRShiftU64 => 5,
+            RelocKind::TypeIdLocal => 6,
+            RelocKind::TypeIdTarget => 7,
+            RelocKind::TypeExists => 8,
+            RelocKind::TypeSize => 9,
+            RelocKind::EnumValueExists => 10,
+            RelocKind::EnumValue => 11,
+            RelocKind::TypeMatches => 12,
+            RelocKind::Other(v) => v,
+        }
+    }
+}
+
+impl core::fmt::Display for RelocKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            RelocKind::FieldByteOffset => write!(f, "byte_off"),
+            RelocKind::FieldByteSize => write!(f, "byte_sz"),
+            RelocKind::FieldExists => write!(f, "field_exists"),
+            RelocKind::FieldSigned => write!(f, "signed"),
+            RelocKind::FieldLShiftU64 => write!(f, "lshift_u64"),
+            RelocKind::FieldRShiftU64 => write!(f, "rshift_u64"),
+            RelocKind::TypeIdLocal => write!(f, "local_type_id"),
+            RelocKind::TypeIdTarget => write!(f, "target_type_id"),
+            RelocKind::TypeExists => write!(f, "type_exists"),
+            RelocKind::TypeSize => write!(f, "type_size"),
+            RelocKind::EnumValueExists => write!(f, "enumval_exists"),
+            RelocKind::EnumValue => write!(f, "enumval_value"),
+            RelocKind::TypeMatches => write!(f, "type_matches"),
+            RelocKind::Other(v) => write!(f, "unknown({})", v),
+        }
+    }
+}
+
+/// The per-section records of a `.BTF.ext` sub-section.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct InfoSec<'a, T> {
+    pub sec_name: &'a str,
+    pub records: Vec<T>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Func {
+    pub insn_off: u32,
+    pub type_id: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Line<'a> {
+    pub insn_off: u32,
+    pub file_name: Option<&'a str>,
+    pub line: Option<&'a str>,
+    pub line_num: u32,
+    pub line_col: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Relocation<'a> {
+    pub insn_off: u32,
+    pub type_id: u32,
+    pub access_str: &'a str,
+    pub kind: RelocKind,
+}
+
+#[derive(Clone, Debug)]
+pub struct File<'a> {
+    pub header: Header,
+    pub func_info: untrusted::Input<'a>,
+    pub line_info: untrusted::Input<'a>,
+    pub core_relo: untrusted::Input<'a>,
+}
+
+impl<'a> ReadExt<'a> for File<'a> {
+    type Error = Error;
+
+    fn read<O: ByteOrder>(r: &mut untrusted::Reader<'a>) -> Result<File<'a>, Error> {
+        let header = Header::read::<O>(r)?;
+        let data = r.read_bytes_to_end();
+
+        let section = |name, off: u32, len: u32| -> Result<untrusted::Input<'a>, Error> {
+            let start = off as usize;
+            let end = start
+                .checked_add(len as usize)
+                .ok_or(OutOfRange(name, off as u64))?;
+
+            sub_input(data, start..end).ok_or(OutOfRange(name, off as u64))
+        };
+
+        Ok(File {
+            func_info: section("func_info", header.func_info_off, header.func_info_len)?,
+            line_info: section("line_info", header.line_info_off, header.line_info_len)?,
+            core_relo: section("core_relo", header.core_relo_off, header.core_relo_len)?,
+            header,
+        })
+    }
+}
+
+impl<'a> File<'a> {
+    /// The `func_info` records of every section.
+    pub fn func_info(&self, strs: &file::Strings<'a>) -> Result<Vec<InfoSec<'a, Func>>, Error> {
+        self.read_info(self.func_info, strs)
+    }
+
+    /// The `line_info` records of every section.
+    pub fn line_info(&self, strs: &file::Strings<'a>) -> Result<Vec<InfoSec<'a, Line<'a>>>, Error> {
+        self.read_info(self.line_info, strs)
+    }
+
+    /// The CO-RE relocation records of every section.
+    pub fn core_relo(
+        &self,
+        strs: &file::Strings<'a>,
+    ) -> Result<Vec<InfoSec<'a, Relocation<'a>>>, Error> {
+        self.read_info(self.core_relo, strs)
+    }
+
+    fn read_info<T: Record<'a>>(
+        &self,
+        input: untrusted::Input<'a>,
+        strs: &file::Strings<'a>,
+    ) -> Result<Vec<InfoSec<'a, T>>, Error> {
+        if self.header.is_le() {
+            read_info::<LittleEndian, T>(input, strs)
+        } else {
+            read_info::<BigEndian, T>(input, strs)
+        }
+    }
+}
+
+trait Record<'a>: Sized {
+    /// The minimum size of the raw record.
+    const SIZE: usize;
+
+    /// The sub-section holding the records.
+    const SECTION: Section;
+
+    fn read<O: ByteOrder>(
+        r: &mut untrusted::Reader<'a>,
+        strs: &file::Strings<'a>,
+    ) -> Result<Self, Error>;
+}
+
+impl<'a> Record<'a> for Func {
+    const SIZE: usize = mem::size_of::<FuncInfo>();
+    const SECTION: Section = Section::FuncInfo;
+
+    fn read<O: ByteOrder>(
+        r: &mut untrusted::Reader<'a>,
+        _strs: &file::Strings<'a>,
+    ) -> Result<Self, Error> {
+        let fi = FuncInfo::read::<O>(r)?;
+
+        Ok(Func {
+            insn_off: fi.insn_off,
+            type_id: fi.type_id,
+        })
+    }
+}
+
+impl<'a> Record<'a> for Line<'a> {
+    const SIZE: usize = mem::size_of::<LineInfo>();
+    const SECTION: Section = Section::LineInfo;
+
+    fn read<O: ByteOrder>(
+        r: &mut untrusted::Reader<'a>,
+        strs: &file::Strings<'a>,
+    ) -> Result<Self, Error> {
+        let li = LineInfo::read::<O>(r)?;
+
+        Ok(Line {
+            insn_off: li.insn_off,
+            file_name: strs.get(li.file_name_off)?,
+            line: strs.get(li.line_off)?,
+            line_num: li.line_num(),
+            line_col: li.line_col(),
+        })
+    }
+}
+
+impl<'a> Record<'a> for Relocation<'a> {
+    const SIZE: usize = mem::size_of::<CoreRelo>();
+    const SECTION: Section = Section::CoreRelo;
+
+    fn read<O: ByteOrder>(
+        r: &mut untrusted::Reader<'a>,
+        strs: &file::Strings<'a>,
+    ) -> Result<Self, Error> {
+        let cr = CoreRelo::read::<O>(r)?;
+
+        Ok(Relocation {
+            insn_off: cr.insn_off,
+            type_id: cr.type_id,
+            access_str: strs
+                .get(cr.access_str_off)?
+                .ok_or(Expected("core relo access string"))?,
+            kind: RelocKind::from(cr.kind),
+        })
+    }
+}
+
+fn read_info<'a, O: ByteOrder, T: Record<'a>>(
+    input: untrusted::Input<'a>,
+    strs: &file::Strings<'a>,
+) -> Result<Vec<InfoSec<'a, T>>, Error> {
+    if input.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    input.read_all(EndOfInput, |r| {
+        let rec_size = r.read_u32::<O>().map_err(at(T::SECTION, 0))? as usize;
+
+        if rec_size < T::SIZE {
+            return Err(at(T::SECTION, 0)(Malformed(
+                "BTF.ext record size too small",
+            )));
+        }
+
+        let mut secs = Vec::new();
+        let mut off = mem::size_of::<u32>();
+
+        while !r.at_end() {
+            let sec_name = r
+                .read_u32::<O>()
+                .map_err(Error::from)
+                .and_then(|name_off| strs.get(name_off))
+                .and_then(|name| name.ok_or(Expected("BTF.ext section name")))
+                .map_err(at(T::SECTION, off))?;
+            let num_info =
+                r.read_u32::<O>()
+                    .map_err(at(T::SECTION, off + mem::size_of::<u32>()))? as usize;
+
+            off += mem::size_of::<u32>() * 2;
+
+            let records = (0..num_info)
+                .map(|idx| {
+                    let start = off + idx * rec_size;
+
+                    r.read_bytes(rec_size)
+                        .map_err(Error::from)
+                        .and_then(|b| {
+                            b.read_all(EndOfInput, |r| {
+                                let rec = T::read::<O>(r, strs)?;
+                                r.skip_to_end();
+                                Ok(rec)
+                            })
+                        })
+                        .map_err(|err| {
+                            err.locate(|loc| {
+                                loc.context.push(Context::Record(idx));
+                                loc.rebase(T::SECTION, start);
+                            })
+                        })
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+
+            off += num_info * rec_size;
+
+            secs.push(InfoSec { sec_name, records });
+        }
+
+        Ok(secs)
+    })
+}
+
+/// Locate an error at `offset` in `section`.
+fn at<E: Into<Error>>(section: Section, offset: usize) -> impl FnOnce(E) -> Error {
+    move |err| err.into().locate(|loc| loc.rebase(section, offset))
+}
+
+fn sub_input(input: untrusted::Input, range: Range<usize>) -> Option<untrusted::Input> {
+    input
+        .as_slice_less_safe()
+        .get(range)
+        .map(untrusted::Input::from)
+}
+
+pub fn parse(input: untrusted::Input) -> Result<File, Error> {
+    match input.as_slice_less_safe() {
+        [0x9f, 0xeb, ..] => input.read_all(EndOfInput, File::read::<LittleEndian>),
+        [0xeb, 0x9f, ..] => input.read_all(EndOfInput, File::read::<BigEndian>),
+        _ => Err(Malformed("invalid magic")),
+    }
+}
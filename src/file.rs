@@ -1,13 +1,27 @@
 use core::mem;
+use core::ops::Range;
 use core::str::{from_utf8, FromStr};
 
+cfg_if::cfg_if! {
+    if #[cfg(feature = "std")] {
+        use std::borrow::Cow;
+    } else if #[cfg(feature = "alloc")] {
+        use alloc::borrow::Cow;
+        use alloc::string::String;
+        use alloc::vec::Vec;
+    }
+}
+
 use byteorder::{BigEndian, ByteOrder, LittleEndian};
 use derive_more::{Deref, Display, From};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use crate::Error::{self, *};
+use crate::{
+    error::Section,
+    Error::{self, *},
+};
 
 pub trait ReadExt<'a>
 where
@@ -31,6 +45,10 @@ pub trait ReadBytesExt {
         self.read_ty(O::read_u32)
     }
 
+    fn read_u64<O: ByteOrder>(&mut self) -> Result<u64, Self::Error> {
+        self.read_ty(O::read_u64)
+    }
+
     fn read_i16<O: ByteOrder>(&mut self) -> Result<i16, Self::Error> {
         self.read_ty(O::read_i16)
     }
@@ -49,6 +67,39 @@ impl<'a> ReadBytesExt for untrusted::Reader<'a> {
     }
 }
 
+pub trait WriteExt {
+    fn write<O: ByteOrder>(&self, w: &mut impl WriteBytesExt);
+}
+
+pub trait WriteBytesExt {
+    fn write_ty<const N: usize, F: FnOnce(&mut [u8])>(&mut self, f: F);
+
+    fn write_u8(&mut self, n: u8) {
+        self.write_ty::<1, _>(|b| b[0] = n)
+    }
+
+    fn write_u16<O: ByteOrder>(&mut self, n: u16) {
+        self.write_ty::<2, _>(|b| O::write_u16(b, n))
+    }
+
+    fn write_u32<O: ByteOrder>(&mut self, n: u32) {
+        self.write_ty::<4, _>(|b| O::write_u32(b, n))
+    }
+
+    fn write_i32<O: ByteOrder>(&mut self, n: i32) {
+        self.write_ty::<4, _>(|b| O::write_i32(b, n))
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl WriteBytesExt for Vec<u8> {
+    fn write_ty<const N: usize, F: FnOnce(&mut [u8])>(&mut self, f: F) {
+        let mut buf = [0u8; N];
+        f(&mut buf);
+        self.extend_from_slice(&buf)
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Header {
@@ -72,6 +123,7 @@ impl Header {
         self.magic == Self::MAGIC
     }
 
+    /// Read the known fields, the rest of the `len` bytes of the header are left to the caller.
     pub fn read<O: ByteOrder>(r: &mut untrusted::Reader) -> Result<Self, Error> {
         let hdr = Header {
             magic: r.read_u16::<LittleEndian>()?,
@@ -84,17 +136,23 @@ impl Header {
             str_len: r.read_u32::<O>()?,
         };
 
-        match (hdr.len as usize).checked_sub(mem::size_of::<Self>()) {
-            Some(n) if n > 0 => {
-                r.skip(n)?;
-            }
-            _ => {}
-        }
-
         Ok(hdr)
     }
 }
 
+impl WriteExt for Header {
+    fn write<O: ByteOrder>(&self, w: &mut impl WriteBytesExt) {
+        w.write_u16::<O>(self.magic);
+        w.write_u8(self.version);
+        w.write_u8(self.flags);
+        w.write_u32::<O>(self.len);
+        w.write_u32::<O>(self.type_off);
+        w.write_u32::<O>(self.type_len);
+        w.write_u32::<O>(self.str_off);
+        w.write_u32::<O>(self.str_len);
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, PartialEq, Deref)]
 pub struct Type {
@@ -141,11 +199,19 @@ impl<'a> ReadExt<'a> for Type {
     }
 }
 
+impl WriteExt for Type {
+    fn write<O: ByteOrder>(&self, w: &mut impl WriteBytesExt) {
+        w.write_u32::<O>(self.name_off);
+        w.write_u32::<O>(self.info.0);
+        w.write_u32::<O>(self.size_or_type);
+    }
+}
+
 /* "info" bits arrangement
  * bits  0-15: vlen (e.g. # of struct's members)
  * bits 16-23: unused
- * bits 24-27: kind (e.g. int, ptr, array...etc)
- * bits 28-30: unused
+ * bits 24-28: kind (e.g. int, ptr, array...etc)
+ * bits 29-30: unused
  * bit     31: kind_flag, currently used by
  *             struct, union and fwd
  */
@@ -159,72 +225,142 @@ impl Info {
     const KIND_FLAG: u32 = 0x8000_0000;
     const KIND_SHIFT: usize = 24;
 
+    pub fn new(kind: Kind, kflag: bool, vlen: usize) -> Self {
+        Info(
+            ((u8::from(kind) as u32) << Self::KIND_SHIFT)
+                | if kflag { Self::KIND_FLAG } else { 0 }
+                | (vlen as u32 & Self::VLEN_MASK),
+        )
+    }
+
     pub fn vlen(&self) -> usize {
         (self.0 & Self::VLEN_MASK) as usize
     }
 
     pub fn kind(&self) -> Kind {
-        unsafe { mem::transmute(((self.0 & Self::KIND_MASK) >> Self::KIND_SHIFT) as u8) }
+        Kind::from(((self.0 & Self::KIND_MASK) >> Self::KIND_SHIFT) as u8)
     }
 
     pub fn kflag(&self) -> bool {
         (self.0 & Self::KIND_FLAG) != 0
     }
 
-    pub fn type_size(&self) -> usize {
-        mem::size_of::<Type>()
-            + match self.kind() {
-                Kind::Integer => mem::size_of::<u32>(),
-                Kind::Enum => mem::size_of::<Enum>() * self.vlen(),
-                Kind::Enum64 => mem::size_of::<Enum64>() * self.vlen(),
-                Kind::Array => mem::size_of::<Array>(),
-                Kind::Struct | Kind::Union => mem::size_of::<Member>() * self.vlen(),
-                Kind::FuncProto => mem::size_of::<Param>() * self.vlen(),
-                Kind::Variable => mem::size_of::<Var>(),
-                Kind::DataSection => mem::size_of::<VarSectInfo>() * self.vlen(),
-                Kind::DeclTag => mem::size_of::<DeclTag>(),
-                Kind::Unknown
-                | Kind::Forward
-                | Kind::Const
-                | Kind::Volatile
-                | Kind::Restrict
-                | Kind::Pointer
-                | Kind::Typedef
-                | Kind::Func
-                | Kind::Float
-                | Kind::TypeTag => 0,
-            }
+    /// The size of the whole type record, or `None` if the kind is unknown.
+    pub fn type_size(&self) -> Option<usize> {
+        Some(
+            mem::size_of::<Type>()
+                + match self.kind() {
+                    Kind::Integer => mem::size_of::<u32>(),
+                    Kind::Enum => mem::size_of::<Enum>() * self.vlen(),
+                    Kind::Enum64 => mem::size_of::<Enum64>() * self.vlen(),
+                    Kind::Array => mem::size_of::<Array>(),
+                    Kind::Struct | Kind::Union => mem::size_of::<Member>() * self.vlen(),
+                    Kind::FuncProto => mem::size_of::<Param>() * self.vlen(),
+                    Kind::Variable => mem::size_of::<u32>(), // struct btf_var { __u32 linkage; }
+                    Kind::DataSection => mem::size_of::<VarSectInfo>() * self.vlen(),
+                    Kind::DeclTag => mem::size_of::<DeclTag>(),
+                    Kind::Unknown
+                    | Kind::Forward
+                    | Kind::Const
+                    | Kind::Volatile
+                    | Kind::Restrict
+                    | Kind::Pointer
+                    | Kind::Typedef
+                    | Kind::Func
+                    | Kind::Float
+                    | Kind::TypeTag => 0,
+                    Kind::Other(_) => return None,
+                },
+        )
     }
 }
 
-#[repr(u8)]
-#[derive(Clone, Copy, Debug, PartialEq, Display, From)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display)]
 #[cfg_attr(
     feature = "serde",
     derive(Deserialize, Serialize),
     serde(rename_all = "lowercase")
 )]
 pub enum Kind {
-    Unknown = 0,
-    Integer = 1,
-    Pointer = 2,
-    Array = 3,
-    Struct = 4,
-    Union = 5,
-    Enum = 6,
-    Forward = 7,
-    Typedef = 8,
-    Volatile = 9,
-    Const = 10,
-    Restrict = 11,
-    Func = 12,
-    FuncProto = 13,
-    Variable = 14,
-    DataSection = 15,
-    Float = 16,
-    DeclTag = 17,
-    TypeTag = 18,
-    Enum64 = 19,
+    Unknown,
+    Integer,
+    Pointer,
+    Array,
+    Struct,
+    Union,
+    Enum,
+    Forward,
+    Typedef,
+    Volatile,
+    Const,
+    Restrict,
+    Func,
+    FuncProto,
+    Variable,
+    DataSection,
+    Float,
+    DeclTag,
+    TypeTag,
+    Enum64,
+    /// A kind unknown to this crate, e.g. from a newer kernel.
+    #[display(fmt = "Other({})", _0)]
+    Other(u8),
+}
+
+impl From<u8> for Kind {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => Kind::Unknown,
+            1 => Kind::Integer,
+            2 => Kind::Pointer,
+            3 => Kind::Array,
+            4 => Kind::Struct,
+            5 => Kind::Union,
+            6 => Kind::Enum,
+            7 => Kind::Forward,
+            8 => Kind::Typedef,
+            9 => Kind::Volatile,
+            10 => Kind::Const,
+            11 => Kind::Restrict,
+            12 => Kind::Func,
+            13 => Kind::FuncProto,
+            14 => Kind::Variable,
+            15 => Kind::DataSection,
+            16 => Kind::Float,
+            17 => Kind::DeclTag,
+            18 => Kind::TypeTag,
+            19 => Kind::Enum64,
+            _ => Kind::Other(v),
+        }
+    }
+}
+
+impl From<Kind> for u8 {
+    fn from(k: Kind) -> Self {
+        match k {
+            Kind::Unknown => 0,
+            Kind::Integer => 1,
+            Kind::Pointer => 2,
+            Kind::Array => 3,
+            Kind::Struct => 4,
+            Kind::Union => 5,
+            Kind::Enum => 6,
+            Kind::Forward => 7,
+            Kind::Typedef => 8,
+            Kind::Volatile => 9,
+            Kind::Const => 10,
+            Kind::Restrict => 11,
+            Kind::Func => 12,
+            Kind::FuncProto => 13,
+            Kind::Variable => 14,
+            Kind::DataSection => 15,
+            Kind::Float => 16,
+            Kind::DeclTag => 17,
+            Kind::TypeTag => 18,
+            Kind::Enum64 => 19,
+            Kind::Other(v) => v,
+        }
+    }
 }
 
 impl Kind {
@@ -322,10 +458,40 @@ impl Kind {
     pub fn is_any_enum(&self) -> bool {
         matches!(self, Kind::Enum | Kind::Enum64)
     }
+
+    pub fn is_other(&self) -> bool {
+        matches!(self, Kind::Other(_))
+    }
+
+    /// The short name of the kind, as used by libbpf and bpftool.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Unknown => "unkn",
+            Kind::Integer => "int",
+            Kind::Pointer => "ptr",
+            Kind::Array => "array",
+            Kind::Struct => "struct",
+            Kind::Union => "union",
+            Kind::Enum => "enum",
+            Kind::Forward => "fwd",
+            Kind::Typedef => "typedef",
+            Kind::Volatile => "volatile",
+            Kind::Const => "const",
+            Kind::Restrict => "restrict",
+            Kind::Func => "func",
+            Kind::FuncProto => "func_proto",
+            Kind::Variable => "var",
+            Kind::DataSection => "datasec",
+            Kind::Float => "float",
+            Kind::DeclTag => "decl_tag",
+            Kind::TypeTag => "type_tag",
+            Kind::Enum64 => "enum64",
+            Kind::Other(_) => "unknown",
+        }
+    }
 }
 
 /// BTF_KIND_INT is followed by a u32 and the following is the 32 bits arrangement:
-
 #[repr(transparent)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Int(pub u32);
@@ -340,6 +506,12 @@ impl Int {
     const ENCODING_SHIFT: usize = 24;
     const OFFSET_SHIFT: usize = 16;
 
+    pub fn new(encoding: IntEncoding, offset: usize, bits: usize) -> Self {
+        Int((encoding.bits << Self::ENCODING_SHIFT)
+            | ((offset as u32) << Self::OFFSET_SHIFT) & Self::OFFSET_MASK
+            | (bits as u32) & Self::BITS_MASK)
+    }
+
     pub fn offset(&self) -> usize {
         ((self.0 & Self::OFFSET_MASK) >> Self::OFFSET_SHIFT) as usize
     }
@@ -373,6 +545,12 @@ impl<'a> ReadExt<'a> for Int {
     }
 }
 
+impl WriteExt for Int {
+    fn write<O: ByteOrder>(&self, w: &mut impl WriteBytesExt) {
+        w.write_u32::<O>(self.0)
+    }
+}
+
 bitflags::bitflags! {
     #[derive(Default)]
     pub struct IntEncoding: u32 {
@@ -400,15 +578,19 @@ impl FromStr for IntEncoding {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        s.split('|').map(str::trim).map(str::to_lowercase).fold(
-            Ok(IntEncoding::default()),
-            |e, s| match s.as_str() {
-                "signed" => e.map(|e| e | Self::SIGNED),
-                "char" => e.map(|e| e | Self::CHAR),
-                "bool" => e.map(|e| e | Self::BOOL),
-                _ => Err(Unexpected("int encoding")),
-            },
-        )
+        s.split('|')
+            .map(str::trim)
+            .try_fold(IntEncoding::default(), |e, s| {
+                if s.eq_ignore_ascii_case("signed") {
+                    Ok(e | Self::SIGNED)
+                } else if s.eq_ignore_ascii_case("char") {
+                    Ok(e | Self::CHAR)
+                } else if s.eq_ignore_ascii_case("bool") {
+                    Ok(e | Self::BOOL)
+                } else {
+                    Err(Unexpected("int encoding"))
+                }
+            })
     }
 }
 
@@ -466,6 +648,14 @@ impl<'a> ReadExt<'a> for Array {
     }
 }
 
+impl WriteExt for Array {
+    fn write<O: ByteOrder>(&self, w: &mut impl WriteBytesExt) {
+        w.write_u32::<O>(self.ty);
+        w.write_u32::<O>(self.index_ty);
+        w.write_u32::<O>(self.nelems);
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Member {
@@ -496,6 +686,14 @@ impl<'a> ReadExt<'a> for Member {
     }
 }
 
+impl WriteExt for Member {
+    fn write<O: ByteOrder>(&self, w: &mut impl WriteBytesExt) {
+        w.write_u32::<O>(self.name_off);
+        w.write_u32::<O>(self.ty);
+        w.write_u32::<O>(self.offset);
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Enum {
@@ -514,6 +712,13 @@ impl<'a> ReadExt<'a> for Enum {
     }
 }
 
+impl WriteExt for Enum {
+    fn write<O: ByteOrder>(&self, w: &mut impl WriteBytesExt) {
+        w.write_u32::<O>(self.name_off);
+        w.write_u32::<O>(self.val);
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Enum64 {
@@ -534,6 +739,14 @@ impl<'a> ReadExt<'a> for Enum64 {
     }
 }
 
+impl WriteExt for Enum64 {
+    fn write<O: ByteOrder>(&self, w: &mut impl WriteBytesExt) {
+        w.write_u32::<O>(self.name_off);
+        w.write_u32::<O>(self.val_lo32);
+        w.write_u32::<O>(self.val_hi32);
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Param {
@@ -558,6 +771,13 @@ impl<'a> ReadExt<'a> for Param {
     }
 }
 
+impl WriteExt for Param {
+    fn write<O: ByteOrder>(&self, w: &mut impl WriteBytesExt) {
+        w.write_u32::<O>(self.name_off);
+        w.write_u32::<O>(self.ty);
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Var {
@@ -574,7 +794,12 @@ impl<'a> ReadExt<'a> for Var {
     }
 }
 
-#[repr(u32)]
+impl WriteExt for Var {
+    fn write<O: ByteOrder>(&self, w: &mut impl WriteBytesExt) {
+        w.write_u32::<O>(self.linkage.into());
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[cfg_attr(
     feature = "serde",
@@ -582,9 +807,11 @@ impl<'a> ReadExt<'a> for Var {
     serde(rename_all = "lowercase")
 )]
 pub enum Linkage {
-    Static = 0,
-    Global = 1,
-    Extern = 2,
+    Static,
+    Global,
+    Extern,
+    /// A linkage unknown to this crate, e.g. from a newer kernel.
+    Other(u32),
 }
 
 impl core::fmt::Display for Linkage {
@@ -593,13 +820,30 @@ impl core::fmt::Display for Linkage {
             Linkage::Static => write!(f, "static"),
             Linkage::Global => write!(f, "global"),
             Linkage::Extern => write!(f, "extern"),
+            Linkage::Other(v) => write!(f, "unknown({})", v),
         }
     }
 }
 
 impl From<u32> for Linkage {
     fn from(v: u32) -> Self {
-        unsafe { mem::transmute(v) }
+        match v {
+            0 => Linkage::Static,
+            1 => Linkage::Global,
+            2 => Linkage::Extern,
+            _ => Linkage::Other(v),
+        }
+    }
+}
+
+impl From<Linkage> for u32 {
+    fn from(v: Linkage) -> Self {
+        match v {
+            Linkage::Static => 0,
+            Linkage::Global => 1,
+            Linkage::Extern => 2,
+            Linkage::Other(v) => v,
+        }
     }
 }
 
@@ -624,6 +868,14 @@ impl<'a> ReadExt<'a> for VarSectInfo {
     }
 }
 
+impl WriteExt for VarSectInfo {
+    fn write<O: ByteOrder>(&self, w: &mut impl WriteBytesExt) {
+        w.write_u32::<O>(self.type_id);
+        w.write_u32::<O>(self.offset);
+        w.write_u32::<O>(self.size);
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct DeclTag {
@@ -640,9 +892,17 @@ impl<'a> ReadExt<'a> for DeclTag {
     }
 }
 
+impl WriteExt for DeclTag {
+    fn write<O: ByteOrder>(&self, w: &mut impl WriteBytesExt) {
+        w.write_i32::<O>(self.component_idx);
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct File<'a> {
     pub header: Header,
+    /// The header bytes following the fields known to [`Header`], from a newer version of the format.
+    pub extra_header: untrusted::Input<'a>,
     pub types: untrusted::Input<'a>,
     pub strs: untrusted::Input<'a>,
 }
@@ -651,24 +911,160 @@ impl<'a> ReadExt<'a> for File<'a> {
     type Error = Error;
 
     fn read<O: ByteOrder>(r: &mut untrusted::Reader<'a>) -> Result<File<'a>, Error> {
-        let header = Header::read::<O>(r)?;
+        let header = Header::read::<O>(r).map_err(at_header(0))?;
 
-        r.skip(header.type_off as usize)?;
+        let extra_header = (header.len as usize)
+            .checked_sub(mem::size_of::<Header>())
+            .ok_or(Malformed("header too short"))
+            .and_then(|n| {
+                r.read_bytes(n)
+                    .map_err(|_| Malformed("header out of bounds"))
+            })
+            .map_err(at_header(4))?;
 
-        let types = r.read_bytes(header.type_len as usize)?;
+        let data = r.read_bytes_to_end();
 
-        r.skip((header.str_off - header.type_off - header.type_len) as usize)?;
+        let section = |off: u32, len: u32| -> Option<Range<usize>> {
+            let start = off as usize;
+            let end = start.checked_add(len as usize)?;
 
-        let strs = r.read_bytes(header.str_len as usize)?;
+            (end <= data.len()).then_some(start..end)
+        };
+
+        let type_range = section(header.type_off, header.type_len)
+            .ok_or(Malformed("type section out of bounds"))
+            .map_err(at_header(8))?;
+        let str_range = section(header.str_off, header.str_len)
+            .ok_or(Malformed("string section out of bounds"))
+            .map_err(at_header(16))?;
+
+        if !type_range.is_empty()
+            && !str_range.is_empty()
+            && type_range.start < str_range.end
+            && str_range.start < type_range.end
+        {
+            return Err(at_header(8)(Malformed("type and string sections overlap")));
+        }
 
-        r.skip_to_end();
+        let b = data.as_slice_less_safe();
 
         Ok(File {
             header,
-            types,
+            extra_header,
+            types: untrusted::Input::from(&b[type_range]),
+            strs: untrusted::Input::from(&b[str_range]),
+        })
+    }
+}
+
+/// Locate an error at `offset` in the header.
+fn at_header(offset: usize) -> impl FnOnce(Error) -> Error {
+    move |err| {
+        err.locate(|loc| {
+            loc.section = Some(Section::Header);
+            loc.offset = Some(offset);
+        })
+    }
+}
+
+impl<'a> File<'a> {
+    pub fn is_le(&self) -> bool {
+        self.header.is_le()
+    }
+
+    /// The number of types in the type section, not including the implicit void type.
+    pub fn type_count(&self) -> Result<u32, Error> {
+        if self.is_le() {
+            self.count_types::<LittleEndian>()
+        } else {
+            self.count_types::<BigEndian>()
+        }
+    }
+
+    fn count_types<O: ByteOrder>(&self) -> Result<u32, Error> {
+        self.types.read_all(EndOfInput, |r| {
+            let mut n = 0;
+            let mut off = 0;
+
+            while !r.at_end() {
+                let size = Type::read::<O>(r)
+                    .and_then(|ty| {
+                        let size = ty.type_size().ok_or(UnknownKind(u8::from(ty.kind())))?;
+
+                        r.skip(size - mem::size_of::<Type>())?;
+
+                        Ok(size)
+                    })
+                    .map_err(|err| {
+                        err.locate(|loc| {
+                            loc.type_id = Some(n + 1);
+                            loc.rebase(Section::Types, off);
+                        })
+                    })?;
+
+                n += 1;
+                off += size;
+            }
+
+            Ok(n)
+        })
+    }
+
+    pub fn strings(&self) -> Strings<'a> {
+        Strings::new(self.strs)
+    }
+}
+
+/// The string section of a BTF file.
+///
+/// The string offsets of a split BTF, such as a kernel module's, continue
+/// after the end of its base BTF's string section.
+#[derive(Clone, Copy, Debug)]
+pub struct Strings<'a> {
+    pub base: Option<untrusted::Input<'a>>,
+    pub strs: untrusted::Input<'a>,
+}
+
+impl<'a> Strings<'a> {
+    pub fn new(strs: untrusted::Input<'a>) -> Self {
+        Strings { base: None, strs }
+    }
+
+    pub fn split(base: untrusted::Input<'a>, strs: untrusted::Input<'a>) -> Self {
+        Strings {
+            base: Some(base),
             strs,
+        }
+    }
+
+    /// The offset of the first string of this string section.
+    pub fn start_off(&self) -> u32 {
+        self.base.map_or(0, |b| b.len() as u32)
+    }
+
+    pub fn get(&self, off: u32) -> Result<Option<&'a str>, Error> {
+        match self.base {
+            Some(ref base) if (off as usize) < base.len() => read_str(base, off),
+            Some(ref base) => read_str(&self.strs, off - base.len() as u32),
+            None => read_str(&self.strs, off),
+        }
+        .map_err(|err| {
+            err.locate(|loc| {
+                loc.section = Some(Section::Strings);
+                loc.offset = Some(off as usize);
+            })
         })
     }
+
+    /// Get the string at `off` without failing, see [`read_str_lossy`].
+    #[cfg(feature = "alloc")]
+    pub fn get_lossy(&self, off: u32) -> Option<Cow<'a, str>> {
+        match self.base {
+            Some(ref base) if (off as usize) < base.len() => read_str_lossy(base, off),
+            Some(ref base) => read_str_lossy(&self.strs, off - base.len() as u32),
+            None => read_str_lossy(&self.strs, off),
+        }
+    }
 }
 
 pub fn parse(input: untrusted::Input) -> Result<File, Error> {
@@ -683,12 +1079,66 @@ pub fn read_str<'a>(input: &untrusted::Input<'a>, off: u32) -> Result<Option<&'a
     if off == 0 {
         Ok(None)
     } else {
-        input
+        let b = input
             .as_slice_less_safe()
             .get(off as usize..)
-            .and_then(|s| s.split(|&b| b == 0).next())
-            .map(from_utf8)
-            .transpose()
-            .map_err(Utf8Error)
+            .ok_or(OutOfRange("string offset", off as u64))?;
+        let s = b.split(|&b| b == 0).next().unwrap_or_default();
+
+        from_utf8(s).map(Some).map_err(Utf8Error)
+    }
+}
+
+/// Read the string at `off`, replacing invalid UTF-8 sequences with `U+FFFD`, `None` if `off` is out of range.
+#[cfg(feature = "alloc")]
+pub fn read_str_lossy<'a>(input: &untrusted::Input<'a>, off: u32) -> Option<Cow<'a, str>> {
+    if off == 0 {
+        return None;
+    }
+
+    let b = input.as_slice_less_safe().get(off as usize..)?;
+
+    Some(String::from_utf8_lossy(
+        b.split(|&b| b == 0).next().unwrap_or_default(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(type_off: u32, type_len: u32, str_off: u32, str_len: u32) -> Vec<u8> {
+        let hdr = Header {
+            magic: Header::MAGIC,
+            version: Header::VERSION,
+            flags: 0,
+            len: mem::size_of::<Header>() as u32,
+            type_off,
+            type_len,
+            str_off,
+            str_len,
+        };
+        let mut b = Vec::new();
+
+        hdr.write::<LittleEndian>(&mut b);
+        b.extend_from_slice(b"\0int\0long");
+        b
+    }
+
+    fn read(b: &[u8]) -> Result<File<'_>, Error> {
+        File::read::<LittleEndian>(&mut untrusted::Reader::new(untrusted::Input::from(b)))
+    }
+
+    #[test]
+    fn empty_sections_dont_overlap() {
+        assert!(read(&file(4, 0, 0, 9)).is_ok());
+        assert!(read(&file(0, 9, 4, 0)).is_ok());
+
+        let err = read(&file(0, 8, 4, 5)).unwrap_err();
+
+        assert!(matches!(
+            err.root_cause(),
+            Malformed("type and string sections overlap")
+        ));
     }
 }
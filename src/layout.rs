@@ -0,0 +1,212 @@
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use crate::{
+    file::Kind,
+    table::TypeTable,
+    ty::Type,
+    Error::{self, *},
+};
+
+/// Resolve the size and alignment of types, like `btf__resolve_size` and `btf__align_of` of libbpf.
+#[derive(Clone, Copy, Debug)]
+pub struct Layout<'t, 'a> {
+    types: &'t TypeTable<'a>,
+    ptr_size: usize,
+}
+
+impl<'t, 'a> Layout<'t, 'a> {
+    /// Lay out the types for a target whose pointers are `ptr_size` bytes, see [`guess_ptr_size`].
+    pub fn new(types: &'t TypeTable<'a>, ptr_size: usize) -> Self {
+        Layout { types, ptr_size }
+    }
+
+    pub fn ptr_size(&self) -> usize {
+        self.ptr_size
+    }
+
+    /// Follow the typedefs, modifiers, variables and tags to the type they refer to.
+    pub fn resolve_type(&self, type_id: u32) -> Result<u32, Error> {
+        let mut id = type_id;
+
+        for _ in 0..self.max_depth() {
+            let ty = self.types.raw(id)?;
+
+            match ty.kind() {
+                Kind::Typedef
+                | Kind::Volatile
+                | Kind::Const
+                | Kind::Restrict
+                | Kind::Variable
+                | Kind::DeclTag
+                | Kind::TypeTag => id = ty.type_id(),
+                _ => return Ok(id),
+            }
+        }
+
+        Err(Cycle(type_id))
+    }
+
+    /// The size in bytes of a value of the type.
+    pub fn resolve_size(&self, type_id: u32) -> Result<usize, Error> {
+        let mut id = type_id;
+        let mut nr_elems = 1usize;
+
+        for _ in 0..self.max_depth() {
+            let ty = self.types.raw(id)?;
+            let size = match ty.kind() {
+                Kind::Integer
+                | Kind::Float
+                | Kind::Enum
+                | Kind::Enum64
+                | Kind::Struct
+                | Kind::Union
+                | Kind::DataSection => ty.size(),
+                Kind::Pointer => self.ptr_size,
+                Kind::Typedef
+                | Kind::Volatile
+                | Kind::Const
+                | Kind::Restrict
+                | Kind::Variable
+                | Kind::DeclTag
+                | Kind::TypeTag => {
+                    id = ty.type_id();
+                    continue;
+                }
+                Kind::Array => {
+                    let (elem_type_id, n) = self.array(id)?;
+
+                    nr_elems = nr_elems
+                        .checked_mul(n as usize)
+                        .ok_or(OutOfRange("array size", n as u64))?;
+                    id = elem_type_id;
+                    continue;
+                }
+                kind => return Err(Unsized(kind)),
+            };
+
+            return size
+                .checked_mul(nr_elems)
+                .ok_or(OutOfRange("array size", nr_elems as u64));
+        }
+
+        Err(Cycle(type_id))
+    }
+
+    /// The natural alignment in bytes of the type, 1 for a packed struct or union.
+    pub fn align_of(&self, type_id: u32) -> Result<usize, Error> {
+        self.align(type_id, &mut Vec::new())
+    }
+
+    /// Returns `true` if the offset of a member or the size of the struct or union breaks the natural alignment.
+    pub fn is_packed(&self, type_id: u32) -> Result<bool, Error> {
+        let id = self.resolve_type(type_id)?;
+
+        match self.types.kind(id)? {
+            Kind::Struct | Kind::Union => self
+                .composite(id, &mut Vec::new())
+                .map(|(_, packed)| packed),
+            _ => Err(Expected("struct or union")),
+        }
+    }
+
+    fn align(&self, type_id: u32, path: &mut Vec<u32>) -> Result<usize, Error> {
+        let mut id = type_id;
+
+        for _ in 0..self.max_depth() {
+            let ty = self.types.raw(id)?;
+
+            match ty.kind() {
+                Kind::Integer | Kind::Float | Kind::Enum | Kind::Enum64 => {
+                    return Ok(ty.size().min(self.ptr_size))
+                }
+                Kind::Pointer => return Ok(self.ptr_size),
+                Kind::Typedef | Kind::Volatile | Kind::Const | Kind::Restrict | Kind::TypeTag => {
+                    id = ty.type_id()
+                }
+                Kind::Array => id = self.array(id)?.0,
+                Kind::Struct | Kind::Union => {
+                    return self
+                        .composite(id, path)
+                        .map(|(align, packed)| if packed { 1 } else { align })
+                }
+                kind => return Err(Unsized(kind)),
+            }
+        }
+
+        Err(Cycle(type_id))
+    }
+
+    /// The largest alignment of the members of a struct or union, and whether it is packed.
+    fn composite(&self, type_id: u32, path: &mut Vec<u32>) -> Result<(usize, bool), Error> {
+        if path.contains(&type_id) {
+            return Err(Cycle(type_id));
+        }
+
+        path.push(type_id);
+
+        let res = self.members_align(type_id, path);
+
+        path.pop();
+
+        res
+    }
+
+    fn members_align(&self, type_id: u32, path: &mut Vec<u32>) -> Result<(usize, bool), Error> {
+        let (size, members) = match self.types.get(type_id)? {
+            Type::Struct { size, members, .. } | Type::Union { size, members, .. } => {
+                (size, members)
+            }
+            _ => return Err(Expected("struct or union")),
+        };
+
+        let mut max_align = 1;
+        let mut packed = false;
+
+        for m in &members {
+            let align = self.align(m.type_id, path)?;
+
+            max_align = max_align.max(align);
+
+            if m.bitfield_size == 0 && !(m.bits_offset as usize).is_multiple_of(align * 8) {
+                packed = true;
+            }
+        }
+
+        Ok((max_align, packed || !size.is_multiple_of(max_align)))
+    }
+
+    fn array(&self, type_id: u32) -> Result<(u32, u32), Error> {
+        match self.types.get(type_id)? {
+            Type::Array {
+                type_id, nr_elems, ..
+            } => Ok((type_id, nr_elems)),
+            _ => Err(Expected("array")),
+        }
+    }
+
+    /// A chain of references longer than the number of types must loop back on itself.
+    fn max_depth(&self) -> u32 {
+        self.types.end_id() + 1
+    }
+}
+
+/// Guess the pointer size of the target from the size of its `long` type, like libbpf.
+pub fn guess_ptr_size(types: &TypeTable) -> Option<usize> {
+    if let Some(size) = types.base().and_then(guess_ptr_size) {
+        return Some(size);
+    }
+
+    types.type_ids().find_map(|type_id| {
+        let ty = types.raw(type_id).ok()?;
+
+        if !ty.kind().is_int() || !matches!(ty.size(), 4 | 8) {
+            return None;
+        }
+
+        match types.name(type_id).ok()?? {
+            "long" | "long int" | "unsigned long" | "long unsigned int" => Some(ty.size()),
+            _ => None,
+        }
+    })
+}
@@ -1,19 +1,58 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-#[cfg(not(feature = "std"))]
+#[cfg(all(feature = "alloc", not(feature = "std")))]
 extern crate alloc;
 
-mod error;
+pub mod error;
 pub mod file;
-pub mod ty;
+pub mod view;
 
-#[cfg(feature = "rust")]
-pub mod rust;
-
-pub use self::error::Error;
-pub use self::ty::{Type, Types};
+pub use self::error::{Error, Location};
 pub use self::file::Kind;
 
-pub fn parse(b: &[u8]) -> Result<self::Types, Error> {
-    self::Types::parse(untrusted::Input::from(b))
+cfg_if::cfg_if! {
+    if #[cfg(feature = "alloc")] {
+        pub mod c;
+        pub mod data;
+        #[cfg(all(feature = "serde", feature = "std"))]
+        pub mod de;
+        pub mod elf;
+        pub mod ext;
+        pub mod layout;
+        pub mod names;
+        mod owned;
+        pub mod patch;
+        pub mod reloc;
+        #[cfg(all(feature = "serde", feature = "std"))]
+        pub mod ser;
+        pub mod table;
+        pub mod ty;
+        pub mod validate;
+        pub mod value;
+        pub mod writer;
+
+        pub use self::layout::Layout;
+        pub use self::names::NameIndex;
+        pub use self::owned::Btf;
+        pub use self::table::TypeTable;
+        pub use self::ty::{Type, Types};
+        pub use self::validate::validate;
+        pub use self::value::Value;
+        pub use self::writer::Writer;
+
+        /// Parse the types of a raw BTF file, or of the `.BTF` section of an ELF object.
+        pub fn parse(b: &[u8]) -> Result<self::Types<'_>, Error> {
+            self::Types::parse(untrusted::Input::from(elf::btf_sections(b)?.btf))
+        }
+
+        /// Parse the types of a split BTF, such as a kernel module's, on top of its base BTF.
+        pub fn parse_split<'a>(b: &'a [u8], base: &'a [u8]) -> Result<self::Types<'a>, Error> {
+            let base = file::parse(untrusted::Input::from(elf::btf_sections(base)?.btf))?;
+
+            self::Types::parse_split(untrusted::Input::from(elf::btf_sections(b)?.btf), &base)
+        }
+    }
 }
+
+#[cfg(feature = "rust")]
+pub mod rust;
@@ -0,0 +1,78 @@
+cfg_if::cfg_if! {
+    if #[cfg(feature = "std")] {
+        use std::collections::HashMap as Map;
+    } else {
+        use alloc::{collections::BTreeMap as Map, vec::Vec};
+    }
+}
+
+use crate::{file::Kind, table::TypeTable, Error};
+
+/// An index of the named types, built in one pass over a type table and its base.
+///
+/// The index is a hash map with the `std` feature, and a B-tree without.
+#[derive(Clone, Debug, Default)]
+pub struct NameIndex<'a> {
+    names: Map<&'a str, Vec<(Kind, u32)>>,
+}
+
+impl<'a> NameIndex<'a> {
+    /// Index the types of `types`, and those of its base for split BTF.
+    pub fn new(types: &TypeTable<'a>) -> Result<NameIndex<'a>, Error> {
+        let mut index = NameIndex::default();
+
+        index.add_table(types)?;
+
+        Ok(index)
+    }
+
+    fn add_table(&mut self, types: &TypeTable<'a>) -> Result<(), Error> {
+        if let Some(base) = types.base() {
+            self.add_table(base)?;
+        }
+
+        for type_id in types.type_ids() {
+            let ty = types.raw(type_id)?;
+
+            if let Some(name) = types.strings().get(ty.name_off)? {
+                self.names
+                    .entry(name)
+                    .or_default()
+                    .push((ty.kind(), type_id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The number of distinct names.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The first type with the name and kind, e.g. `struct task_struct`, looking into the base first.
+    pub fn get(&self, name: &str, kind: Kind) -> Option<u32> {
+        self.find_by_kind(name, kind).next()
+    }
+
+    /// The ids of the types with the name, in increasing order.
+    pub fn find<'i>(&'i self, name: &str) -> impl Iterator<Item = u32> + 'i {
+        self.entries(name).iter().map(|&(_, type_id)| type_id)
+    }
+
+    /// The ids of the types with the name and kind, e.g. all the typedefs named `u32`, in increasing order.
+    pub fn find_by_kind<'i>(&'i self, name: &str, kind: Kind) -> impl Iterator<Item = u32> + 'i {
+        self.entries(name)
+            .iter()
+            .filter(move |&&(k, _)| k == kind)
+            .map(|&(_, type_id)| type_id)
+    }
+
+    fn entries(&self, name: &str) -> &[(Kind, u32)] {
+        self.names.get(name).map_or(&[], |ids| ids.as_slice())
+    }
+}
@@ -0,0 +1,175 @@
+use core::ops::{Deref, Range};
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "std")] {
+        use std::{borrow::Cow, fs, io, path::Path, sync::Arc};
+    } else {
+        use alloc::{borrow::Cow, sync::Arc, vec::Vec};
+    }
+}
+
+use crate::{elf, file, table::TypeTable, ty, Error, Type};
+
+enum Data {
+    Vec(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mmap(memmap::Mmap),
+}
+
+impl Deref for Data {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Data::Vec(v) => v,
+            #[cfg(feature = "mmap")]
+            Data::Mmap(m) => m,
+        }
+    }
+}
+
+/// A BTF file owning its data, which lends out the types decoded from it.
+///
+/// The data is either a raw BTF file or an ELF object with a `.BTF` section.
+pub struct Btf {
+    data: Data,
+    header: file::Header,
+    extra_header: Range<usize>,
+    types: Range<usize>,
+    strs: Range<usize>,
+    offsets: Vec<u32>,
+    base: Option<Arc<Btf>>,
+}
+
+impl Btf {
+    pub fn from_bytes(b: Vec<u8>) -> Result<Btf, Error> {
+        Self::new(Data::Vec(b))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: io::Read>(mut r: R) -> Result<Btf, Error> {
+        let mut b = Vec::new();
+
+        r.read_to_end(&mut b)?;
+
+        Self::from_bytes(b)
+    }
+
+    /// Read a file, such as `/sys/kernel/btf/vmlinux` or a BPF object.
+    #[cfg(feature = "std")]
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Btf, Error> {
+        Self::from_bytes(fs::read(path)?)
+    }
+
+    /// Use a memory mapped file, which must not be modified while the map is alive.
+    #[cfg(feature = "mmap")]
+    pub fn from_mmap(mmap: memmap::Mmap) -> Result<Btf, Error> {
+        Self::new(Data::Mmap(mmap))
+    }
+
+    fn new(data: Data) -> Result<Btf, Error> {
+        let b: &[u8] = &data;
+        let f = file::parse(untrusted::Input::from(elf::btf_sections(b)?.btf))?;
+
+        let range_of = |part: untrusted::Input| {
+            let start = part.as_slice_less_safe().as_ptr() as usize - b.as_ptr() as usize;
+
+            start..start + part.len()
+        };
+
+        let header = f.header.clone();
+        let extra_header = range_of(f.extra_header);
+        let types = range_of(f.types);
+        let strs = range_of(f.strs);
+        let offsets = TypeTable::from_file(f)?.into_offsets();
+
+        Ok(Btf {
+            data,
+            header,
+            extra_header,
+            types,
+            strs,
+            offsets,
+            base: None,
+        })
+    }
+
+    /// Use `base` as the base of this split BTF, such as a kernel module's.
+    pub fn with_base(mut self, base: Arc<Btf>) -> Btf {
+        self.base = Some(base);
+        self
+    }
+
+    pub fn base(&self) -> Option<&Arc<Btf>> {
+        self.base.as_ref()
+    }
+
+    /// The whole data, including the rest of the ELF object.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn is_le(&self) -> bool {
+        self.header.is_le()
+    }
+
+    pub fn file(&self) -> file::File<'_> {
+        file::File {
+            header: self.header.clone(),
+            extra_header: self.input(&self.extra_header),
+            types: self.input(&self.types),
+            strs: self.input(&self.strs),
+        }
+    }
+
+    pub fn strings(&self) -> file::Strings<'_> {
+        match self.base {
+            Some(ref base) => file::Strings::split(base.input(&base.strs), self.input(&self.strs)),
+            None => file::Strings::new(self.input(&self.strs)),
+        }
+    }
+
+    /// The number of types, not including the void type and the base types.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// The type id of the first type, which is 1 unless this is a split BTF.
+    pub fn start_id(&self) -> u32 {
+        self.base.as_ref().map_or(1, |b| b.end_id())
+    }
+
+    /// The type id after the last type.
+    pub fn end_id(&self) -> u32 {
+        self.start_id() + self.offsets.len() as u32
+    }
+
+    /// Iterate the types, not including the base types.
+    pub fn types(&self) -> ty::Types<'_> {
+        ty::Types::new(&self.file(), self.strings(), self.start_id())
+    }
+
+    /// A random-access table of the types, looking into the base for split BTF.
+    pub fn table(&self) -> TypeTable<'_> {
+        TypeTable::from_parts(
+            self.is_le(),
+            self.input(&self.types),
+            self.strings(),
+            Cow::Borrowed(&self.offsets),
+            self.base.as_ref().map(|b| b.table()),
+        )
+    }
+
+    /// Decode the type with the given type id, see [`TypeTable::get`].
+    pub fn get(&self, type_id: u32) -> Result<Type<'_>, Error> {
+        self.table().get(type_id)
+    }
+
+    fn input(&self, range: &Range<usize>) -> untrusted::Input<'_> {
+        untrusted::Input::from(&self.data[range.clone()])
+    }
+}
@@ -0,0 +1,490 @@
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    error::{Context, Section},
+    ext::Relocation,
+    reloc::{Resolution, Resolver},
+    Error::{self, *},
+};
+
+/// The size of a BPF instruction.
+pub const INSN_SIZE: usize = 8;
+
+/// The immediate of a poisoned instruction, reported by the verifier as `invalid func unknown#195896080`.
+pub const POISON_IMM: i32 = 0xbad2310;
+
+const BPF_LD: u8 = 0x00;
+const BPF_LDX: u8 = 0x01;
+const BPF_ST: u8 = 0x02;
+const BPF_STX: u8 = 0x03;
+const BPF_ALU: u8 = 0x04;
+const BPF_JMP: u8 = 0x05;
+const BPF_ALU64: u8 = 0x07;
+
+const BPF_W: u8 = 0x00;
+const BPF_H: u8 = 0x08;
+const BPF_B: u8 = 0x10;
+const BPF_DW: u8 = 0x18;
+
+const BPF_IMM: u8 = 0x00;
+const BPF_K: u8 = 0x00;
+const BPF_CALL: u8 = 0x80;
+
+/// The patched instructions of a program.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Patched<'a> {
+    pub insns: Vec<u8>,
+    /// The outcome of every relocation, in the order of the records.
+    pub reports: Vec<Report<'a>>,
+}
+
+/// The outcome of a relocation applied to an instruction.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Report<'a> {
+    /// The index of the relocated instruction in the program.
+    pub insn_idx: usize,
+    pub relocation: Relocation<'a>,
+    pub resolution: Resolution<'a>,
+    /// The instruction was replaced by an invalid call, which the verifier rejects if it is reachable.
+    pub poisoned: bool,
+}
+
+/// Apply the CO-RE relocations of a program section to its instructions, like libbpf's `bpf_core_apply_relo_insn`.
+///
+/// The instructions are in the byte order of the local BTF, and the offsets of the relocations
+/// are relative to the start of the section.
+pub fn patch<'a>(
+    resolver: &Resolver<'_, 'a>,
+    insns: &[u8],
+    relocations: &[Relocation<'a>],
+) -> Result<Patched<'a>, Error> {
+    let is_le = resolver.local().is_le();
+    let mut insns = insns.to_vec();
+
+    let reports = relocations
+        .iter()
+        .enumerate()
+        .map(|(idx, relo)| {
+            apply(resolver, &mut insns, relo, is_le).map_err(|err| {
+                err.locate(|loc| {
+                    loc.section.get_or_insert(Section::CoreRelo);
+                    loc.type_id.get_or_insert(relo.type_id);
+                    loc.context.push(Context::Record(idx));
+                })
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(Patched { insns, reports })
+}
+
+fn apply<'a>(
+    resolver: &Resolver<'_, 'a>,
+    insns: &mut [u8],
+    relo: &Relocation<'a>,
+    is_le: bool,
+) -> Result<Report<'a>, Error> {
+    let off = relo.insn_off as usize;
+
+    if !off.is_multiple_of(INSN_SIZE) || off + INSN_SIZE > insns.len() {
+        return Err(OutOfRange("instruction offset", off as u64));
+    }
+
+    let res = resolver.resolve(relo.type_id, relo.access_str, relo.kind)?;
+    let poisoned = patch_insn(&mut insns[off..], &res, is_le)?;
+
+    Ok(Report {
+        insn_idx: off / INSN_SIZE,
+        relocation: relo.clone(),
+        resolution: res,
+        poisoned,
+    })
+}
+
+/// Patch the instruction at the start of `insns` with the relocated value, like libbpf's `bpf_core_patch_insn`.
+///
+/// Returns `true` if the instruction was poisoned.
+pub fn patch_insn(insns: &mut [u8], res: &Resolution, is_le: bool) -> Result<bool, Error> {
+    let mut insn = Insn::read(insns, is_le)?;
+
+    if res.poison {
+        poison(insns, is_le)?;
+
+        return Ok(true);
+    }
+
+    match insn.class() {
+        BPF_ALU | BPF_ALU64 => {
+            if insn.code & 0x08 != BPF_K {
+                return Err(Expected("ALU instruction with an immediate"));
+            }
+            if res.validate && insn.imm as i64 as u64 != res.orig_val {
+                return Err(Unexpected("immediate of the relocated ALU instruction"));
+            }
+
+            insn.imm = res.new_val as i32;
+        }
+        BPF_LDX | BPF_ST | BPF_STX => {
+            if res.validate && insn.off as i64 as u64 != res.orig_val {
+                return Err(Unexpected("offset of the relocated LDX/ST/STX instruction"));
+            }
+            if res.new_val > i16::MAX as u64 {
+                return Err(OutOfRange("LDX/ST/STX offset", res.new_val));
+            }
+            // only the pointers and unsigned integers can be loaded with another size
+            if res.fail_memsz_adjust {
+                poison(insns, is_le)?;
+
+                return Ok(true);
+            }
+
+            insn.off = res.new_val as i16;
+
+            if res.new_sz != res.orig_sz {
+                if insn.size() != res.orig_sz {
+                    return Err(Unexpected(
+                        "memory size of the relocated LDX/ST/STX instruction",
+                    ));
+                }
+
+                let size = match res.new_sz {
+                    1 => BPF_B,
+                    2 => BPF_H,
+                    4 => BPF_W,
+                    8 => BPF_DW,
+                    n => return Err(OutOfRange("LDX/ST/STX memory size", n as u64)),
+                };
+
+                insn.code = (insn.code & !0x18) | size;
+            }
+        }
+        BPF_LD => {
+            let mut next = match insns.get(INSN_SIZE..) {
+                Some(b) if insn.is_ldimm64() => Insn::read(b, is_le)?,
+                _ => return Err(Expected("LDIMM64 instruction")),
+            };
+
+            if insn.src_reg != 0
+                || insn.off != 0
+                || next.code != 0
+                || next.dst_reg != 0
+                || next.src_reg != 0
+                || next.off != 0
+            {
+                return Err(Expected("LDIMM64 instruction"));
+            }
+
+            let imm = (insn.imm as u32 as u64) | ((next.imm as u32 as u64) << 32);
+
+            if res.validate && imm != res.orig_val {
+                return Err(Unexpected("immediate of the relocated LDIMM64 instruction"));
+            }
+
+            insn.imm = res.new_val as i32;
+            next.imm = (res.new_val >> 32) as i32;
+            next.write(&mut insns[INSN_SIZE..], is_le);
+        }
+        _ => return Err(Unexpected("class of the relocated instruction")),
+    }
+
+    insn.write(insns, is_le);
+
+    Ok(false)
+}
+
+/// Replace the instruction by a call to an invalid helper, and the second half of a LDIMM64 too.
+fn poison(insns: &mut [u8], is_le: bool) -> Result<(), Error> {
+    let insn = Insn::read(insns, is_le)?;
+    let invalid = Insn {
+        code: BPF_JMP | BPF_CALL,
+        dst_reg: 0,
+        src_reg: 0,
+        off: 0,
+        imm: POISON_IMM,
+    };
+
+    if insn.is_ldimm64() {
+        if let Some(next) = insns.get_mut(INSN_SIZE..INSN_SIZE * 2) {
+            invalid.write(next, is_le);
+        }
+    }
+
+    invalid.write(insns, is_le);
+
+    Ok(())
+}
+
+/// A BPF instruction, see `struct bpf_insn`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Insn {
+    code: u8,
+    dst_reg: u8,
+    src_reg: u8,
+    off: i16,
+    imm: i32,
+}
+
+impl Insn {
+    fn read(b: &[u8], is_le: bool) -> Result<Insn, Error> {
+        let b = b.get(..INSN_SIZE).ok_or(EndOfInput)?;
+
+        Ok(if is_le {
+            Insn {
+                code: b[0],
+                dst_reg: b[1] & 0x0f,
+                src_reg: b[1] >> 4,
+                off: LittleEndian::read_i16(&b[2..]),
+                imm: LittleEndian::read_i32(&b[4..]),
+            }
+        } else {
+            Insn {
+                code: b[0],
+                dst_reg: b[1] >> 4,
+                src_reg: b[1] & 0x0f,
+                off: BigEndian::read_i16(&b[2..]),
+                imm: BigEndian::read_i32(&b[4..]),
+            }
+        })
+    }
+
+    fn write(&self, b: &mut [u8], is_le: bool) {
+        b[0] = self.code;
+
+        if is_le {
+            b[1] = (self.src_reg << 4) | (self.dst_reg & 0x0f);
+            LittleEndian::write_i16(&mut b[2..], self.off);
+            LittleEndian::write_i32(&mut b[4..], self.imm);
+        } else {
+            b[1] = (self.dst_reg << 4) | (self.src_reg & 0x0f);
+            BigEndian::write_i16(&mut b[2..], self.off);
+            BigEndian::write_i32(&mut b[4..], self.imm);
+        }
+    }
+
+    fn class(&self) -> u8 {
+        self.code & 0x07
+    }
+
+    /// The size in bytes of a load or store.
+    fn size(&self) -> u32 {
+        match self.code & 0x18 {
+            BPF_B => 1,
+            BPF_H => 2,
+            BPF_W => 4,
+            _ => 8,
+        }
+    }
+
+    fn is_ldimm64(&self) -> bool {
+        self.code == BPF_LD | BPF_IMM | BPF_DW
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ext::RelocKind,
+        reloc::tests::{local, target},
+        TypeTable,
+    };
+
+    const STRUCT_S: u32 = 4;
+    const ENUM_E: u32 = 5;
+    const ENUM64_E64: u32 = 8;
+    const STRUCT_T: u32 = 9;
+
+    const ALU64_ADD_K: u8 = BPF_ALU64 | BPF_K;
+    const LDX_MEM_W: u8 = BPF_LDX | 0x60 | BPF_W;
+    const LDX_MEM_DW: u8 = BPF_LDX | 0x60 | BPF_DW;
+    const LD_IMM_DW: u8 = BPF_LD | BPF_IMM | BPF_DW;
+    const CALL: u8 = BPF_JMP | BPF_CALL;
+
+    fn insn(code: u8, off: i16, imm: i32) -> Insn {
+        Insn {
+            code,
+            dst_reg: 1,
+            src_reg: if code & 0x07 == BPF_LDX { 2 } else { 0 },
+            off,
+            imm,
+        }
+    }
+
+    /// The second half of a LDIMM64, holding the upper 32 bits.
+    fn ldimm64_hi(imm: i32) -> Insn {
+        Insn {
+            code: 0,
+            dst_reg: 0,
+            src_reg: 0,
+            off: 0,
+            imm,
+        }
+    }
+
+    fn poisoned() -> Insn {
+        Insn {
+            code: CALL,
+            dst_reg: 0,
+            src_reg: 0,
+            off: 0,
+            imm: POISON_IMM,
+        }
+    }
+
+    fn encode(insns: &[Insn], is_le: bool) -> Vec<u8> {
+        let mut b = vec![0; insns.len() * INSN_SIZE];
+
+        for (insn, b) in insns.iter().zip(b.chunks_mut(INSN_SIZE)) {
+            insn.write(b, is_le);
+        }
+
+        b
+    }
+
+    fn relo(insn_off: u32, type_id: u32, access_str: &str, kind: RelocKind) -> Relocation<'_> {
+        Relocation {
+            insn_off,
+            type_id,
+            access_str,
+            kind,
+        }
+    }
+
+    fn check(is_le: bool) {
+        let (local, target) = if is_le {
+            (local::<LittleEndian>(), target::<LittleEndian>())
+        } else {
+            (local::<BigEndian>(), target::<BigEndian>())
+        };
+        let local = TypeTable::parse(untrusted::Input::from(&local)).unwrap();
+        let target = TypeTable::parse(untrusted::Input::from(&target)).unwrap();
+        let r = Resolver::new(&local, &target).unwrap();
+
+        let insns = encode(
+            &[
+                // r1 += offsetof(struct s, a)
+                insn(ALU64_ADD_K, 0, 0),
+                // r1 = *(u32 *)(r2 + offsetof(struct s, a))
+                insn(LDX_MEM_W, 0, 0),
+                // r1 = *(u32 *)(r2 + offsetof(struct s, c)), the bitfield keeps its load size
+                insn(LDX_MEM_W, 4, 0),
+                // r1 = *(u32 *)(r2 + offsetof(struct t, u)), an unsigned long in the target
+                insn(LDX_MEM_W, 4, 0),
+                // r1 = *(int *)(r2 + offsetof(struct t, e)), a signed long in the target
+                insn(LDX_MEM_W, 0, 0),
+                // r1 = A ll
+                insn(LD_IMM_DW, 0, 1),
+                ldimm64_hi(0),
+                // r1 = Z ll, missing in the target
+                insn(LD_IMM_DW, 0, 3),
+                ldimm64_hi(0),
+            ],
+            is_le,
+        );
+        let relos = [
+            relo(0, STRUCT_S, "0:0", RelocKind::FieldByteOffset),
+            relo(8, STRUCT_S, "0:0", RelocKind::FieldByteOffset),
+            relo(16, STRUCT_S, "0:2", RelocKind::FieldByteOffset),
+            relo(24, STRUCT_T, "0:1", RelocKind::FieldByteOffset),
+            relo(32, STRUCT_T, "0:0", RelocKind::FieldByteOffset),
+            relo(40, ENUM64_E64, "0", RelocKind::EnumValue),
+            relo(56, ENUM_E, "2", RelocKind::EnumValue),
+        ];
+
+        let patched = patch(&r, &insns, &relos).unwrap();
+
+        assert_eq!(
+            patched.insns,
+            encode(
+                &[
+                    insn(ALU64_ADD_K, 0, 8),
+                    insn(LDX_MEM_W, 8, 0),
+                    insn(LDX_MEM_W, 0, 0),
+                    insn(LDX_MEM_DW, 8, 0),
+                    poisoned(),
+                    insn(LD_IMM_DW, 0, 2),
+                    ldimm64_hi(1),
+                    poisoned(),
+                    poisoned(),
+                ],
+                is_le
+            )
+        );
+        assert_eq!(
+            patched
+                .reports
+                .iter()
+                .map(|r| (r.insn_idx, r.poisoned))
+                .collect::<Vec<_>>(),
+            [
+                (0, false),
+                (1, false),
+                (2, false),
+                (3, false),
+                (4, true),
+                (5, false),
+                (7, true)
+            ]
+        );
+        assert!(patched.reports[4].resolution.fail_memsz_adjust);
+
+        // the instruction doesn't hold the local value
+        let err = patch(
+            &r,
+            &encode(&[insn(ALU64_ADD_K, 0, 4)], is_le),
+            &[relo(0, STRUCT_S, "0:0", RelocKind::FieldByteOffset)],
+        )
+        .unwrap_err();
+
+        assert!(matches!(
+            err.root_cause(),
+            Unexpected("immediate of the relocated ALU instruction")
+        ));
+        assert_eq!(err.location().unwrap().context, [Context::Record(0)]);
+
+        let err = patch(
+            &r,
+            &encode(&[insn(LD_IMM_DW, 0, 2), ldimm64_hi(0)], is_le),
+            &[relo(0, ENUM64_E64, "0", RelocKind::EnumValue)],
+        )
+        .unwrap_err();
+
+        assert!(matches!(
+            err.root_cause(),
+            Unexpected("immediate of the relocated LDIMM64 instruction")
+        ));
+
+        // the offset isn't the one of an instruction
+        for off in [4, 16] {
+            let err = patch(
+                &r,
+                &insns[..16],
+                &[relo(off, STRUCT_S, "0:0", RelocKind::FieldByteOffset)],
+            )
+            .unwrap_err();
+
+            assert!(matches!(
+                err.root_cause(),
+                OutOfRange("instruction offset", n) if *n == off as u64
+            ));
+        }
+    }
+
+    #[test]
+    fn patch_le() {
+        check(true);
+    }
+
+    #[test]
+    fn patch_be() {
+        check(false);
+    }
+}
@@ -0,0 +1,1661 @@
+use core::mem;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "std")] {
+        use std::borrow::Cow;
+        use std::collections::HashMap as Map;
+    } else {
+        use alloc::{borrow::Cow, collections::BTreeMap as Map, vec::Vec};
+    }
+}
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    ext::RelocKind,
+    file::Kind,
+    layout::{self, Layout},
+    table::TypeTable,
+    ty::{EnumValue, Type},
+    Error::{self, *},
+};
+
+/// The maximum number of accessors of a CO-RE access string, like libbpf's `BPF_CORE_SPEC_MAX_LEN`.
+pub const MAX_SPEC_LEN: usize = 64;
+
+/// The maximum depth of the types compared by the compatibility checks.
+const MAX_DEPTH: u32 = 32;
+
+/// The accessor of a named member, an array element or an enumerator.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Accessor<'a> {
+    /// The struct, union or enum holding the member or enumerator, or the element type of an array.
+    pub type_id: u32,
+    pub idx: u32,
+    /// The name of the member or enumerator, `None` for an array element.
+    pub name: Option<Cow<'a, str>>,
+}
+
+/// A parsed CO-RE access string, like libbpf's `bpf_core_spec`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Spec<'a> {
+    /// The type the access starts from.
+    pub root_type_id: u32,
+    /// The named members, array elements and enumerators accessed, skipping the anonymous members.
+    pub access: Vec<Accessor<'a>>,
+    /// The raw access indices, including those of the anonymous members.
+    pub raw_access: Vec<u32>,
+    /// The offset in bits of the field from the start of the root type.
+    pub bit_offset: u32,
+}
+
+/// The result of a CO-RE relocation, like libbpf's `bpf_core_relo_res`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Resolution<'a> {
+    /// The value computed from the local BTF, expected in the instruction.
+    pub orig_val: u64,
+    /// The value computed from the target BTF.
+    pub new_val: u64,
+    /// The relocation can't be satisfied, the instruction must be poisoned.
+    pub poison: bool,
+    /// The instruction must hold `orig_val` before it is patched.
+    pub validate: bool,
+    /// The size of a load or store can't be adjusted from `orig_sz` to `new_sz`.
+    pub fail_memsz_adjust: bool,
+    /// The size of the field in the local BTF, for the loads and stores.
+    pub orig_sz: u32,
+    pub orig_type_id: u32,
+    /// The size of the field in the target BTF, for the loads and stores.
+    pub new_sz: u32,
+    pub new_type_id: u32,
+    /// The access of the first matching target candidate.
+    pub target: Option<Spec<'a>>,
+}
+
+/// The value of a relocation computed against one side.
+#[derive(Clone, Copy, Debug, Default)]
+struct Calc {
+    val: u64,
+    sz: u32,
+    type_id: u32,
+    validate: bool,
+}
+
+/// A resolver of CO-RE relocations from a local BTF against a target BTF, like libbpf's `relo_core.c`.
+///
+/// The candidates of a local type are the target types of a compatible kind with the same essential name,
+/// see [`essential_name`].
+#[derive(Clone, Debug)]
+pub struct Resolver<'t, 'a> {
+    local: &'t TypeTable<'a>,
+    target: &'t TypeTable<'a>,
+    local_layout: Layout<'t, 'a>,
+    target_layout: Layout<'t, 'a>,
+    cands: Map<&'a str, Vec<(Kind, u32)>>,
+}
+
+impl<'t, 'a> Resolver<'t, 'a> {
+    /// Index the named types of the target by their essential names.
+    pub fn new(local: &'t TypeTable<'a>, target: &'t TypeTable<'a>) -> Result<Self, Error> {
+        let mut cands = Map::<&'a str, Vec<(Kind, u32)>>::new();
+
+        for type_id in 1..target.end_id() {
+            if let Some(name) = target.name(type_id)?.filter(|name| !name.is_empty()) {
+                cands
+                    .entry(essential_name(name))
+                    .or_default()
+                    .push((target.kind(type_id)?, type_id));
+            }
+        }
+
+        Ok(Resolver {
+            local,
+            target,
+            local_layout: Layout::new(local, ptr_size(local)),
+            target_layout: Layout::new(target, ptr_size(target)),
+            cands,
+        })
+    }
+
+    /// The BTF of the BPF object.
+    pub fn local(&self) -> &'t TypeTable<'a> {
+        self.local
+    }
+
+    /// The BTF of the kernel, or of the module, the BPF object is loaded into.
+    pub fn target(&self) -> &'t TypeTable<'a> {
+        self.target
+    }
+
+    /// The target types which may be relocated to the local type, in increasing order.
+    pub fn candidates(&self, local_type_id: u32) -> Result<Vec<u32>, Error> {
+        let name = self.local_name(local_type_id)?;
+        let kind = self.local.kind(local_type_id)?;
+
+        Ok(self
+            .cands
+            .get(essential_name(name))
+            .map_or(&[][..], |ids| ids.as_slice())
+            .iter()
+            .filter(|&&(k, _)| kind_core_compat(kind, k))
+            .map(|&(_, type_id)| type_id)
+            .collect())
+    }
+
+    /// Parse the access string of a relocation against the local BTF, like libbpf's `bpf_core_parse_spec`.
+    pub fn parse_spec(
+        &self,
+        type_id: u32,
+        access_str: &str,
+        kind: RelocKind,
+    ) -> Result<Spec<'a>, Error> {
+        let types = self.local;
+        let mut spec = Spec {
+            root_type_id: type_id,
+            ..Default::default()
+        };
+
+        if access_str.is_empty() || access_str.starts_with(':') {
+            return Err(Expected("CO-RE access string"));
+        }
+
+        // the type based relocations don't have a field access string
+        if kind.is_type_based() {
+            if access_str != "0" {
+                return Err(Expected("CO-RE access string `0` of a type relocation"));
+            }
+
+            return Ok(spec);
+        }
+
+        let raw = access_str
+            .strip_suffix(':')
+            .unwrap_or(access_str)
+            .split(':')
+            .map(|s| {
+                s.parse::<u32>()
+                    .map_err(|_| Expected("CO-RE access string"))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        if raw.len() > MAX_SPEC_LEN {
+            return Err(OutOfRange("CO-RE access string length", raw.len() as u64));
+        }
+
+        let mut id = skip_mods(types, type_id)?;
+        let idx = raw[0];
+
+        if kind.is_enum_value_based() {
+            let values = match types.get(id)? {
+                Type::Enum { values, .. } | Type::Enum64 { values, .. } if raw.len() == 1 => values,
+                _ => return Err(Expected("enum with a single CO-RE accessor")),
+            };
+            let v = values
+                .into_iter()
+                .nth(idx as usize)
+                .ok_or(OutOfRange("CO-RE enumerator index", idx as u64))?;
+
+            spec.access.push(Accessor {
+                type_id: id,
+                idx,
+                name: v.name,
+            });
+            spec.raw_access = raw;
+
+            return Ok(spec);
+        }
+
+        if !kind.is_field_based() {
+            return Err(OutOfRange("relocation kind", u32::from(kind) as u64));
+        }
+
+        spec.access.push(Accessor {
+            type_id: id,
+            idx,
+            name: None,
+        });
+        spec.bit_offset = elem_bit_offset(&self.local_layout, id, idx)?;
+
+        for &idx in &raw[1..] {
+            id = skip_mods(types, id)?;
+
+            match types.get(id)? {
+                Type::Struct { members, .. } | Type::Union { members, .. } => {
+                    let m = members
+                        .into_iter()
+                        .nth(idx as usize)
+                        .ok_or(OutOfRange("CO-RE member index", idx as u64))?;
+
+                    spec.bit_offset = add_bits(spec.bit_offset, m.bits_offset)?;
+
+                    // the anonymous members are only in the raw access
+                    if m.name.as_deref().is_some_and(|name| !name.is_empty()) {
+                        spec.access.push(Accessor {
+                            type_id: id,
+                            idx,
+                            name: m.name,
+                        });
+                    }
+
+                    id = m.type_id;
+                }
+                Type::Array {
+                    type_id: elem_type_id,
+                    nr_elems,
+                    ..
+                } => {
+                    let flex = is_flex_arr(types, spec.access.last(), nr_elems)?;
+
+                    id = skip_mods(types, elem_type_id)?;
+
+                    if !flex && idx >= nr_elems {
+                        return Err(OutOfRange("CO-RE array index", idx as u64));
+                    }
+
+                    spec.access.push(Accessor {
+                        type_id: id,
+                        idx,
+                        name: None,
+                    });
+                    spec.bit_offset = add_bits(
+                        spec.bit_offset,
+                        elem_bit_offset(&self.local_layout, id, idx)?,
+                    )?;
+                }
+                _ => return Err(Expected("struct, union or array in CO-RE access string")),
+            }
+        }
+
+        spec.raw_access = raw;
+
+        Ok(spec)
+    }
+
+    /// Compute the relocated value of an access to a local type, like libbpf's `bpf_core_calc_relo_insn`.
+    ///
+    /// All the matching candidates must agree on the value, and a relocation without any candidate
+    /// is resolved against a missing target, e.g. to poison the instruction.
+    pub fn resolve(
+        &self,
+        type_id: u32,
+        access_str: &str,
+        kind: RelocKind,
+    ) -> Result<Resolution<'a>, Error> {
+        if let RelocKind::Other(v) = kind {
+            return Err(OutOfRange("relocation kind", v as u64));
+        }
+
+        let local_spec = self.parse_spec(type_id, access_str, kind)?;
+
+        // the local type id is not relocated, but it may have changed while linking
+        if kind == RelocKind::TypeIdLocal {
+            return Ok(Resolution {
+                orig_val: type_id as u64,
+                new_val: type_id as u64,
+                ..Default::default()
+            });
+        }
+
+        let mut res: Option<Resolution<'a>> = None;
+
+        for cand_id in self.candidates(type_id)? {
+            let cand_spec = match self.spec_match(&local_spec, kind, cand_id)? {
+                Some(spec) => spec,
+                None => continue,
+            };
+            let cand_res = self.calc_relo(&local_spec, Some(&cand_spec), kind)?;
+
+            match res {
+                None => {
+                    res = Some(Resolution {
+                        target: Some(cand_spec),
+                        ..cand_res
+                    })
+                }
+                Some(ref res) => {
+                    let targ_spec = res.target.as_ref().ok_or(Expected("target spec"))?;
+
+                    if cand_spec.bit_offset != targ_spec.bit_offset {
+                        return Err(Unexpected(
+                            "CO-RE relocation candidates at different offsets",
+                        ));
+                    }
+                    if cand_res.poison != res.poison || cand_res.new_val != res.new_val {
+                        return Err(Unexpected(
+                            "CO-RE relocation candidates with different values",
+                        ));
+                    }
+                }
+            }
+        }
+
+        match res {
+            Some(res) => Ok(res),
+            None => self.calc_relo(&local_spec, None, kind),
+        }
+    }
+
+    fn local_name(&self, type_id: u32) -> Result<&'a str, Error> {
+        self.local
+            .name(type_id)?
+            .filter(|name| !name.is_empty())
+            .ok_or(Unexpected("CO-RE relocation of an anonymous type"))
+    }
+
+    /// Match the local access against a target candidate, like libbpf's `bpf_core_spec_match`.
+    fn spec_match(
+        &self,
+        local_spec: &Spec<'a>,
+        kind: RelocKind,
+        targ_id: u32,
+    ) -> Result<Option<Spec<'a>>, Error> {
+        let types = self.target;
+        let mut spec = Spec {
+            root_type_id: targ_id,
+            ..Default::default()
+        };
+
+        if kind.is_type_based() {
+            let matched = if kind == RelocKind::TypeMatches {
+                types_match(self.local, local_spec.root_type_id, types, targ_id)?
+            } else {
+                types_are_compat(self.local, local_spec.root_type_id, types, targ_id)?
+            };
+
+            return Ok(if matched { Some(spec) } else { None });
+        }
+
+        let local_acc = local_spec
+            .access
+            .first()
+            .ok_or(Expected("CO-RE accessor"))?;
+
+        if kind.is_enum_value_based() {
+            let targ_id = skip_mods(types, targ_id)?;
+            let values = match types.get(targ_id)? {
+                Type::Enum { values, .. } | Type::Enum64 { values, .. } => values,
+                _ => return Ok(None),
+            };
+            let local_name = essential_name(local_acc.name.as_deref().unwrap_or_default());
+
+            for (idx, v) in values.into_iter().enumerate() {
+                if essential_name(v.name.as_deref().unwrap_or_default()) == local_name {
+                    spec.access.push(Accessor {
+                        type_id: targ_id,
+                        idx: idx as u32,
+                        name: v.name,
+                    });
+                    spec.raw_access.push(idx as u32);
+
+                    return Ok(Some(spec));
+                }
+            }
+
+            return Ok(None);
+        }
+
+        let mut targ_id = targ_id;
+
+        for (i, local_acc) in local_spec.access.iter().enumerate() {
+            targ_id = skip_mods(types, targ_id)?;
+
+            if local_acc.name.is_some() {
+                match self.match_member(local_acc, targ_id, &mut spec)? {
+                    Some(next_id) => targ_id = next_id,
+                    None => return Ok(None),
+                }
+            } else {
+                // the root is already treated as an array element, the others are the elements of an array
+                if i > 0 {
+                    let (elem_type_id, nr_elems) = match types.get(targ_id)? {
+                        Type::Array {
+                            type_id, nr_elems, ..
+                        } => (type_id, nr_elems),
+                        _ => return Ok(None),
+                    };
+                    let flex = is_flex_arr(types, spec.access.last(), nr_elems)?;
+
+                    if !flex && local_acc.idx >= nr_elems {
+                        return Ok(None);
+                    }
+
+                    targ_id = skip_mods(types, elem_type_id)?;
+                }
+
+                if spec.raw_access.len() == MAX_SPEC_LEN {
+                    return Err(OutOfRange(
+                        "CO-RE access string length",
+                        MAX_SPEC_LEN as u64,
+                    ));
+                }
+
+                spec.access.push(Accessor {
+                    type_id: targ_id,
+                    idx: local_acc.idx,
+                    name: None,
+                });
+                spec.raw_access.push(local_acc.idx);
+                spec.bit_offset = add_bits(
+                    spec.bit_offset,
+                    elem_bit_offset(&self.target_layout, targ_id, local_acc.idx)?,
+                )?;
+            }
+        }
+
+        Ok(Some(spec))
+    }
+
+    /// Find the target member with the name of the local one, looking into the anonymous members,
+    /// and return the type of the member.
+    fn match_member(
+        &self,
+        local_acc: &Accessor<'a>,
+        targ_id: u32,
+        spec: &mut Spec<'a>,
+    ) -> Result<Option<u32>, Error> {
+        let local_m = match self.local.get(local_acc.type_id)? {
+            Type::Struct { members, .. } | Type::Union { members, .. } => members
+                .into_iter()
+                .nth(local_acc.idx as usize)
+                .ok_or(OutOfRange("CO-RE member index", local_acc.idx as u64))?,
+            _ => return Err(Expected("struct or union")),
+        };
+        let local_name = local_m.name.as_deref().unwrap_or_default();
+
+        let targ_id = skip_mods(self.target, targ_id)?;
+        let members = match self.target.get(targ_id)? {
+            Type::Struct { members, .. } | Type::Union { members, .. } => members,
+            _ => return Ok(None),
+        };
+
+        for (idx, m) in members.into_iter().enumerate() {
+            if spec.raw_access.len() == MAX_SPEC_LEN {
+                return Err(OutOfRange(
+                    "CO-RE access string length",
+                    MAX_SPEC_LEN as u64,
+                ));
+            }
+
+            // speculate this member will be the good one
+            let bit_offset = spec.bit_offset;
+
+            spec.bit_offset = add_bits(spec.bit_offset, m.bits_offset)?;
+            spec.raw_access.push(idx as u32);
+
+            match m.name {
+                Some(ref name) if !name.is_empty() => {
+                    if name == local_name {
+                        if fields_are_compat(self.local, local_m.type_id, self.target, m.type_id)? {
+                            spec.access.push(Accessor {
+                                type_id: targ_id,
+                                idx: idx as u32,
+                                name: m.name,
+                            });
+
+                            return Ok(Some(m.type_id));
+                        }
+
+                        // the names of the members are unique, even through the anonymous members
+                        return Ok(None);
+                    }
+                }
+                _ => {
+                    if let Some(next_id) = self.match_member(local_acc, m.type_id, spec)? {
+                        return Ok(Some(next_id));
+                    }
+                }
+            }
+
+            spec.bit_offset = bit_offset;
+            spec.raw_access.pop();
+        }
+
+        Ok(None)
+    }
+
+    /// Compute the original and the relocated values, like libbpf's `bpf_core_calc_relo`.
+    fn calc_relo(
+        &self,
+        local_spec: &Spec<'a>,
+        targ_spec: Option<&Spec<'a>>,
+        kind: RelocKind,
+    ) -> Result<Resolution<'a>, Error> {
+        let mut res = Resolution {
+            validate: true,
+            ..Default::default()
+        };
+
+        if kind.is_field_based() {
+            let orig = calc_field(self.local, &self.local_layout, Some(local_spec), kind)?
+                .ok_or(Expected("local CO-RE spec"))?;
+
+            res.orig_val = orig.val;
+            res.orig_sz = orig.sz;
+            res.orig_type_id = orig.type_id;
+            res.validate = orig.validate;
+
+            match calc_field(self.target, &self.target_layout, targ_spec, kind)? {
+                Some(new) => {
+                    res.new_val = new.val;
+                    res.new_sz = new.sz;
+                    res.new_type_id = new.type_id;
+
+                    if res.orig_sz != res.new_sz {
+                        res.fail_memsz_adjust =
+                            !self.can_adjust_memsz(res.orig_type_id, res.new_type_id)?;
+                    }
+                }
+                None => res.poison = true,
+            }
+        } else if kind.is_type_based() {
+            let orig = calc_type(&self.local_layout, Some(local_spec), kind)?;
+
+            res.orig_val = orig.val;
+            res.validate = orig.validate;
+            res.new_val = calc_type(&self.target_layout, targ_spec, kind)?.val;
+        } else if kind.is_enum_value_based() {
+            res.orig_val = calc_enum_value(self.local, Some(local_spec), kind)?
+                .ok_or(Expected("local CO-RE spec"))?;
+
+            match calc_enum_value(self.target, targ_spec, kind)? {
+                Some(val) => res.new_val = val,
+                None => res.poison = true,
+            }
+        } else {
+            return Err(OutOfRange("relocation kind", u32::from(kind) as u64));
+        }
+
+        Ok(res)
+    }
+
+    /// Returns `true` if a load or store keeps the value when its size changes,
+    /// for the pointers and the unsigned integers which are zero extended.
+    fn can_adjust_memsz(&self, orig_type_id: u32, new_type_id: u32) -> Result<bool, Error> {
+        Ok(
+            match (self.local.get(orig_type_id)?, self.target.get(new_type_id)?) {
+                (Type::Ptr { .. }, Type::Ptr { .. }) => true,
+                (
+                    Type::Int {
+                        encoding: orig_enc, ..
+                    },
+                    Type::Int {
+                        encoding: new_enc, ..
+                    },
+                ) => !orig_enc.is_signed() && !new_enc.is_signed(),
+                _ => false,
+            },
+        )
+    }
+}
+
+/// The value of a field based relocation, or `None` to poison the instruction,
+/// like libbpf's `bpf_core_calc_field_relo`.
+fn calc_field(
+    types: &TypeTable,
+    layout: &Layout,
+    spec: Option<&Spec>,
+    kind: RelocKind,
+) -> Result<Option<Calc>, Error> {
+    if kind == RelocKind::FieldExists {
+        return Ok(Some(Calc {
+            val: spec.is_some() as u64,
+            validate: true,
+            ..Default::default()
+        }));
+    }
+
+    let spec = match spec {
+        Some(spec) => spec,
+        None => return Ok(None),
+    };
+    let acc = spec.access.last().ok_or(Expected("CO-RE accessor"))?;
+
+    // an array element
+    if acc.name.is_none() {
+        let sz = field_size(layout, acc.type_id)?;
+
+        return match kind {
+            RelocKind::FieldByteOffset => Ok(Some(Calc {
+                val: (spec.bit_offset / 8) as u64,
+                sz,
+                type_id: acc.type_id,
+                validate: true,
+            })),
+            RelocKind::FieldByteSize => Ok(Some(Calc {
+                val: sz as u64,
+                validate: true,
+                ..Default::default()
+            })),
+            _ => Err(Unexpected("CO-RE relocation kind of an array element")),
+        };
+    }
+
+    let m = match types.get(acc.type_id)? {
+        Type::Struct { members, .. } | Type::Union { members, .. } => members
+            .into_iter()
+            .nth(acc.idx as usize)
+            .ok_or(OutOfRange("CO-RE member index", acc.idx as u64))?,
+        _ => return Err(Expected("struct or union")),
+    };
+    let field_type_id = skip_mods(types, m.type_id)?;
+    let mt = types.get(field_type_id)?;
+    let bit_off = spec.bit_offset;
+    let mut bit_sz = m.bitfield_size;
+    let bitfield = bit_sz > 0;
+
+    let (byte_off, byte_sz) = if bitfield {
+        let mut byte_sz = field_size(layout, field_type_id)?;
+
+        if byte_sz == 0 {
+            return Err(Unsized(types.kind(field_type_id)?));
+        }
+
+        let mut byte_off = bit_off / 8 / byte_sz * byte_sz;
+
+        // the smallest integer to load the bitfield
+        while (bit_off - byte_off * 8) as u64 + bit_sz as u64 > byte_sz as u64 * 8 {
+            if byte_sz >= 8 {
+                return Err(OutOfRange("bitfield size", bit_sz as u64));
+            }
+
+            byte_sz *= 2;
+            byte_off = bit_off / 8 / byte_sz * byte_sz;
+        }
+
+        (byte_off, byte_sz)
+    } else {
+        let byte_sz = field_size(layout, field_type_id)?;
+
+        bit_sz = byte_sz
+            .checked_mul(8)
+            .ok_or(OutOfRange("CO-RE field size", byte_sz as u64))?;
+
+        (bit_off / 8, byte_sz)
+    };
+
+    // the bitfields may be laid out differently by the compiler, only the signedness is certain
+    let mut calc = Calc {
+        validate: !bitfield,
+        ..Default::default()
+    };
+
+    match kind {
+        RelocKind::FieldByteOffset => {
+            calc.val = byte_off as u64;
+
+            if !bitfield {
+                // the size of the loads and stores of an array is the size of its elements
+                let mut elem_id = field_type_id;
+
+                while let Type::Array { type_id, .. } = types.get(elem_id)? {
+                    elem_id = skip_mods(types, type_id)?;
+                }
+
+                calc.sz = field_size(layout, elem_id)?;
+                calc.type_id = field_type_id;
+            }
+        }
+        RelocKind::FieldByteSize => calc.val = byte_sz as u64,
+        RelocKind::FieldSigned => {
+            calc.val = match mt {
+                Type::Enum { signed, .. } | Type::Enum64 { signed, .. } => signed,
+                Type::Int { encoding, .. } => encoding.is_signed(),
+                _ => false,
+            } as u64;
+            calc.validate = true;
+        }
+        // the shifts wrap around like the unsigned arithmetic of libbpf, e.g. for a field of more than 8 bytes
+        RelocKind::FieldLShiftU64 => {
+            calc.val = if types.is_le() {
+                64u32.wrapping_sub((bit_off - byte_off * 8).wrapping_add(bit_sz))
+            } else {
+                8u32.wrapping_sub(byte_sz)
+                    .wrapping_mul(8)
+                    .wrapping_add(bit_off - byte_off * 8)
+            } as u64;
+        }
+        RelocKind::FieldRShiftU64 => {
+            calc.val = 64u32.wrapping_sub(bit_sz) as u64;
+            calc.validate = true;
+        }
+        _ => return Err(OutOfRange("relocation kind", u32::from(kind) as u64)),
+    }
+
+    Ok(Some(calc))
+}
+
+/// The value of a type based relocation, zero for a missing type, like libbpf's `bpf_core_calc_type_relo`.
+fn calc_type(layout: &Layout, spec: Option<&Spec>, kind: RelocKind) -> Result<Calc, Error> {
+    let mut calc = Calc {
+        validate: true,
+        ..Default::default()
+    };
+
+    let spec = match spec {
+        Some(spec) => spec,
+        None => return Ok(calc),
+    };
+
+    match kind {
+        RelocKind::TypeIdTarget => {
+            // the type id in the instruction may change while linking
+            calc.val = spec.root_type_id as u64;
+            calc.validate = false;
+        }
+        RelocKind::TypeExists | RelocKind::TypeMatches => calc.val = 1,
+        RelocKind::TypeSize => calc.val = layout.resolve_size(spec.root_type_id)? as u64,
+        _ => return Err(OutOfRange("relocation kind", u32::from(kind) as u64)),
+    }
+
+    Ok(calc)
+}
+
+/// The value of an enum based relocation, or `None` to poison the instruction,
+/// like libbpf's `bpf_core_calc_enumval_relo`.
+fn calc_enum_value(
+    types: &TypeTable,
+    spec: Option<&Spec>,
+    kind: RelocKind,
+) -> Result<Option<u64>, Error> {
+    match kind {
+        RelocKind::EnumValueExists => Ok(Some(spec.is_some() as u64)),
+        RelocKind::EnumValue => {
+            let acc = match spec.and_then(|spec| spec.access.first()) {
+                Some(acc) => acc,
+                None => return Ok(None),
+            };
+            let (values, is_64) = match types.get(acc.type_id)? {
+                Type::Enum { values, .. } => (values, false),
+                Type::Enum64 { values, .. } => (values, true),
+                _ => return Err(Expected("enum")),
+            };
+            let v = values
+                .get(acc.idx as usize)
+                .ok_or(OutOfRange("CO-RE enumerator index", acc.idx as u64))?;
+
+            Ok(Some(match (is_64, v.val) {
+                (true, val) => val.as_u64(),
+                // the 32 bits values are sign extended, like `btf_enum.val`
+                (false, EnumValue::Signed(n)) => n as u64,
+                (false, EnumValue::Unsigned(n)) => n as u32 as i32 as i64 as u64,
+            }))
+        }
+        _ => Err(OutOfRange("relocation kind", u32::from(kind) as u64)),
+    }
+}
+
+/// The name of a type, member or enumerator without its `___flavor` suffix,
+/// e.g. `task_struct` for `task_struct___old`.
+pub fn essential_name(name: &str) -> &str {
+    let b = name.as_bytes();
+
+    for i in (0..b.len().saturating_sub(4)).rev() {
+        if b[i] != b'_' && &b[i + 1..i + 4] == b"___" && b[i + 4] != b'_' {
+            return &name[..=i];
+        }
+    }
+
+    name
+}
+
+/// Returns `true` if a local field of the type can be relocated to a target field of the type,
+/// like libbpf's `bpf_core_fields_are_compat`.
+///
+/// The typedefs and modifiers are skipped. Any struct or union is compatible with any other,
+/// the pointers, floats and integers of any size are compatible, the enums and forward declarations
+/// must have the same essential name unless one of them is anonymous, and the arrays must have
+/// compatible elements.
+pub fn fields_are_compat(
+    local: &TypeTable,
+    local_id: u32,
+    target: &TypeTable,
+    targ_id: u32,
+) -> Result<bool, Error> {
+    let (mut local_id, mut targ_id) = (local_id, targ_id);
+
+    for _ in 0..MAX_DEPTH {
+        local_id = skip_mods(local, local_id)?;
+        targ_id = skip_mods(target, targ_id)?;
+
+        let (local_kind, targ_kind) = (local.kind(local_id)?, target.kind(targ_id)?);
+
+        if local_kind.is_composite() && targ_kind.is_composite() {
+            return Ok(true);
+        }
+        if !kind_core_compat(local_kind, targ_kind) {
+            return Ok(false);
+        }
+
+        match (local.get(local_id)?, target.get(targ_id)?) {
+            (Type::Ptr { .. }, _) | (Type::Float { .. }, _) => return Ok(true),
+            (Type::Fwd { .. }, _) | (Type::Enum { .. }, _) | (Type::Enum64 { .. }, _) => {
+                // one of them is anonymous, or both have the same essential name
+                let local_name = local.name(local_id)?.unwrap_or_default();
+                let targ_name = target.name(targ_id)?.unwrap_or_default();
+
+                return Ok(local_name.is_empty()
+                    || targ_name.is_empty()
+                    || essential_name(local_name) == essential_name(targ_name));
+            }
+            (
+                Type::Int {
+                    bits_offset: local_off,
+                    ..
+                },
+                Type::Int {
+                    bits_offset: targ_off,
+                    ..
+                },
+            ) => {
+                // the deprecated bitfield-like integers are rejected
+                return Ok(local_off == 0 && targ_off == 0);
+            }
+            (Type::Array { type_id: l, .. }, Type::Array { type_id: t, .. }) => {
+                local_id = l;
+                targ_id = t;
+            }
+            _ => return Ok(false),
+        }
+    }
+
+    Err(Cycle(local_id))
+}
+
+/// Returns `true` if the local type can be relocated to the target one, like libbpf's `bpf_core_types_are_compat`.
+///
+/// The names of the types aren't compared, the candidates are usually found by their essential names.
+/// The typedefs and modifiers are skipped. The structs, unions, enums and forward declarations of the
+/// same kind are compatible, whatever their members, the pointers and arrays must have compatible
+/// element types, and the function prototypes must have compatible parameters and return types.
+pub fn types_are_compat(
+    local: &TypeTable,
+    local_id: u32,
+    target: &TypeTable,
+    targ_id: u32,
+) -> Result<bool, Error> {
+    // the names were matched by the caller
+    if !kind_core_compat(local.kind(local_id)?, target.kind(targ_id)?) {
+        return Ok(false);
+    }
+
+    are_compat(local, local_id, target, targ_id, MAX_DEPTH)
+}
+
+fn are_compat(
+    local: &TypeTable,
+    local_id: u32,
+    target: &TypeTable,
+    targ_id: u32,
+    level: u32,
+) -> Result<bool, Error> {
+    let (mut local_id, mut targ_id) = (local_id, targ_id);
+
+    for _ in 0..MAX_DEPTH {
+        local_id = skip_mods(local, local_id)?;
+        targ_id = skip_mods(target, targ_id)?;
+
+        if !kind_core_compat(local.kind(local_id)?, target.kind(targ_id)?) {
+            return Ok(false);
+        }
+
+        match (local.get(local_id)?, target.get(targ_id)?) {
+            (Type::Void, _)
+            | (Type::Struct { .. }, _)
+            | (Type::Union { .. }, _)
+            | (Type::Enum { .. }, _)
+            | (Type::Enum64 { .. }, _)
+            | (Type::Fwd { .. }, _) => return Ok(true),
+            (
+                Type::Int {
+                    bits_offset: local_off,
+                    ..
+                },
+                Type::Int {
+                    bits_offset: targ_off,
+                    ..
+                },
+            ) => return Ok(local_off == 0 && targ_off == 0),
+            (Type::Ptr { type_id: l }, Type::Ptr { type_id: t })
+            | (Type::Array { type_id: l, .. }, Type::Array { type_id: t, .. }) => {
+                local_id = l;
+                targ_id = t;
+            }
+            (
+                Type::FuncProto {
+                    ret_type_id: local_ret,
+                    params: local_params,
+                },
+                Type::FuncProto {
+                    ret_type_id: targ_ret,
+                    params: targ_params,
+                },
+            ) => {
+                if local_params.len() != targ_params.len() {
+                    return Ok(false);
+                }
+
+                for (l, t) in local_params.iter().zip(&targ_params) {
+                    if level == 0 {
+                        return Err(Cycle(local_id));
+                    }
+                    if !are_compat(local, l.type_id, target, t.type_id, level - 1)? {
+                        return Ok(false);
+                    }
+                }
+
+                // tail recurse for the return type
+                local_id = local_ret;
+                targ_id = targ_ret;
+            }
+            _ => return Ok(false),
+        }
+    }
+
+    Err(Cycle(local_id))
+}
+
+/// Returns `true` if the local type matches the target one, like libbpf's `bpf_core_types_match`,
+/// which is stricter than [`types_are_compat`].
+///
+/// The typedefs and modifiers are skipped and the types must have the same essential names,
+/// an anonymous type only matching an anonymous one. Every member of a local struct or union must match a member of
+/// the target with the same name, recursively, every enumerator of a local enum must have a target one
+/// with the same name, and the integers must have the same size and signedness. The arrays must have
+/// the same number of elements, and the function prototypes must have matching parameters and return
+/// types. Behind a pointer, a struct or union only has to be of the same kind as the target.
+pub fn types_match(
+    local: &TypeTable,
+    local_id: u32,
+    target: &TypeTable,
+    targ_id: u32,
+) -> Result<bool, Error> {
+    are_match(local, local_id, target, targ_id, false, MAX_DEPTH)
+}
+
+fn are_match(
+    local: &TypeTable,
+    local_id: u32,
+    target: &TypeTable,
+    targ_id: u32,
+    behind_ptr: bool,
+    level: u32,
+) -> Result<bool, Error> {
+    if level == 0 {
+        return Err(Cycle(local_id));
+    }
+
+    let (mut local_id, mut targ_id, mut behind_ptr) = (local_id, targ_id, behind_ptr);
+
+    for _ in 0..MAX_DEPTH {
+        local_id = skip_mods(local, local_id)?;
+        targ_id = skip_mods(target, targ_id)?;
+
+        // the root typedefs were name matched by the caller
+        if !names_match(local.name(local_id)?, target.name(targ_id)?) {
+            return Ok(false);
+        }
+
+        let local_t = local.get(local_id)?;
+        let targ_t = target.get(targ_id)?;
+        let (local_k, targ_k) = (local.kind(local_id)?, target.kind(targ_id)?);
+
+        match local_t {
+            Type::Void => return Ok(local_k == targ_k),
+            Type::Fwd { fwd_kind, .. } => {
+                let local_f = fwd_kind == Kind::Union;
+
+                return Ok(match targ_t {
+                    Type::Fwd { fwd_kind, .. } => local_f == (fwd_kind == Kind::Union),
+                    // the kind of the forward declaration is the kind of the target
+                    Type::Struct { .. } if behind_ptr => !local_f,
+                    Type::Union { .. } if behind_ptr => local_f,
+                    _ => false,
+                });
+            }
+            Type::Enum { .. } | Type::Enum64 { .. } => {
+                return Ok(
+                    (targ_k.is_enum() || targ_k.is_enum64()) && enums_match(&local_t, &targ_t)?
+                );
+            }
+            Type::Struct { .. } | Type::Union { .. } => {
+                if behind_ptr {
+                    return Ok(match targ_t {
+                        Type::Fwd { fwd_kind, .. } => local_k == fwd_kind,
+                        _ => local_k == targ_k,
+                    });
+                }
+
+                return Ok(local_k == targ_k
+                    && composites_match(local, &local_t, target, &targ_t, behind_ptr, level)?);
+            }
+            Type::Int { size, encoding, .. } => {
+                return Ok(match targ_t {
+                    Type::Int {
+                        size: targ_size,
+                        encoding: targ_encoding,
+                        ..
+                    } => size == targ_size && encoding.is_signed() == targ_encoding.is_signed(),
+                    _ => false,
+                });
+            }
+            Type::Ptr { type_id } => match targ_t {
+                Type::Ptr { type_id: t } => {
+                    behind_ptr = true;
+                    local_id = type_id;
+                    targ_id = t;
+                }
+                _ => return Ok(false),
+            },
+            Type::Array {
+                type_id, nr_elems, ..
+            } => match targ_t {
+                Type::Array {
+                    type_id: t,
+                    nr_elems: n,
+                    ..
+                } if n == nr_elems => {
+                    local_id = type_id;
+                    targ_id = t;
+                }
+                _ => return Ok(false),
+            },
+            Type::FuncProto {
+                ret_type_id,
+                params,
+            } => match targ_t {
+                Type::FuncProto {
+                    ret_type_id: targ_ret,
+                    params: targ_params,
+                } if params.len() == targ_params.len() => {
+                    for (l, t) in params.iter().zip(&targ_params) {
+                        if !are_match(local, l.type_id, target, t.type_id, behind_ptr, level - 1)? {
+                            return Ok(false);
+                        }
+                    }
+
+                    // tail recurse for the return type
+                    local_id = ret_type_id;
+                    targ_id = targ_ret;
+                }
+                _ => return Ok(false),
+            },
+            _ => return Ok(false),
+        }
+    }
+
+    Err(Cycle(local_id))
+}
+
+/// Every local enumerator must have a target one with the same essential name.
+fn enums_match(local_t: &Type, targ_t: &Type) -> Result<bool, Error> {
+    let (local_size, local_values, targ_size, targ_values) = match (local_t, targ_t) {
+        (
+            Type::Enum { size, values, .. } | Type::Enum64 { size, values, .. },
+            Type::Enum {
+                size: targ_size,
+                values: targ_values,
+                ..
+            }
+            | Type::Enum64 {
+                size: targ_size,
+                values: targ_values,
+                ..
+            },
+        ) => (size, values, targ_size, targ_values),
+        _ => return Err(Expected("enum")),
+    };
+
+    if local_size != targ_size || local_values.len() > targ_values.len() {
+        return Ok(false);
+    }
+
+    Ok(local_values.iter().all(|l| {
+        targ_values
+            .iter()
+            .any(|t| names_match(l.name.as_deref(), t.name.as_deref()))
+    }))
+}
+
+/// Every local member must have a target one with a matching name and type.
+fn composites_match(
+    local: &TypeTable,
+    local_t: &Type,
+    target: &TypeTable,
+    targ_t: &Type,
+    behind_ptr: bool,
+    level: u32,
+) -> Result<bool, Error> {
+    let (local_members, targ_members) = match (local_t, targ_t) {
+        (
+            Type::Struct { members, .. } | Type::Union { members, .. },
+            Type::Struct {
+                members: targ_members,
+                ..
+            }
+            | Type::Union {
+                members: targ_members,
+                ..
+            },
+        ) => (members, targ_members),
+        _ => return Err(Expected("struct or union")),
+    };
+
+    if local_members.len() > targ_members.len() {
+        return Ok(false);
+    }
+
+    for l in local_members {
+        let mut matched = false;
+
+        for t in targ_members {
+            if names_match(l.name.as_deref(), t.name.as_deref())
+                && are_match(local, l.type_id, target, t.type_id, behind_ptr, level - 1)?
+            {
+                matched = true;
+                break;
+            }
+        }
+
+        if !matched {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// An anonymous target name only matches an anonymous local name, like libbpf's `bpf_core_names_match`,
+/// the others must have the same essential name.
+fn names_match(local: Option<&str>, target: Option<&str>) -> bool {
+    let (local, target) = (local.unwrap_or_default(), target.unwrap_or_default());
+
+    if target.is_empty() {
+        local.is_empty()
+    } else {
+        essential_name(local) == essential_name(target)
+    }
+}
+
+/// The enums and enum64 are compatible, the other kinds must be the same.
+fn kind_core_compat(local: Kind, target: Kind) -> bool {
+    local == target
+        || ((local.is_enum() || local.is_enum64()) && (target.is_enum() || target.is_enum64()))
+}
+
+/// Follow the typedefs and modifiers, like libbpf's `skip_mods_and_typedefs`.
+fn skip_mods(types: &TypeTable, type_id: u32) -> Result<u32, Error> {
+    let mut id = type_id;
+
+    for _ in 0..=types.end_id() {
+        match types.kind(id)? {
+            Kind::Typedef | Kind::Volatile | Kind::Const | Kind::Restrict | Kind::TypeTag => {
+                id = types.raw(id)?.type_id()
+            }
+            _ => return Ok(id),
+        }
+    }
+
+    Err(Cycle(type_id))
+}
+
+/// A flexible array is the last member of a struct, without any element.
+fn is_flex_arr(types: &TypeTable, acc: Option<&Accessor>, nr_elems: u32) -> Result<bool, Error> {
+    match acc {
+        Some(acc) if acc.name.is_some() && nr_elems == 0 => match types.get(acc.type_id)? {
+            Type::Struct { members, .. } | Type::Union { members, .. } => {
+                Ok(acc.idx as usize + 1 == members.len())
+            }
+            _ => Ok(false),
+        },
+        _ => Ok(false),
+    }
+}
+
+/// The offset in bits of the `idx`-th element of an array of the type.
+fn elem_bit_offset(layout: &Layout, type_id: u32, idx: u32) -> Result<u32, Error> {
+    (layout.resolve_size(type_id)? as u64)
+        .checked_mul(idx as u64 * 8)
+        .and_then(|bits| u32::try_from(bits).ok())
+        .ok_or(OutOfRange("CO-RE access index", idx as u64))
+}
+
+/// The size of a field, which must fit in 32 bits like in libbpf.
+fn field_size(layout: &Layout, type_id: u32) -> Result<u32, Error> {
+    let size = layout.resolve_size(type_id)?;
+
+    u32::try_from(size).map_err(|_| OutOfRange("CO-RE field size", size as u64))
+}
+
+fn add_bits(bit_offset: u32, bits: u32) -> Result<u32, Error> {
+    bit_offset
+        .checked_add(bits)
+        .ok_or(OutOfRange("CO-RE bit offset", bits as u64))
+}
+
+fn ptr_size(types: &TypeTable) -> usize {
+    layout::guess_ptr_size(types).unwrap_or(mem::size_of::<usize>())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+    use super::*;
+    use crate::{
+        file::IntEncoding,
+        ty::{Enum, Member, Param},
+        writer,
+    };
+
+    fn int(name: &'static str, size: usize, encoding: IntEncoding) -> Type<'static> {
+        Type::Int {
+            name: name.into(),
+            size,
+            bits_offset: 0,
+            nr_bits: size * 8,
+            encoding,
+        }
+    }
+
+    fn member(
+        name: &'static str,
+        type_id: u32,
+        bits_offset: u32,
+        bitfield_size: u32,
+    ) -> Member<'static> {
+        Member {
+            name: Some(name.into()),
+            type_id,
+            bits_offset,
+            bitfield_size,
+        }
+    }
+
+    fn enumeration(values: &[(&'static str, u64)]) -> Type<'static> {
+        Type::Enum {
+            name: Some("e".into()),
+            size: 4,
+            signed: false,
+            values: values
+                .iter()
+                .map(|&(name, val)| Enum {
+                    name: Some(name.into()),
+                    val: EnumValue::Unsigned(val),
+                })
+                .collect(),
+        }
+    }
+
+    /// The types shared by both sides: `int`, `unsigned int` and `int[4]`.
+    fn common() -> Vec<Type<'static>> {
+        vec![
+            int("int", 4, IntEncoding::SIGNED),
+            int("unsigned int", 4, IntEncoding::empty()),
+            Type::Array {
+                type_id: 1,
+                index_type_id: 1,
+                nr_elems: 4,
+            },
+        ]
+    }
+
+    /// `long`, `unsigned long` and `enum e64`, after the types of a side.
+    fn extra(types: &mut Vec<Type<'static>>, e64: u64) {
+        types.push(int("long", 8, IntEncoding::SIGNED));
+        types.push(int("unsigned long", 8, IntEncoding::empty()));
+        types.push(Type::Enum64 {
+            name: Some("e64".into()),
+            size: 8,
+            signed: false,
+            values: vec![Enum {
+                name: Some("A".into()),
+                val: EnumValue::Unsigned(e64),
+            }],
+        });
+    }
+
+    /// struct s { int a; int b:3; unsigned int c:5; int d[4]; int x; };
+    /// enum e { X = 1, Y = 2, Z = 3 };
+    /// enum e64 { A = 1 };
+    /// struct t { int e; unsigned int u; };
+    pub(crate) fn local<O: ByteOrder>() -> Vec<u8> {
+        let mut types = common();
+
+        types.push(Type::Struct {
+            name: Some("s".into()),
+            size: 28,
+            members: vec![
+                member("a", 1, 0, 0),
+                member("b", 1, 32, 3),
+                member("c", 2, 35, 5),
+                member("d", 3, 64, 0),
+                member("x", 1, 192, 0),
+            ],
+        });
+        types.push(enumeration(&[("X", 1), ("Y", 2), ("Z", 3)]));
+        extra(&mut types, 1);
+        types.push(Type::Struct {
+            name: Some("t".into()),
+            size: 8,
+            members: vec![member("e", 1, 0, 0), member("u", 2, 32, 0)],
+        });
+
+        writer::write::<O>(&types).unwrap()
+    }
+
+    /// struct s { int pre:30; unsigned int c:5; int a; int b:3 /* at bit 100 */; int d[4]; };
+    /// enum e { Y = 20, X = 10 };
+    /// enum e64 { A = 0x1_0000_0002 };
+    /// struct t { long e; unsigned long u; };
+    pub(crate) fn target<O: ByteOrder>() -> Vec<u8> {
+        let mut types = common();
+
+        types.push(Type::Struct {
+            name: Some("s".into()),
+            size: 32,
+            members: vec![
+                member("pre", 1, 0, 30),
+                member("c", 2, 30, 5),
+                member("a", 1, 64, 0),
+                member("b", 1, 100, 3),
+                member("d", 3, 128, 0),
+            ],
+        });
+        types.push(enumeration(&[("Y", 20), ("X", 10)]));
+        extra(&mut types, 0x1_0000_0002);
+        types.push(Type::Struct {
+            name: Some("t".into()),
+            size: 16,
+            members: vec![member("e", 6, 0, 0), member("u", 7, 64, 0)],
+        });
+
+        writer::write::<O>(&types).unwrap()
+    }
+
+    fn check(resolver: &Resolver, type_id: u32, access: &str, kind: RelocKind, vals: (u64, u64)) {
+        let res = resolver.resolve(type_id, access, kind).unwrap();
+
+        assert!(!res.poison, "{} {:?}", access, kind);
+        assert_eq!((res.orig_val, res.new_val), vals, "{} {:?}", access, kind);
+    }
+
+    fn poisoned(resolver: &Resolver, type_id: u32, access: &str, kind: RelocKind) -> bool {
+        resolver.resolve(type_id, access, kind).unwrap().poison
+    }
+
+    #[test]
+    fn resolve_le() {
+        let (local, target) = (local::<LittleEndian>(), target::<LittleEndian>());
+        let local = TypeTable::parse(untrusted::Input::from(&local)).unwrap();
+        let target = TypeTable::parse(untrusted::Input::from(&target)).unwrap();
+        let r = Resolver::new(&local, &target).unwrap();
+
+        use RelocKind::*;
+
+        // a
+        check(&r, 4, "0:0", FieldByteOffset, (0, 8));
+        check(&r, 4, "0:0", FieldByteSize, (4, 4));
+        check(&r, 4, "0:0", FieldSigned, (1, 1));
+        check(&r, 4, "0:0", FieldLShiftU64, (32, 32));
+        check(&r, 4, "0:0", FieldRShiftU64, (32, 32));
+        check(&r, 4, "0:0", FieldExists, (1, 1));
+
+        // b, loaded from the int holding it
+        check(&r, 4, "0:1", FieldByteOffset, (4, 12));
+        check(&r, 4, "0:1", FieldByteSize, (4, 4));
+        check(&r, 4, "0:1", FieldLShiftU64, (61, 57));
+        check(&r, 4, "0:1", FieldRShiftU64, (61, 61));
+
+        // c, straddling two ints in the target, loaded as a u64
+        check(&r, 4, "0:2", FieldByteOffset, (4, 0));
+        check(&r, 4, "0:2", FieldByteSize, (4, 8));
+        check(&r, 4, "0:2", FieldSigned, (0, 0));
+        check(&r, 4, "0:2", FieldLShiftU64, (56, 29));
+        check(&r, 4, "0:2", FieldRShiftU64, (59, 59));
+
+        // d and d[2]
+        check(&r, 4, "0:3", FieldByteOffset, (8, 16));
+        check(&r, 4, "0:3", FieldByteSize, (16, 16));
+        check(&r, 4, "0:3:2", FieldByteOffset, (16, 24));
+        check(&r, 4, "0:3:2", FieldByteSize, (4, 4));
+
+        // x, missing in the target
+        check(&r, 4, "0:4", FieldExists, (1, 0));
+        assert!(poisoned(&r, 4, "0:4", FieldByteOffset));
+
+        check(&r, 4, "0", TypeSize, (28, 32));
+        check(&r, 4, "0", TypeExists, (1, 1));
+
+        check(&r, 5, "0", EnumValue, (1, 10));
+        check(&r, 5, "1", EnumValue, (2, 20));
+        check(&r, 5, "2", EnumValueExists, (1, 0));
+        assert!(poisoned(&r, 5, "2", EnumValue));
+    }
+
+    #[test]
+    fn resolve_be() {
+        let (local, target) = (local::<BigEndian>(), target::<BigEndian>());
+        let local = TypeTable::parse(untrusted::Input::from(&local)).unwrap();
+        let target = TypeTable::parse(untrusted::Input::from(&target)).unwrap();
+        let r = Resolver::new(&local, &target).unwrap();
+
+        use RelocKind::*;
+
+        check(&r, 4, "0:0", FieldLShiftU64, (32, 32));
+        check(&r, 4, "0:1", FieldLShiftU64, (32, 36));
+        check(&r, 4, "0:2", FieldLShiftU64, (35, 30));
+        // (8 - 16) * 8 wraps around in 32 bits
+        check(&r, 4, "0:3", FieldLShiftU64, (0xffff_ffc0, 0xffff_ffc0));
+        check(&r, 4, "0:3", FieldRShiftU64, (0xffff_ffc0, 0xffff_ffc0));
+    }
+
+    #[test]
+    fn field_too_large() {
+        let mut types = common();
+
+        // int[0x0800_0000], 512 MiB
+        types.push(Type::Array {
+            type_id: 1,
+            index_type_id: 1,
+            nr_elems: 0x0800_0000,
+        });
+        types.push(Type::Struct {
+            name: Some("s".into()),
+            size: 0x2000_0000,
+            members: vec![member("big", 4, 0, 0)],
+        });
+
+        let b = writer::write::<LittleEndian>(&types).unwrap();
+        let types = TypeTable::parse(untrusted::Input::from(&b)).unwrap();
+        let r = Resolver::new(&types, &types).unwrap();
+
+        assert!(matches!(
+            r.resolve(5, "0:0", RelocKind::FieldByteSize),
+            Err(OutOfRange("CO-RE field size", 0x2000_0000))
+        ));
+    }
+
+    fn table(types: &[Type]) -> Vec<u8> {
+        writer::write::<LittleEndian>(types).unwrap()
+    }
+
+    fn matches(local: &[u8], local_id: u32, target: &[u8], targ_id: u32) -> bool {
+        let local = TypeTable::parse(untrusted::Input::from(local)).unwrap();
+        let target = TypeTable::parse(untrusted::Input::from(target)).unwrap();
+
+        types_match(&local, local_id, &target, targ_id).unwrap()
+    }
+
+    #[test]
+    fn anonymous_target_matches_anonymous_local() {
+        // typedef struct foo { int a; } foo_t;
+        let named = |name: Option<&'static str>| {
+            table(&[
+                int("int", 4, IntEncoding::SIGNED),
+                Type::Struct {
+                    name: name.map(Into::into),
+                    size: 4,
+                    members: vec![member("a", 1, 0, 0)],
+                },
+                Type::Typedef {
+                    name: "foo_t".into(),
+                    type_id: 2,
+                },
+            ])
+        };
+        let (foo, anon) = (named(Some("foo")), named(None));
+
+        assert!(matches(&foo, 3, &foo, 3));
+        assert!(matches(&anon, 3, &anon, 3));
+        assert!(!matches(&foo, 3, &anon, 3));
+        assert!(!matches(&anon, 3, &foo, 3));
+
+        // struct s { struct { int a; } inner; };
+        let outer = |name: Option<&'static str>| {
+            table(&[
+                int("int", 4, IntEncoding::SIGNED),
+                Type::Struct {
+                    name: None,
+                    size: 4,
+                    members: vec![member("a", 1, 0, 0)],
+                },
+                Type::Struct {
+                    name: Some("s".into()),
+                    size: 4,
+                    members: vec![Member {
+                        name: name.map(Into::into),
+                        type_id: 2,
+                        bits_offset: 0,
+                        bitfield_size: 0,
+                    }],
+                },
+            ])
+        };
+        let (inner, embedded) = (outer(Some("inner")), outer(None));
+
+        assert!(matches(&inner, 3, &inner, 3));
+        assert!(matches(&embedded, 3, &embedded, 3));
+        assert!(!matches(&inner, 3, &embedded, 3));
+        assert!(!matches(&embedded, 3, &inner, 3));
+    }
+
+    #[test]
+    fn predicates() {
+        let enum_e = |name: &'static str, size, enum64| {
+            let values = vec![Enum {
+                name: Some("A".into()),
+                val: EnumValue::Unsigned(1),
+            }];
+
+            if enum64 {
+                Type::Enum64 {
+                    name: Some(name.into()),
+                    size,
+                    signed: false,
+                    values,
+                }
+            } else {
+                Type::Enum {
+                    name: Some(name.into()),
+                    size,
+                    signed: false,
+                    values,
+                }
+            }
+        };
+        let strukt = |name: &'static str| Type::Struct {
+            name: Some(name.into()),
+            size: 4,
+            members: vec![member("a", 1, 0, 0)],
+        };
+        let array = |nr_elems| Type::Array {
+            type_id: 1,
+            index_type_id: 1,
+            nr_elems,
+        };
+        let proto = |nr_params| Type::FuncProto {
+            ret_type_id: 1,
+            params: vec![
+                Param {
+                    name: None,
+                    type_id: 1
+                };
+                nr_params
+            ],
+        };
+
+        let b = table(&[
+            int("int", 4, IntEncoding::SIGNED),
+            int("unsigned int", 4, IntEncoding::empty()),
+            int("long", 8, IntEncoding::SIGNED),
+            strukt("foo"),
+            Type::Fwd {
+                name: "foo".into(),
+                fwd_kind: Kind::Struct,
+            },
+            Type::Ptr { type_id: 4 },
+            Type::Ptr { type_id: 5 },
+            enum_e("e", 8, false),
+            enum_e("e", 8, true),
+            enum_e("e", 4, false),
+            array(4),
+            array(8),
+            proto(2),
+            proto(1),
+            proto(2),
+            strukt("foo___v2"),
+            strukt("bar"),
+            enum_e("e___v2", 4, false),
+            enum_e("f", 4, false),
+        ]);
+        let types = TypeTable::parse(untrusted::Input::from(&b)).unwrap();
+
+        let matches = |l, t| types_match(&types, l, &types, t).unwrap();
+        let compat = |l, t| types_are_compat(&types, l, &types, t).unwrap();
+        let fields_compat = |l, t| fields_are_compat(&types, l, &types, t).unwrap();
+
+        // struct foo vs a forward declaration, only behind a pointer
+        assert!(matches(6, 7));
+        assert!(matches(7, 6));
+        assert!(!matches(4, 5));
+        assert!(!compat(4, 5));
+        assert!(!compat(6, 7));
+
+        // enum vs enum64, which must have the same size to match
+        assert!(compat(8, 9));
+        assert!(matches(8, 9));
+        assert!(compat(10, 9));
+        assert!(!matches(10, 9));
+        assert!(fields_compat(10, 9));
+
+        // int[4] vs int[8]
+        assert!(!matches(11, 12));
+        assert!(compat(11, 12));
+        assert!(fields_compat(11, 12));
+
+        // int (*)(int, int) vs int (*)(int)
+        assert!(!compat(13, 14));
+        assert!(!matches(13, 14));
+        assert!(compat(13, 15));
+        assert!(matches(13, 15));
+
+        // int vs unsigned int and long
+        assert!(matches(1, 1));
+        assert!(!matches(1, 2));
+        assert!(!matches(1, 3));
+        assert!(compat(1, 2));
+        assert!(compat(1, 3));
+        assert!(fields_compat(1, 3));
+
+        // the flavors are ignored
+        assert!(matches(16, 4));
+        assert!(matches(4, 16));
+        assert!(!matches(17, 4));
+        assert!(fields_compat(18, 10));
+        assert!(!fields_compat(19, 10));
+    }
+}
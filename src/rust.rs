@@ -20,11 +20,11 @@ use crate::{
 };
 
 trait EscapeKeyword {
-    fn escape_keyword(&self) -> Cow<str>;
+    fn escape_keyword(&self) -> Cow<'_, str>;
 }
 
 impl EscapeKeyword for str {
-    fn escape_keyword(&self) -> Cow<str> {
+    fn escape_keyword(&self) -> Cow<'_, str> {
         if self.is_keyword() {
             format!("_{}", self).into()
         } else {
@@ -47,11 +47,11 @@ impl<'a> ToTokens for TypeFmt<'a> {
         tokens.append_all(match *ty {
             Type::Void => quote! { ::libc::c_void },
             Type::Int {
-                name,
+                ref name,
                 size,
                 bits_offset,
-                nr_bits,
                 encoding,
+                ..
             } => {
                 if encoding.is_bool() {
                     quote! { bool }
@@ -118,7 +118,10 @@ impl<'a> ToTokens for TypeFmt<'a> {
 
                 quote! { [#t; #n] }
             }
-            Type::Struct { name, .. } | Type::Union { name, .. } | Type::Enum { name, .. }
+            Type::Struct { ref name, .. }
+            | Type::Union { ref name, .. }
+            | Type::Enum { ref name, .. }
+            | Type::Enum64 { ref name, .. }
                 if name.is_some() =>
             {
                 let ident = Ident::new(
@@ -126,7 +129,7 @@ impl<'a> ToTokens for TypeFmt<'a> {
                         .ns
                         .borrow()
                         .get_name(self.type_id)
-                        .or(name)
+                        .or(name.as_deref())
                         .expect("name")
                         .escape_keyword(),
                     Span::call_site(),
@@ -135,23 +138,23 @@ impl<'a> ToTokens for TypeFmt<'a> {
                 quote! { #ident }
             }
 
-            Type::Struct { name, .. } if name.is_none() => {
+            Type::Struct { ref name, .. } if name.is_none() => {
                 let ident =
                     Ident::new(&StructDecl::anon_type_name(self.type_id), Span::call_site());
 
                 quote! { #ident }
             }
-            Type::Union { name, .. } if name.is_none() => {
+            Type::Union { ref name, .. } if name.is_none() => {
                 let ident = Ident::new(&UnionDecl::anon_type_name(self.type_id), Span::call_site());
 
                 quote! { #ident }
             }
-            Type::Enum { name, .. } if name.is_none() => {
+            Type::Enum { ref name, .. } | Type::Enum64 { ref name, .. } if name.is_none() => {
                 let ident = Ident::new(&EnumDecl::anon_type_name(self.type_id), Span::call_site());
 
                 quote! { #ident }
             }
-            Type::Typedef { name, .. } | Type::Fwd { name, .. } => {
+            Type::Typedef { ref name, .. } | Type::Fwd { ref name, .. } => {
                 let ident = Ident::new(name, Span::call_site());
 
                 quote! { #ident }
@@ -191,14 +194,16 @@ impl<'a> TypeDecl<'a> {
 impl<'a> ToTokens for TypeDecl<'a> {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         tokens.append_all(match *self.ty {
-            Type::Int { name, encoding, .. } if encoding.is_bool() => {
+            Type::Int {
+                ref name, encoding, ..
+            } if encoding.is_bool() => {
                 let ident = Ident::new(&name.escape_keyword(), Span::call_site());
 
                 Some(quote! {
                     pub type #ident = bool;
                 })
             }
-            Type::Float { name, size } => {
+            Type::Float { ref name, size } => {
                 let ident = Ident::new(&name.escape_keyword(), Span::call_site());
                 let t = Ident::new(&format!("f{}", size * 8), Span::call_site());
 
@@ -207,9 +212,11 @@ impl<'a> ToTokens for TypeDecl<'a> {
                 })
             }
             Type::Struct {
-                name, ref members, ..
+                ref name,
+                ref members,
+                ..
             } => {
-                let name = name.map_or_else(
+                let name = name.as_deref().map_or_else(
                     || StructDecl::anon_type_name(self.type_id),
                     |s| s.escape_keyword(),
                 );
@@ -220,9 +227,11 @@ impl<'a> ToTokens for TypeDecl<'a> {
                 Some(quote! { #s })
             }
             Type::Union {
-                name, ref members, ..
+                ref name,
+                ref members,
+                ..
             } => {
-                let name = name.map_or_else(
+                let name = name.as_deref().map_or_else(
                     || UnionDecl::anon_type_name(self.type_id),
                     |s| s.escape_keyword(),
                 );
@@ -232,28 +241,35 @@ impl<'a> ToTokens for TypeDecl<'a> {
                 Some(quote! { #u })
             }
             Type::Enum {
-                name,
+                ref name,
+                size,
+                signed,
+                ref values,
+            }
+            | Type::Enum64 {
+                ref name,
                 size,
+                signed,
                 ref values,
             } => {
-                let name = name.map_or_else(
+                let name = name.as_deref().map_or_else(
                     || EnumDecl::anon_type_name(self.type_id),
                     |s| s.escape_keyword(),
                 );
                 let name = self.ns.borrow_mut().get_unique_name(&name, self.type_id);
-                let e = EnumDecl::new(&name, size, values);
+                let e = EnumDecl::new(&name, size, signed, values);
 
                 Some(quote! { #e })
             }
-            Type::Fwd { name, fwd_kind } => {
-                let fwd_name = name;
+            Type::Fwd { ref name, fwd_kind } => {
+                let fwd_name: &str = name;
 
                 let found = self.types.types.iter().any(|t| match t {
                     Type::Struct { name, .. } if fwd_kind == Kind::Struct => {
-                        name.unwrap_or_default() == fwd_name
+                        name.as_deref().unwrap_or_default() == fwd_name
                     }
                     Type::Union { name, .. } if fwd_kind == Kind::Union => {
-                        name.unwrap_or_default() == fwd_name
+                        name.as_deref().unwrap_or_default() == fwd_name
                     }
                     _ => false,
                 });
@@ -268,16 +284,15 @@ impl<'a> ToTokens for TypeDecl<'a> {
                     })
                 }
             }
-            Type::Typedef { name, type_id } => {
+            Type::Typedef { ref name, type_id } => {
+                let name: &str = name;
+
                 if Self::BUILDIN_TYPES.contains(&name) {
                     None
                 } else {
                     let inner = self.types.get_type(type_id).expect("typedef");
 
-                    let ignore = match inner.name() {
-                        Some(inner_name) if inner_name == name => true,
-                        _ => false,
-                    };
+                    let ignore = matches!(inner.name(), Some(inner_name) if inner_name == name);
 
                     if ignore {
                         None
@@ -291,7 +306,9 @@ impl<'a> ToTokens for TypeDecl<'a> {
                     }
                 }
             }
-            Type::Func { name, type_id, .. } => {
+            Type::Func {
+                ref name, type_id, ..
+            } => {
                 let f = FuncDecl::new(self.types, self.ns.clone(), name, type_id);
 
                 Some(quote! { #f })
@@ -348,6 +365,7 @@ impl<'a> ToTokens for StructDecl<'a> {
         let members = self.members.iter().enumerate().map(|(i, m)| {
             let name = m
                 .name
+                .as_deref()
                 .map_or_else(|| Self::anon_field_name(i), EscapeKeyword::escape_keyword);
             let ident = Ident::new(&name, Span::call_site());
             let ty = TypeFmt::new(self.types, self.ns.clone(), m.type_id);
@@ -391,6 +409,7 @@ impl<'a> ToTokens for UnionDecl<'a> {
         let members = self.members.iter().enumerate().map(|(i, m)| {
             let name = m
                 .name
+                .as_deref()
                 .map_or_else(|| Self::anon_field_name(i), EscapeKeyword::escape_keyword);
             let field = Ident::new(&name, Span::call_site());
             let t = TypeFmt::new(self.types, self.ns.clone(), m.type_id);
@@ -414,6 +433,7 @@ impl<'a> ToTokens for UnionDecl<'a> {
 struct EnumDecl<'a> {
     name: &'a str,
     size: usize,
+    signed: bool,
     values: &'a [ty::Enum<'a>],
 }
 
@@ -428,7 +448,8 @@ impl<'a> ToTokens for EnumDecl<'a> {
         let repr = if self.values.is_empty() {
             None
         } else {
-            let ty = Ident::new(&format!("u{}", self.size * 8), Span::call_site());
+            let sign = if self.signed { "i" } else { "u" };
+            let ty = Ident::new(&format!("{}{}", sign, self.size * 8), Span::call_site());
 
             Some(quote! { #[repr(#ty)] })
         };
@@ -439,15 +460,17 @@ impl<'a> ToTokens for EnumDecl<'a> {
             .iter()
             .enumerate()
             .map(|(i, v)| {
-                let name = v.name.map_or_else(
+                let name = v.name.as_deref().map_or_else(
                     || Self::anon_type_name(i as u32),
                     EscapeKeyword::escape_keyword,
                 );
                 let val_ident = Ident::new(&name, Span::call_site());
 
                 if let Some(e) = self.values.iter().take(i).find(|e| e.val == v.val) {
-                    let val =
-                        Ident::new(&e.name.expect("name").escape_keyword(), Span::call_site());
+                    let val = Ident::new(
+                        &e.name.as_deref().expect("name").escape_keyword(),
+                        Span::call_site(),
+                    );
 
                     consts.push(quote! {
                         pub const #val_ident: Self = Self::#val;
@@ -455,7 +478,10 @@ impl<'a> ToTokens for EnumDecl<'a> {
 
                     None
                 } else {
-                    let val = Literal::u64_unsuffixed(v.val);
+                    let val = match v.val {
+                        ty::EnumValue::Signed(n) => Literal::i64_unsuffixed(n),
+                        ty::EnumValue::Unsigned(n) => Literal::u64_unsuffixed(n),
+                    };
 
                     Some(quote! {
                         #val_ident = #val,
@@ -534,7 +560,7 @@ impl<'a> ToTokens for FuncProto<'a> {
         let params = self.params.iter().map(|p| {
             if p.is_variable_argument() {
                 quote! { ... }
-            } else if let Some(name) = p.name {
+            } else if let Some(ref name) = p.name {
                 let ident = Ident::new(&name.escape_keyword(), Span::call_site());
                 let t = TypeFmt::new(self.types, self.ns.clone(), p.type_id);
 
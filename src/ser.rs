@@ -0,0 +1,800 @@
+use std::fmt::Display;
+
+use serde::ser::{self, Impossible, Serialize};
+
+use crate::{
+    file::Kind,
+    table::TypeTable,
+    ty::{EnumValue, Type},
+    value::Decoder,
+    Error::{self, *},
+};
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+/// Serialize a value into a new buffer laid out as `type_id`, see [`Serializer`].
+///
+/// The buffer has the size of the type, and the fields missing from the value are zero.
+pub fn to_bytes<T>(types: &TypeTable, type_id: u32, value: &T) -> Result<Vec<u8>, Error>
+where
+    T: Serialize + ?Sized,
+{
+    let decoder = Decoder::new(types);
+    let mut buf = vec![0; decoder.layout.resolve_size(type_id)?];
+
+    value.serialize(Serializer::new(decoder, type_id, &mut buf))?;
+
+    Ok(buf)
+}
+
+/// A serializer of Rust values into raw memory laid out as a BTF type.
+///
+/// The fields of a Rust struct, or the keys of a map, are matched by name with
+/// the members of the BTF struct, including those of its anonymous structs and
+/// unions. The members without a field are left untouched. A string fills a
+/// `char` array and the name of an enumerator sets an enum.
+pub struct Serializer<'b, 't, 'a> {
+    decoder: Decoder<'t, 'a>,
+    type_id: u32,
+    buf: &'b mut [u8],
+    /// The bits of a bitfield within `buf`, which starts at the byte of its first bit.
+    bits: Option<(usize, usize)>,
+}
+
+impl<'b, 't, 'a> Serializer<'b, 't, 'a> {
+    /// Serialize into `buf`, which must be at least as large as the type.
+    pub fn new(decoder: Decoder<'t, 'a>, type_id: u32, buf: &'b mut [u8]) -> Self {
+        Serializer {
+            decoder,
+            type_id,
+            buf,
+            bits: None,
+        }
+    }
+
+    fn resolve(&self) -> Result<(u32, Type<'a>), Error> {
+        let type_id = self.decoder.layout.resolve_type(self.type_id)?;
+
+        Ok((type_id, self.decoder.types.get(type_id)?))
+    }
+
+    /// Write an integer, negative if `neg` with its two's complement in `raw`.
+    fn integer(self, neg: bool, raw: u128) -> Result<(), Error> {
+        let (type_id, ty) = self.resolve()?;
+        let (bits_offset, nr_bits, signed) = match ty {
+            Type::Int {
+                bits_offset,
+                nr_bits,
+                encoding,
+                ..
+            } => (bits_offset, nr_bits, encoding.is_signed()),
+            Type::Enum { size, signed, .. } | Type::Enum64 { size, signed, .. } => {
+                (0, size * 8, signed)
+            }
+            Type::Ptr { .. } => (0, self.decoder.layout.ptr_size() * 8, false),
+            Type::Float { .. } if self.bits.is_none() => {
+                return self.float(if neg { raw as i128 as f64 } else { raw as f64 })
+            }
+            _ => return Err(Expected("integer, enum or pointer")),
+        };
+        let (bits_offset, nr_bits, len) = match self.bits {
+            Some((shift, nr_bits)) => (shift, nr_bits, self.buf.len()),
+            None => (
+                bits_offset,
+                nr_bits,
+                self.decoder.layout.resolve_size(type_id)?,
+            ),
+        };
+
+        if nr_bits == 0 {
+            return Err(OutOfRange("bitfield size", nr_bits as u64));
+        }
+        if !fits(neg, raw, nr_bits, signed) {
+            let msg = if neg {
+                format!("integer {} out of range", raw as i128)
+            } else {
+                format!("integer {} out of range", raw)
+            };
+
+            return Err(<Error as ser::Error>::custom(msg));
+        }
+
+        let b = self.buf.get_mut(..len).ok_or(EndOfInput)?;
+
+        self.decoder.put_bits(b, bits_offset, nr_bits, raw)
+    }
+
+    fn float(self, v: f64) -> Result<(), Error> {
+        let (len, raw) = match self.resolve()?.1 {
+            Type::Float { size: 4, .. } => (4, (v as f32).to_bits() as u128),
+            Type::Float { size: 8, .. } => (8, v.to_bits() as u128),
+            _ => return Err(Expected("float")),
+        };
+        let b = self.buf.get_mut(..len).ok_or(EndOfInput)?;
+
+        self.decoder.put_bits(b, 0, len * 8, raw)
+    }
+
+    /// Copy the bytes into a char array, the rest of which is zeroed.
+    fn chars(self, b: &[u8], nul: bool) -> Result<(), Error> {
+        let (type_id, ty) = self.resolve()?;
+
+        if let Type::Array { .. } = ty {
+            let len = self.decoder.layout.resolve_size(type_id)?;
+            let buf = self.buf.get_mut(..len).ok_or(EndOfInput)?;
+
+            // leave room for the NUL of a string
+            if b.len() + nul as usize > len {
+                return Err(OutOfRange("string length", b.len() as u64));
+            }
+
+            buf[..b.len()].copy_from_slice(b);
+            buf[b.len()..].fill(0);
+
+            Ok(())
+        } else {
+            Err(Expected("char array"))
+        }
+    }
+
+    /// Set an enum to the value of the enumerator with the name.
+    fn enumerator(self, name: &str) -> Result<(), Error> {
+        let values = match self.resolve()?.1 {
+            Type::Enum { values, .. } | Type::Enum64 { values, .. } => values,
+            _ => return Err(Expected("enum")),
+        };
+
+        let v = values
+            .into_iter()
+            .find(|v| v.name.as_deref() == Some(name))
+            .ok_or_else(|| {
+                <Error as ser::Error>::custom(format!("no enumerator named `{}`", name))
+            })?
+            .val;
+
+        match v {
+            EnumValue::Signed(n) => self.integer(n < 0, n as i128 as u128),
+            EnumValue::Unsigned(n) => self.integer(false, n as u128),
+        }
+    }
+}
+
+/// Returns `true` if an integer fits in `nr_bits`, which must not be zero.
+fn fits(neg: bool, raw: u128, nr_bits: usize, signed: bool) -> bool {
+    if nr_bits >= 128 {
+        !neg || signed
+    } else if neg {
+        signed && raw as i128 >= -(1i128 << (nr_bits - 1))
+    } else if signed {
+        raw < 1 << (nr_bits - 1)
+    } else {
+        raw < 1 << nr_bits
+    }
+}
+
+impl<'b, 't, 'a> ser::Serializer for Serializer<'b, 't, 'a> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Elements<'b, 't, 'a>;
+    type SerializeTuple = Elements<'b, 't, 'a>;
+    type SerializeTupleStruct = Elements<'b, 't, 'a>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Fields<'b, 't, 'a>;
+    type SerializeStruct = Fields<'b, 't, 'a>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.integer(false, v as u128)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.serialize_i128(v as i128)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.serialize_i128(v as i128)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.serialize_i128(v as i128)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.serialize_i128(v as i128)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<(), Error> {
+        self.integer(v < 0, v as u128)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.integer(false, v as u128)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.integer(false, v as u128)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.integer(false, v as u128)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.integer(false, v as u128)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), Error> {
+        self.integer(false, v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.float(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        self.float(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.integer(false, v as u128)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        match self.resolve()?.1 {
+            Type::Enum { .. } | Type::Enum64 { .. } => self.enumerator(v),
+            _ => self.chars(v.as_bytes(), true),
+        }
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        self.chars(v, false)
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// A unit variant sets an enum to the enumerator with its name, or an integer to its index.
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        match self.resolve()?.1 {
+            Type::Enum { .. } | Type::Enum64 { .. } => self.enumerator(variant),
+            _ => self.integer(false, variant_index as u128),
+        }
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<(), Error> {
+        Err(Unexpected("enum variant with data"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Elements<'b, 't, 'a>, Error> {
+        Elements::new(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Elements<'b, 't, 'a>, Error> {
+        Elements::new(self)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Elements<'b, 't, 'a>, Error> {
+        Elements::new(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(Unexpected("enum variant with data"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Fields<'b, 't, 'a>, Error> {
+        Fields::new(self)
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Fields<'b, 't, 'a>, Error> {
+        Fields::new(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(Unexpected("enum variant with data"))
+    }
+}
+
+/// The elements of an array, in order.
+pub struct Elements<'b, 't, 'a> {
+    decoder: Decoder<'t, 'a>,
+    type_id: u32,
+    elem_size: usize,
+    buf: &'b mut [u8],
+    idx: usize,
+}
+
+impl<'b, 't, 'a> Elements<'b, 't, 'a> {
+    fn new(s: Serializer<'b, 't, 'a>) -> Result<Self, Error> {
+        match s.resolve()?.1 {
+            Type::Array {
+                type_id, nr_elems, ..
+            } => {
+                let elem_size = s.decoder.layout.resolve_size(type_id)?;
+                let len = elem_size
+                    .checked_mul(nr_elems as usize)
+                    .ok_or(OutOfRange("array size", nr_elems as u64))?;
+
+                Ok(Elements {
+                    decoder: s.decoder,
+                    type_id,
+                    elem_size,
+                    buf: s.buf.get_mut(..len).ok_or(EndOfInput)?,
+                    idx: 0,
+                })
+            }
+            _ => Err(Expected("array")),
+        }
+    }
+}
+
+impl<'b, 't, 'a> ser::SerializeSeq for Elements<'b, 't, 'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let start = self.idx * self.elem_size;
+        let buf = self
+            .buf
+            .get_mut(start..start + self.elem_size)
+            .filter(|_| self.elem_size > 0 || self.idx == 0)
+            .ok_or(OutOfRange("array index", self.idx as u64))?;
+
+        self.idx += 1;
+
+        value.serialize(Serializer::new(self.decoder, self.type_id, buf))
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'b, 't, 'a> ser::SerializeTuple for Elements<'b, 't, 'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'b, 't, 'a> ser::SerializeTupleStruct for Elements<'b, 't, 'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The members of a struct or union, or the variables of a data section, by name.
+pub struct Fields<'b, 't, 'a> {
+    decoder: Decoder<'t, 'a>,
+    type_id: u32,
+    buf: &'b mut [u8],
+    key: Option<String>,
+}
+
+impl<'b, 't, 'a> Fields<'b, 't, 'a> {
+    fn new(s: Serializer<'b, 't, 'a>) -> Result<Self, Error> {
+        let (type_id, ty) = s.resolve()?;
+        let size = match ty {
+            Type::Struct { size, .. } | Type::Union { size, .. } | Type::DataSec { size, .. } => {
+                size
+            }
+            _ => return Err(Expected("struct, union or data section")),
+        };
+
+        Ok(Fields {
+            decoder: s.decoder,
+            type_id,
+            buf: s.buf.get_mut(..size).ok_or(EndOfInput)?,
+            key: None,
+        })
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, name: &str, value: &T) -> Result<(), Error> {
+        let (type_id, bits_offset, bitfield_size) = find_member(
+            &self.decoder,
+            self.type_id,
+            name,
+            self.decoder.types.end_id(),
+        )?
+        .ok_or_else(|| <Error as ser::Error>::custom(format!("no member named `{}`", name)))?;
+
+        let s = if bitfield_size == 0 {
+            if !bits_offset.is_multiple_of(8) {
+                return Err(Malformed("member not aligned on a byte"));
+            }
+
+            Serializer::new(
+                self.decoder,
+                type_id,
+                self.buf.get_mut(bits_offset / 8..).ok_or(EndOfInput)?,
+            )
+        } else {
+            // a bitfield may start in the middle of a byte and span over the next one
+            let off = bits_offset / 8;
+            let shift = bits_offset % 8;
+            let len = (shift + bitfield_size).div_ceil(8);
+
+            Serializer {
+                decoder: self.decoder,
+                type_id,
+                buf: self.buf.get_mut(off..off + len).ok_or(EndOfInput)?,
+                bits: Some((shift, bitfield_size)),
+            }
+        };
+
+        value.serialize(s)
+    }
+}
+
+/// Find the member with the name, looking into the anonymous structs and unions up to `depth` levels deep.
+///
+/// Returns the type id, the offset in bits and the bitfield size of the member.
+fn find_member(
+    decoder: &Decoder,
+    type_id: u32,
+    name: &str,
+    depth: u32,
+) -> Result<Option<(u32, usize, usize)>, Error> {
+    let depth = depth.checked_sub(1).ok_or(Cycle(type_id))?;
+
+    match decoder.types.get(type_id)? {
+        Type::Struct { members, .. } | Type::Union { members, .. } => {
+            for m in members {
+                match m.name {
+                    Some(ref n) if n == name => {
+                        return Ok(Some((
+                            m.type_id,
+                            m.bits_offset as usize,
+                            m.bitfield_size as usize,
+                        )))
+                    }
+                    Some(_) => {}
+                    None => {
+                        let member_type_id = decoder.layout.resolve_type(m.type_id)?;
+
+                        if !matches!(
+                            decoder.types.kind(member_type_id)?,
+                            Kind::Struct | Kind::Union
+                        ) {
+                            continue;
+                        }
+
+                        if let Some((type_id, bits_offset, bitfield_size)) =
+                            find_member(decoder, member_type_id, name, depth)?
+                        {
+                            return Ok(Some((
+                                type_id,
+                                m.bits_offset as usize + bits_offset,
+                                bitfield_size,
+                            )));
+                        }
+                    }
+                }
+            }
+
+            Ok(None)
+        }
+        Type::DataSec { sections, .. } => {
+            for s in sections {
+                if decoder.types.name(s.type_id)? == Some(name) {
+                    return Ok(Some((s.type_id, s.offset as usize * 8, 0)));
+                }
+            }
+
+            Ok(None)
+        }
+        _ => Err(Expected("struct, union or data section")),
+    }
+}
+
+impl<'b, 't, 'a> ser::SerializeStruct for Fields<'b, 't, 'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'b, 't, 'a> ser::SerializeMap for Fields<'b, 't, 'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(KeySerializer)?);
+
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().ok_or(Unexpected("value before its key"))?;
+
+        self.field(&key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The keys of a map must be the names of the members.
+struct KeySerializer;
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = Error;
+
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    fn serialize_str(self, v: &str) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_char(self, v: char) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String, Error> {
+        Ok(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_none(self) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_unit(self) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Err(Expected("string key"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(Expected("string key"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::LittleEndian;
+
+    use super::*;
+    use crate::{file::IntEncoding, writer};
+
+    fn int(nr_bits: usize, encoding: IntEncoding) -> Vec<u8> {
+        writer::write::<LittleEndian>(&[Type::Int {
+            name: "int".into(),
+            size: 4,
+            bits_offset: 0,
+            nr_bits,
+            encoding,
+        }])
+        .unwrap()
+    }
+
+    #[test]
+    fn zero_bits_int() {
+        let b = int(0, IntEncoding::SIGNED);
+        let types = TypeTable::parse(untrusted::Input::from(&b)).unwrap();
+
+        assert!(matches!(
+            to_bytes(&types, 1, &1i32),
+            Err(OutOfRange("bitfield size", 0))
+        ));
+    }
+
+    #[test]
+    fn negative_out_of_range() {
+        let b = int(32, IntEncoding::empty());
+        let types = TypeTable::parse(untrusted::Input::from(&b)).unwrap();
+
+        assert_eq!(to_bytes(&types, 1, &9i32).unwrap(), [9, 0, 0, 0]);
+        assert_eq!(
+            to_bytes(&types, 1, &-9i32).unwrap_err().to_string(),
+            "integer -9 out of range"
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use core::mem;
+use core::ops::Range;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "std")] {
+        use std::borrow::Cow;
+    } else {
+        use alloc::{borrow::Cow, boxed::Box, vec::Vec};
+    }
+}
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::{
+    error::Section,
+    file::{self, Kind, ReadExt},
+    names::NameIndex,
+    ty::{self, Type},
+    Error::{self, *},
+};
+
+/// A random-access table of the types in a BTF file.
+///
+/// The table is built in one pass over the type section and only records
+/// where each type starts, the types are decoded on access.
+#[derive(Clone, Debug)]
+pub struct TypeTable<'a> {
+    is_le: bool,
+    types: untrusted::Input<'a>,
+    strs: file::Strings<'a>,
+    offsets: Cow<'a, [u32]>,
+    start_id: u32,
+    base: Option<Box<TypeTable<'a>>>,
+}
+
+impl<'a> TypeTable<'a> {
+    pub fn parse(input: untrusted::Input<'a>) -> Result<TypeTable<'a>, Error> {
+        Self::from_file(file::parse(input)?)
+    }
+
+    /// Parse a split BTF, whose type ids and string offsets continue after those of `base`.
+    pub fn parse_split(
+        input: untrusted::Input<'a>,
+        base: &'a TypeTable<'a>,
+    ) -> Result<TypeTable<'a>, Error> {
+        let f = file::parse(input)?;
+        let strs = file::Strings::split(base.strs.strs, f.strs);
+
+        Self::new(&f, strs, Some(base.view()))
+    }
+
+    pub fn from_file(f: file::File<'a>) -> Result<TypeTable<'a>, Error> {
+        Self::new(&f, f.strings(), None)
+    }
+
+    fn new(
+        f: &file::File<'a>,
+        strs: file::Strings<'a>,
+        base: Option<TypeTable<'a>>,
+    ) -> Result<TypeTable<'a>, Error> {
+        let start_id = base.as_ref().map_or(1, |b| b.end_id());
+        let offsets = if f.is_le() {
+            index::<LittleEndian>(f.types, start_id)
+        } else {
+            index::<BigEndian>(f.types, start_id)
+        }?;
+
+        Ok(Self::from_parts(
+            f.is_le(),
+            f.types,
+            strs,
+            Cow::Owned(offsets),
+            base,
+        ))
+    }
+
+    /// Assemble a table from the offsets of the type records, which must be valid for `types`.
+    pub(crate) fn from_parts(
+        is_le: bool,
+        types: untrusted::Input<'a>,
+        strs: file::Strings<'a>,
+        offsets: Cow<'a, [u32]>,
+        base: Option<TypeTable<'a>>,
+    ) -> TypeTable<'a> {
+        TypeTable {
+            is_le,
+            types,
+            strs,
+            offsets,
+            start_id: base.as_ref().map_or(1, |b| b.end_id()),
+            base: base.map(Box::new),
+        }
+    }
+
+    /// A copy of this table borrowing its offsets.
+    fn view(&'a self) -> TypeTable<'a> {
+        TypeTable {
+            is_le: self.is_le,
+            types: self.types,
+            strs: self.strs,
+            offsets: Cow::Borrowed(&self.offsets),
+            start_id: self.start_id,
+            base: self.base.as_ref().map(|b| Box::new(b.view())),
+        }
+    }
+
+    /// The offsets of the type records in the type section.
+    pub(crate) fn into_offsets(self) -> Vec<u32> {
+        self.offsets.into_owned()
+    }
+
+    pub fn is_le(&self) -> bool {
+        self.is_le
+    }
+
+    pub fn base(&self) -> Option<&TypeTable<'a>> {
+        self.base.as_deref()
+    }
+
+    pub fn strings(&self) -> &file::Strings<'a> {
+        &self.strs
+    }
+
+    /// The number of types in this table, not including the void type and the base types.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// The type id of the first type in this table, which is 1 unless this is a split BTF.
+    pub fn start_id(&self) -> u32 {
+        self.start_id
+    }
+
+    /// The type id after the last type in this table.
+    pub fn end_id(&self) -> u32 {
+        self.start_id + self.offsets.len() as u32
+    }
+
+    /// The type ids of this table.
+    pub fn type_ids(&self) -> Range<u32> {
+        self.start_id..self.end_id()
+    }
+
+    pub fn contains(&self, type_id: u32) -> bool {
+        type_id < self.end_id()
+    }
+
+    /// Decode the type with the given type id, looking into the base for split BTF.
+    pub fn get(&self, type_id: u32) -> Result<Type<'a>, Error> {
+        if type_id == 0 {
+            return Ok(Type::VOID);
+        }
+
+        match self.lookup(type_id)? {
+            (tbl, Some(input)) => {
+                let mut r = untrusted::Reader::new(input);
+
+                if tbl.is_le {
+                    ty::read_type::<LittleEndian>(&mut r, &tbl.strs)
+                } else {
+                    ty::read_type::<BigEndian>(&mut r, &tbl.strs)
+                }
+                .map_err(|err| tbl.locate(err, type_id))
+            }
+            (_, None) => Ok(Type::VOID),
+        }
+    }
+
+    /// Read the fixed part of the type record without decoding the rest of it.
+    pub fn raw(&self, type_id: u32) -> Result<file::Type, Error> {
+        match self.lookup(type_id)? {
+            (tbl, Some(input)) => input
+                .read_all(EndOfInput, |r| {
+                    let ty = if tbl.is_le {
+                        file::Type::read::<LittleEndian>(r)
+                    } else {
+                        file::Type::read::<BigEndian>(r)
+                    }?;
+
+                    r.skip_to_end();
+
+                    Ok(ty)
+                })
+                .map_err(|err| tbl.locate(err, type_id)),
+            (_, None) => Ok(file::Type {
+                name_off: 0,
+                info: file::Info(0),
+                size_or_type: 0,
+            }),
+        }
+    }
+
+    pub fn kind(&self, type_id: u32) -> Result<Kind, Error> {
+        self.raw(type_id).map(|ty| ty.kind())
+    }
+
+    pub fn name(&self, type_id: u32) -> Result<Option<&'a str>, Error> {
+        let (tbl, _) = self.lookup(type_id)?;
+
+        tbl.strs.get(self.raw(type_id)?.name_off)
+    }
+
+    /// Index the named types of this table and its base, see [`NameIndex`].
+    pub fn names(&self) -> Result<NameIndex<'a>, Error> {
+        NameIndex::new(self)
+    }
+
+    /// Iterate the types of this table, not including the void type and the base types.
+    pub fn iter(&self) -> Iter<'_, 'a> {
+        Iter {
+            table: self,
+            ids: self.type_ids(),
+        }
+    }
+
+    /// Locate an error in the record of `type_id`, which must belong to this table.
+    fn locate(&self, err: Error, type_id: u32) -> Error {
+        let start = self.offsets[(type_id - self.start_id) as usize] as usize;
+
+        err.locate(|loc| {
+            loc.type_id = Some(type_id);
+            loc.rebase(Section::Types, start);
+        })
+    }
+
+    /// Find the table owning `type_id` and the bytes of its record, `None` for the void type.
+    fn lookup(
+        &self,
+        type_id: u32,
+    ) -> Result<(&TypeTable<'a>, Option<untrusted::Input<'a>>), Error> {
+        if type_id == 0 {
+            return Ok((self, None));
+        }
+
+        if type_id < self.start_id {
+            return match self.base {
+                Some(ref base) => base.lookup(type_id),
+                None => Err(OutOfRange("type_id", type_id as u64)),
+            };
+        }
+
+        let idx = (type_id - self.start_id) as usize;
+        let start = *self
+            .offsets
+            .get(idx)
+            .ok_or(OutOfRange("type_id", type_id as u64))? as usize;
+        let end = self
+            .offsets
+            .get(idx + 1)
+            .map_or(self.types.len(), |&off| off as usize);
+
+        let b = &self.types.as_slice_less_safe()[start..end];
+
+        Ok((self, Some(untrusted::Input::from(b))))
+    }
+}
+
+impl<'t, 'a> IntoIterator for &'t TypeTable<'a> {
+    type Item = Result<(u32, Type<'a>), Error>;
+    type IntoIter = Iter<'t, 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Iter<'t, 'a> {
+    table: &'t TypeTable<'a>,
+    ids: Range<u32>,
+}
+
+impl<'t, 'a> Iterator for Iter<'t, 'a> {
+    type Item = Result<(u32, Type<'a>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ids
+            .next()
+            .map(|type_id| self.table.get(type_id).map(|ty| (type_id, ty)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<'t, 'a> ExactSizeIterator for Iter<'t, 'a> {}
+
+fn index<O: ByteOrder>(types: untrusted::Input, start_id: u32) -> Result<Vec<u32>, Error> {
+    types.read_all(EndOfInput, |r| {
+        let mut offsets = Vec::new();
+        let mut off = 0;
+
+        while !r.at_end() {
+            let size = file::Type::read::<O>(r)
+                .and_then(|ty| {
+                    let size = ty.type_size().ok_or(UnknownKind(u8::from(ty.kind())))?;
+
+                    r.skip(size - mem::size_of::<file::Type>())?;
+
+                    Ok(size)
+                })
+                .map_err(|err| {
+                    err.locate(|loc| {
+                        loc.type_id = Some(start_id + offsets.len() as u32);
+                        loc.rebase(Section::Types, off);
+                    })
+                })?;
+
+            offsets.push(off as u32);
+            off += size;
+        }
+
+        Ok(offsets)
+    })
+}
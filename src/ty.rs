@@ -1,5 +1,13 @@
-#[cfg(not(feature = "std"))]
-use alloc::vec::Vec;
+use core::iter;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "std")] {
+        use std::borrow::Cow;
+    } else {
+        use alloc::borrow::Cow;
+        use alloc::vec::Vec;
+    }
+}
 
 use byteorder::{BigEndian, ByteOrder, LittleEndian};
 use derive_more::IsVariant;
@@ -8,10 +16,14 @@ use derive_more::IsVariant;
 use serde::Serialize;
 
 use crate::{
-    file::{self, Kind, ReadExt},
+    error::{Context, Location, Section},
+    file::{self, ReadExt},
+    view,
     Error::{self, *},
 };
 
+pub use crate::view::EnumValue;
+
 #[derive(Debug, Clone, PartialEq, IsVariant)]
 #[cfg_attr(
     feature = "serde",
@@ -21,7 +33,7 @@ use crate::{
 pub enum Type<'a> {
     Void,
     Int {
-        name: &'a str,
+        name: Cow<'a, str>,
         size: usize,
         bits_offset: usize,
         nr_bits: usize,
@@ -36,26 +48,33 @@ pub enum Type<'a> {
         nr_elems: u32,
     },
     Struct {
-        name: Option<&'a str>,
+        name: Option<Cow<'a, str>>,
         size: usize,
         members: Vec<Member<'a>>,
     },
     Union {
-        name: Option<&'a str>,
+        name: Option<Cow<'a, str>>,
         size: usize,
         members: Vec<Member<'a>>,
     },
     Enum {
-        name: Option<&'a str>,
+        name: Option<Cow<'a, str>>,
         size: usize,
+        signed: bool,
+        values: Vec<Enum<'a>>,
+    },
+    Enum64 {
+        name: Option<Cow<'a, str>>,
+        size: usize,
+        signed: bool,
         values: Vec<Enum<'a>>,
     },
     Fwd {
-        name: &'a str,
+        name: Cow<'a, str>,
         fwd_kind: file::Kind,
     },
     Typedef {
-        name: &'a str,
+        name: Cow<'a, str>,
         type_id: u32,
     },
     Volatile {
@@ -68,7 +87,7 @@ pub enum Type<'a> {
         type_id: u32,
     },
     Func {
-        name: &'a str,
+        name: Cow<'a, str>,
         type_id: u32,
         linkage: file::Linkage,
     },
@@ -77,26 +96,26 @@ pub enum Type<'a> {
         params: Vec<Param<'a>>,
     },
     Variable {
-        name: &'a str,
+        name: Cow<'a, str>,
         type_id: u32,
         linkage: file::Linkage,
     },
     DataSec {
-        name: &'a str,
+        name: Cow<'a, str>,
         size: usize,
         sections: Vec<file::VarSectInfo>,
     },
     Float {
-        name: &'a str,
+        name: Cow<'a, str>,
         size: usize,
     },
     DeclTag {
-        name: &'a str,
+        name: Cow<'a, str>,
         type_id: u32,
         component_idx: i32,
     },
     TypeTag {
-        name: &'a str,
+        name: Cow<'a, str>,
         type_id: u32,
     },
 }
@@ -105,14 +124,15 @@ impl<'a> Type<'a> {
     pub const VOID: Type<'a> = Type::Void;
 
     pub fn name(&self) -> Option<&str> {
-        match *self {
+        match self {
             Type::Void => None,
             Type::Int { name, .. } => Some(name),
             Type::Ptr { .. } => None,
             Type::Array { .. } => None,
-            Type::Struct { name, .. } => name,
-            Type::Union { name, .. } => name,
-            Type::Enum { name, .. } => name,
+            Type::Struct { name, .. } => name.as_deref(),
+            Type::Union { name, .. } => name.as_deref(),
+            Type::Enum { name, .. } => name.as_deref(),
+            Type::Enum64 { name, .. } => name.as_deref(),
             Type::Fwd { name, .. } => Some(name),
             Type::Typedef { name, .. } => Some(name),
             Type::Volatile { .. } => None,
@@ -127,18 +147,165 @@ impl<'a> Type<'a> {
             Type::TypeTag { name, .. } => Some(name),
         }
     }
+
+    /// Copy the borrowed names, so the type no longer borrows from the BTF data.
+    pub fn into_owned(self) -> Type<'static> {
+        match self {
+            Type::Void => Type::Void,
+            Type::Int {
+                name,
+                size,
+                bits_offset,
+                nr_bits,
+                encoding,
+            } => Type::Int {
+                name: owned(name),
+                size,
+                bits_offset,
+                nr_bits,
+                encoding,
+            },
+            Type::Ptr { type_id } => Type::Ptr { type_id },
+            Type::Array {
+                type_id,
+                index_type_id,
+                nr_elems,
+            } => Type::Array {
+                type_id,
+                index_type_id,
+                nr_elems,
+            },
+            Type::Struct {
+                name,
+                size,
+                members,
+            } => Type::Struct {
+                name: name.map(owned),
+                size,
+                members: members.into_iter().map(Member::into_owned).collect(),
+            },
+            Type::Union {
+                name,
+                size,
+                members,
+            } => Type::Union {
+                name: name.map(owned),
+                size,
+                members: members.into_iter().map(Member::into_owned).collect(),
+            },
+            Type::Enum {
+                name,
+                size,
+                signed,
+                values,
+            } => Type::Enum {
+                name: name.map(owned),
+                size,
+                signed,
+                values: values.into_iter().map(Enum::into_owned).collect(),
+            },
+            Type::Enum64 {
+                name,
+                size,
+                signed,
+                values,
+            } => Type::Enum64 {
+                name: name.map(owned),
+                size,
+                signed,
+                values: values.into_iter().map(Enum::into_owned).collect(),
+            },
+            Type::Fwd { name, fwd_kind } => Type::Fwd {
+                name: owned(name),
+                fwd_kind,
+            },
+            Type::Typedef { name, type_id } => Type::Typedef {
+                name: owned(name),
+                type_id,
+            },
+            Type::Volatile { type_id } => Type::Volatile { type_id },
+            Type::Const { type_id } => Type::Const { type_id },
+            Type::Restrict { type_id } => Type::Restrict { type_id },
+            Type::Func {
+                name,
+                type_id,
+                linkage,
+            } => Type::Func {
+                name: owned(name),
+                type_id,
+                linkage,
+            },
+            Type::FuncProto {
+                ret_type_id,
+                params,
+            } => Type::FuncProto {
+                ret_type_id,
+                params: params.into_iter().map(Param::into_owned).collect(),
+            },
+            Type::Variable {
+                name,
+                type_id,
+                linkage,
+            } => Type::Variable {
+                name: owned(name),
+                type_id,
+                linkage,
+            },
+            Type::DataSec {
+                name,
+                size,
+                sections,
+            } => Type::DataSec {
+                name: owned(name),
+                size,
+                sections,
+            },
+            Type::Float { name, size } => Type::Float {
+                name: owned(name),
+                size,
+            },
+            Type::DeclTag {
+                name,
+                type_id,
+                component_idx,
+            } => Type::DeclTag {
+                name: owned(name),
+                type_id,
+                component_idx,
+            },
+            Type::TypeTag { name, type_id } => Type::TypeTag {
+                name: owned(name),
+                type_id,
+            },
+        }
+    }
+}
+
+fn owned(s: Cow<'_, str>) -> Cow<'static, str> {
+    Cow::Owned(s.into_owned())
 }
 
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Member<'a> {
-    pub name: Option<&'a str>,
+    pub name: Option<Cow<'a, str>>,
     pub type_id: u32,
     pub bits_offset: u32,
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "size_is_zero"))]
     pub bitfield_size: u32,
 }
 
+impl<'a> Member<'a> {
+    pub fn into_owned(self) -> Member<'static> {
+        Member {
+            name: self.name.map(owned),
+            type_id: self.type_id,
+            bits_offset: self.bits_offset,
+            bitfield_size: self.bitfield_size,
+        }
+    }
+}
+
 #[cfg(feature = "serde")]
 fn size_is_zero(n: &u32) -> bool {
     *n == 0
@@ -147,20 +314,36 @@ fn size_is_zero(n: &u32) -> bool {
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Enum<'a> {
-    pub name: Option<&'a str>,
-    pub val: u64,
+    pub name: Option<Cow<'a, str>>,
+    pub val: EnumValue,
+}
+
+impl<'a> Enum<'a> {
+    pub fn into_owned(self) -> Enum<'static> {
+        Enum {
+            name: self.name.map(owned),
+            val: self.val,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Param<'a> {
-    pub name: Option<&'a str>,
+    pub name: Option<Cow<'a, str>>,
     pub type_id: u32,
 }
 
 impl<'a> Param<'a> {
+    pub fn into_owned(self) -> Param<'static> {
+        Param {
+            name: self.name.map(owned),
+            type_id: self.type_id,
+        }
+    }
+
     pub fn has_variable_argument(params: &[Param]) -> bool {
-        params.last().map_or(false, |p| p.is_variable_argument())
+        params.last().is_some_and(|p| p.is_variable_argument())
     }
 
     pub fn is_variable_argument(&self) -> bool {
@@ -171,16 +354,66 @@ impl<'a> Param<'a> {
 pub struct Types<'a> {
     is_le: bool,
     types: untrusted::Reader<'a>,
-    strs: untrusted::Input<'a>,
+    strs: file::Strings<'a>,
+    start_id: u32,
+    next_id: u32,
+    offset: usize,
+    diagnostics: Option<Vec<Error>>,
 }
 
 impl<'a> Types<'a> {
     pub fn parse(input: untrusted::Input<'a>) -> Result<Types<'a>, Error> {
-        file::parse(input).map(|f| Types {
-            is_le: f.header.is_le(),
+        file::parse(input).map(|f| Types::new(&f, f.strings(), 1))
+    }
+
+    /// Parse a split BTF, whose type ids and string offsets continue after those of `base`.
+    pub fn parse_split(
+        input: untrusted::Input<'a>,
+        base: &file::File<'a>,
+    ) -> Result<Types<'a>, Error> {
+        let start_id = base.type_count()? + 1;
+
+        file::parse(input)
+            .map(|f| Types::new(&f, file::Strings::split(base.strs, f.strs), start_id))
+    }
+
+    pub(crate) fn new(f: &file::File<'a>, strs: file::Strings<'a>, start_id: u32) -> Types<'a> {
+        Types {
+            is_le: f.is_le(),
             types: untrusted::Reader::new(f.types),
-            strs: f.strs,
-        })
+            strs,
+            start_id,
+            next_id: start_id,
+            offset: 0,
+            diagnostics: None,
+        }
+    }
+
+    /// Recover from malformed records instead of failing, see [`read_type_lenient`].
+    ///
+    /// The iteration only stops early if the end of a record can't be found,
+    /// the problems met are kept in [`Types::diagnostics`].
+    pub fn lenient(mut self) -> Self {
+        self.diagnostics.get_or_insert_with(Vec::new);
+        self
+    }
+
+    pub fn is_lenient(&self) -> bool {
+        self.diagnostics.is_some()
+    }
+
+    /// The problems met so far in lenient mode.
+    pub fn diagnostics(&self) -> &[Error] {
+        self.diagnostics.as_deref().unwrap_or_default()
+    }
+
+    /// The type id of the first type, which is 1 unless this is a split BTF.
+    pub fn start_id(&self) -> u32 {
+        self.start_id
+    }
+
+    pub fn strings(&self) -> &file::Strings<'a> {
+        &self.strs
     }
 }
 
@@ -189,193 +422,456 @@ impl<'a> Iterator for Types<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.types.at_end() {
-            None
-        } else {
-            let ty = if self.is_le {
-                read_type::<LittleEndian>(&mut self.types, &self.strs)
-            } else {
-                read_type::<BigEndian>(&mut self.types, &self.strs)
-            };
+            return None;
+        }
+
+        let (type_id, start) = (self.next_id, self.offset);
+        let locate = |loc: &mut Location| {
+            loc.type_id = Some(type_id);
+            loc.rebase(Section::Types, start);
+        };
+
+        let is_le = self.is_le;
+        let strs = &self.strs;
+        let res = match self.diagnostics {
+            Some(ref mut diags) => {
+                let n = diags.len();
+                let res = self.types.read_partial(|r| {
+                    if is_le {
+                        read_type_lenient::<LittleEndian>(r, strs, diags)
+                    } else {
+                        read_type_lenient::<BigEndian>(r, strs, diags)
+                    }
+                });
+
+                locate_since(diags, n, locate);
+
+                match res {
+                    Err(err) => {
+                        // the end of the record is unknown, nothing more can be decoded
+                        diags.push(err.locate(locate));
+                        self.types.skip_to_end();
+
+                        return None;
+                    }
+                    res => res,
+                }
+            }
+            None => self.types.read_partial(|r| {
+                if is_le {
+                    read_type::<LittleEndian>(r, strs)
+                } else {
+                    read_type::<BigEndian>(r, strs)
+                }
+            }),
+        };
 
-            Some(ty)
+        match res {
+            Ok((record, ty)) => {
+                self.next_id += 1;
+                self.offset += record.len();
+
+                Some(Ok(ty))
+            }
+            Err(err) => {
+                // the following records can't be located once a record failed to decode
+                self.types.skip_to_end();
+
+                Some(Err(err.locate(locate)))
+            }
         }
     }
 }
 
+/// Decode a type record.
+///
+/// The location of an error is relative to the start of the record,
+/// the caller knows the type id and where the record starts.
 pub fn read_type<'a, O: ByteOrder>(
     r: &mut untrusted::Reader<'a>,
-    strs: &untrusted::Input<'a>,
+    strs: &file::Strings<'a>,
 ) -> Result<Type<'a>, Error> {
     let ty = file::Type::read::<O>(r)?;
 
-    let name = file::read_str(strs, ty.name_off)?;
+    Decoder::new(strs, None)
+        .decode_type::<O>(r, &ty)
+        .map_err(|err| {
+            err.locate(|loc| {
+                loc.kind = Some(ty.kind());
+            })
+        })
+}
 
-    Ok(match ty.kind() {
-        Kind::Unknown => Type::Void,
-        Kind::Integer => {
-            let int = file::Int::read::<O>(r)?;
+/// Decode a type record, substituting placeholders for the malformed parts.
+///
+/// - an invalid UTF-8 sequence in a name is replaced with `U+FFFD`,
+///   a missing or out of range name becomes empty, or `None` if it is optional
+/// - the list of members, params, values or entries is truncated at the end of the input
+/// - a record that can't be decoded at all becomes the void type, of kind `Unknown`
+///
+/// Each substitution pushes the error it recovers from to `diagnostics`.
+/// It only fails if the end of the record can't be found, e.g. for an unknown kind.
+pub fn read_type_lenient<'a, O: ByteOrder>(
+    r: &mut untrusted::Reader<'a>,
+    strs: &file::Strings<'a>,
+    diagnostics: &mut Vec<Error>,
+) -> Result<Type<'a>, Error> {
+    let ty = file::Type::read::<O>(r)?;
+    let kind = ty.kind();
+    let n = diagnostics.len();
 
-            Type::Int {
-                name: name.ok_or(Expected("int name"))?,
-                size: ty.size(),
-                bits_offset: int.offset(),
-                nr_bits: int.bits(),
-                encoding: int.encoding(),
-            }
+    let res = match Decoder::new(strs, Some(diagnostics)).decode_type::<O>(r, &ty) {
+        Err(err) if ty.type_size().is_some() => {
+            diagnostics.push(err);
+
+            Ok(Type::Void)
         }
-        Kind::Pointer => Type::Ptr {
-            type_id: ty.type_id(),
-        },
-        Kind::Array => {
-            let array = file::Array::read::<O>(r)?;
+        res => res,
+    };
 
-            Type::Array {
-                type_id: array.ty,
-                index_type_id: array.index_ty,
-                nr_elems: array.nelems,
+    locate_since(diagnostics, n, |loc| {
+        loc.kind = Some(kind);
+    });
+
+    res.map_err(|err| {
+        err.locate(|loc| {
+            loc.kind = Some(kind);
+        })
+    })
+}
+
+/// Refine the location of the errors pushed after the first `n` ones.
+fn locate_since<F: Fn(&mut Location)>(errs: &mut Vec<Error>, n: usize, f: F) {
+    let located = errs
+        .drain(n..)
+        .map(|err| err.locate(&f))
+        .collect::<Vec<_>>();
+
+    errs.extend(located);
+}
+
+struct Decoder<'s, 'a> {
+    strs: &'s file::Strings<'a>,
+    /// Where the recovered errors go, `None` to fail on them.
+    diagnostics: Option<&'s mut Vec<Error>>,
+}
+
+impl<'s, 'a> Decoder<'s, 'a> {
+    fn new(strs: &'s file::Strings<'a>, diagnostics: Option<&'s mut Vec<Error>>) -> Self {
+        Decoder { strs, diagnostics }
+    }
+
+    /// Substitute `placeholder` for a malformed part in lenient mode, or fail.
+    fn recover<T, F: FnOnce() -> T>(
+        &mut self,
+        res: Result<T, Error>,
+        placeholder: F,
+    ) -> Result<T, Error> {
+        match (res, self.diagnostics.as_mut()) {
+            (Ok(v), _) => Ok(v),
+            (Err(err), Some(diags)) => {
+                diags.push(err);
+
+                Ok(placeholder())
             }
+            (Err(err), None) => Err(err),
         }
-        Kind::Struct => Type::Struct {
-            name,
-            size: ty.size(),
-            members: (0..ty.vlen())
-                .map(|_| {
-                    file::Member::read::<O>(r).and_then(|m| {
-                        if ty.kflag() {
-                            Ok(Member {
-                                name: file::read_str(strs, m.name_off)?,
-                                type_id: m.ty,
-                                bits_offset: m.bit_offset(),
-                                bitfield_size: m.bitfield_size(),
-                            })
-                        } else {
-                            Ok(Member {
-                                name: file::read_str(strs, m.name_off)?,
-                                type_id: m.ty,
-                                bits_offset: m.offset,
-                                bitfield_size: 0,
-                            })
-                        }
-                    })
-                })
-                .collect::<Result<Vec<_>, Error>>()?,
-        },
-        Kind::Union => Type::Union {
+    }
+
+    fn name(&mut self, off: u32) -> Result<Option<Cow<'a, str>>, Error> {
+        let strs = self.strs;
+
+        self.recover(strs.get(off).map(|s| s.map(Cow::Borrowed)), || {
+            strs.get_lossy(off)
+        })
+    }
+
+    fn required_name(
+        &mut self,
+        name: Option<Cow<'a, str>>,
+        what: &'static str,
+    ) -> Result<Cow<'a, str>, Error> {
+        self.recover(name.ok_or(Expected(what)), || Cow::Borrowed(""))
+    }
+
+    /// Decode the rest of a type record with the view decoder, collecting its records.
+    fn decode_type<O: ByteOrder>(
+        &mut self,
+        r: &mut untrusted::Reader<'a>,
+        ty: &file::Type,
+    ) -> Result<Type<'a>, Error> {
+        let mut name = self.name(ty.name_off)?;
+
+        if let Some(what) = view::required_name(ty.kind()) {
+            name = Some(self.required_name(name, what)?);
+        }
+
+        // the view borrows its names, a lossy one is put back when collecting
+        let borrowed = name.as_ref().map(|name| match *name {
+            Cow::Borrowed(s) => s,
+            Cow::Owned(_) => "",
+        });
+        // a truncated list fails at its first missing record, as any malformed record
+        let decoded = view::decode_type::<O>(r, ty, borrowed, self.strs, true)?;
+
+        Ok(match decoded {
+            view::Type::Void => Type::Void,
+            view::Type::Int {
+                size,
+                bits_offset,
+                nr_bits,
+                encoding,
+                ..
+            } => Type::Int {
+                name: name.unwrap_or_default(),
+                size,
+                bits_offset,
+                nr_bits,
+                encoding,
+            },
+            view::Type::Ptr { type_id } => Type::Ptr { type_id },
+            view::Type::Array {
+                type_id,
+                index_type_id,
+                nr_elems,
+            } => Type::Array {
+                type_id,
+                index_type_id,
+                nr_elems,
+            },
+            view::Type::Struct { size, members, .. } => Type::Struct {
+                name,
+                size,
+                members: self.collect_members(members)?,
+            },
+            view::Type::Union { size, members, .. } => Type::Union {
+                name,
+                size,
+                members: self.collect_members(members)?,
+            },
+            view::Type::Enum {
+                size,
+                signed,
+                values,
+                ..
+            } => Type::Enum {
+                name,
+                size,
+                signed,
+                values: self.collect_values(values)?,
+            },
+            view::Type::Enum64 {
+                size,
+                signed,
+                values,
+                ..
+            } => Type::Enum64 {
+                name,
+                size,
+                signed,
+                values: self.collect_values(values)?,
+            },
+            view::Type::Fwd { fwd_kind, .. } => Type::Fwd {
+                name: name.unwrap_or_default(),
+                fwd_kind,
+            },
+            view::Type::Typedef { type_id, .. } => Type::Typedef {
+                name: name.unwrap_or_default(),
+                type_id,
+            },
+            view::Type::Volatile { type_id } => Type::Volatile { type_id },
+            view::Type::Const { type_id } => Type::Const { type_id },
+            view::Type::Restrict { type_id } => Type::Restrict { type_id },
+            view::Type::Func {
+                type_id, linkage, ..
+            } => Type::Func {
+                name: name.unwrap_or_default(),
+                type_id,
+                linkage,
+            },
+            view::Type::FuncProto {
+                ret_type_id,
+                params,
+            } => Type::FuncProto {
+                ret_type_id,
+                params: self.collect(params, Context::Param, |p, name| Param {
+                    name,
+                    type_id: p.type_id,
+                })?,
+            },
+            view::Type::Variable {
+                type_id, linkage, ..
+            } => Type::Variable {
+                name: name.unwrap_or_default(),
+                type_id,
+                linkage,
+            },
+            view::Type::DataSec { size, sections, .. } => Type::DataSec {
+                name: name.unwrap_or_default(),
+                size,
+                sections: self.collect(sections, Context::Entry, |v, _| v)?,
+            },
+            view::Type::Float { size, .. } => Type::Float {
+                name: name.unwrap_or_default(),
+                size,
+            },
+            view::Type::DeclTag {
+                type_id,
+                component_idx,
+                ..
+            } => Type::DeclTag {
+                name: name.unwrap_or_default(),
+                type_id,
+                component_idx,
+            },
+            view::Type::TypeTag { type_id, .. } => Type::TypeTag {
+                name: name.unwrap_or_default(),
+                type_id,
+            },
+        })
+    }
+
+    fn collect_members(&mut self, members: view::Members<'a>) -> Result<Vec<Member<'a>>, Error> {
+        self.collect(members, Context::Member, |m, name| Member {
             name,
-            size: ty.size(),
-            members: (0..ty.vlen())
-                .map(|_| {
-                    file::Member::read::<O>(r).and_then(|m| {
-                        if ty.kflag() {
-                            Ok(Member {
-                                name: file::read_str(strs, m.name_off)?,
-                                type_id: m.ty,
-                                bits_offset: m.bit_offset(),
-                                bitfield_size: m.bitfield_size(),
-                            })
-                        } else {
-                            Ok(Member {
-                                name: file::read_str(strs, m.name_off)?,
-                                type_id: m.ty,
-                                bits_offset: m.offset,
-                                bitfield_size: 0,
-                            })
+            type_id: m.type_id,
+            bits_offset: m.bits_offset,
+            bitfield_size: m.bitfield_size,
+        })
+    }
+
+    fn collect_values(&mut self, values: view::Values<'a>) -> Result<Vec<Enum<'a>>, Error> {
+        self.collect(values, Context::Value, |v, name| Enum { name, val: v.val })
+    }
+
+    /// Collect the records following a type, looking up their names and
+    /// locating errors at the failed record.
+    ///
+    /// In lenient mode, the list is truncated at the first record that can't be decoded.
+    fn collect<T, U, F>(
+        &mut self,
+        mut records: view::Records<'a, T>,
+        context: fn(usize) -> Context,
+        mut f: F,
+    ) -> Result<Vec<U>, Error>
+    where
+        F: FnMut(T, Option<Cow<'a, str>>) -> U,
+    {
+        let mut items = Vec::new();
+
+        for (idx, res) in iter::from_fn(|| records.next_unnamed()).enumerate() {
+            let locate = |loc: &mut Location| {
+                loc.context.push(context(idx));
+            };
+            let n = self.diagnostics.as_ref().map_or(0, |diags| diags.len());
+
+            let res = res.and_then(|(name_off, record)| Ok(f(record, self.name(name_off)?)));
+
+            if let Some(diags) = self.diagnostics.as_mut() {
+                locate_since(diags, n, locate);
+            }
+
+            match res {
+                Ok(item) => items.push(item),
+                Err(err) => {
+                    let err = err.locate(locate);
+
+                    match self.diagnostics.as_mut() {
+                        Some(diags) => {
+                            diags.push(err);
+                            break;
                         }
-                    })
-                })
-                .collect::<Result<Vec<_>, Error>>()?,
-        },
-        Kind::Enum => Type::Enum {
-            name,
-            size: ty.size(),
-            values: (0..ty.vlen())
-                .map(|_| {
-                    file::Enum::read::<O>(r).and_then(|v| {
-                        Ok(Enum {
-                            name: file::read_str(strs, v.name_off)?,
-                            val: v.val as u64,
-                        })
-                    })
-                })
-                .collect::<Result<Vec<_>, Error>>()?,
-        },
-        Kind::Enum64 => Type::Enum {
-            name,
-            size: ty.size(),
-            values: (0..ty.vlen())
-                .map(|_| {
-                    file::Enum64::read::<O>(r).and_then(|v| {
-                        Ok(Enum {
-                            name: file::read_str(strs, v.name_off)?,
-                            val: ((v.val_hi32 as u64) << 32) + (v.val_lo32 as u64),
-                        })
-                    })
-                })
-                .collect::<Result<Vec<_>, Error>>()?,
-        },
-        Kind::Forward => Type::Fwd {
-            name: name.ok_or(Expected("forward name"))?,
-            fwd_kind: if ty.kflag() {
-                Kind::Union
-            } else {
-                Kind::Struct
-            },
-        },
-        Kind::Typedef => Type::Typedef {
-            name: name.ok_or(Expected("typedef name"))?,
-            type_id: ty.type_id(),
-        },
-        Kind::Volatile => Type::Volatile {
-            type_id: ty.type_id(),
-        },
-        Kind::Const => Type::Const {
-            type_id: ty.type_id(),
-        },
-        Kind::Restrict => Type::Restrict {
-            type_id: ty.type_id(),
-        },
-        Kind::Func => Type::Func {
-            name: name.ok_or(Expected("func name"))?,
-            type_id: ty.type_id(),
-            linkage: file::Linkage::from(ty.vlen() as u32),
-        },
-        Kind::FuncProto => Type::FuncProto {
-            ret_type_id: ty.type_id(),
-            params: (0..ty.vlen())
-                .map(|_| {
-                    file::Param::read::<O>(r).and_then(|p| {
-                        Ok(Param {
-                            name: file::read_str(strs, p.name_off)?,
-                            type_id: p.ty,
-                        })
-                    })
-                })
-                .collect::<Result<Vec<_>, Error>>()?,
-        },
-        Kind::Variable => Type::Variable {
-            name: name.ok_or(Expected("var name"))?,
-            type_id: ty.type_id(),
-            linkage: file::Var::read::<O>(r)?.linkage,
-        },
-        Kind::DataSection => Type::DataSec {
-            name: name.ok_or(Expected("datasec name"))?,
-            size: ty.size(),
-            sections: (0..ty.vlen())
-                .map(|_| file::VarSectInfo::read::<O>(r))
-                .collect::<Result<Vec<_>, Error>>()?,
-        },
-        Kind::Float => Type::Float {
-            name: name.ok_or(Expected("float name"))?,
-            size: ty.size(),
-        },
-        Kind::DeclTag => Type::DeclTag {
-            name: name.ok_or(Expected("decl_tag name"))?,
-            type_id: ty.type_id(),
-            component_idx: file::DeclTag::read::<O>(r)?.component_idx,
-        },
-        Kind::TypeTag => Type::TypeTag {
-            name: name.ok_or(Expected("type_tag name"))?,
-            type_id: ty.type_id(),
-        },
-    })
+                        None => return Err(err),
+                    }
+                }
+            }
+        }
+
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{ByteOrder, LittleEndian};
+
+    use super::*;
+
+    const STRS: &[u8] = b"\0foo\0a\0\xffb\0";
+
+    /// A struct `foo` of `vlen` members, followed by the records of `names`.
+    fn struct_record(vlen: u32, names: &[u32]) -> Vec<u8> {
+        let mut b = vec![0; 12 + names.len() * 12];
+
+        LittleEndian::write_u32(&mut b[0..], 1);
+        LittleEndian::write_u32(&mut b[4..], 4 << 24 | vlen);
+        LittleEndian::write_u32(&mut b[8..], 8);
+
+        for (i, &name_off) in names.iter().enumerate() {
+            let m = &mut b[12 + i * 12..];
+
+            LittleEndian::write_u32(&mut m[0..], name_off);
+            LittleEndian::write_u32(&mut m[4..], 1);
+            LittleEndian::write_u32(&mut m[8..], i as u32 * 32);
+        }
+
+        b
+    }
+
+    fn decode(b: &[u8]) -> Result<Type<'_>, Error> {
+        let strs = file::Strings::new(untrusted::Input::from(STRS));
+
+        untrusted::Input::from(b).read_all(EndOfInput, |r| read_type::<LittleEndian>(r, &strs))
+    }
+
+    fn decode_lenient(b: &[u8]) -> (Type<'static>, Vec<Error>) {
+        let strs = file::Strings::new(untrusted::Input::from(STRS));
+        let mut diags = vec![];
+        let ty = untrusted::Input::from(b)
+            .read_all(EndOfInput, |r| {
+                read_type_lenient::<LittleEndian>(r, &strs, &mut diags)
+            })
+            .unwrap();
+
+        (ty.into_owned(), diags)
+    }
+
+    fn member_names<'t>(ty: &'t Type) -> Vec<Option<&'t str>> {
+        match ty {
+            Type::Struct { members, .. } => members.iter().map(|m| m.name.as_deref()).collect(),
+            _ => panic!("not a struct: {:?}", ty),
+        }
+    }
+
+    #[test]
+    fn lossy_member_name() {
+        let b = struct_record(2, &[5, 7]);
+
+        let err = decode(&b).unwrap_err();
+        let loc = err.location().unwrap();
+        assert_eq!(loc.context, [Context::Member(1)]);
+        assert_eq!(loc.kind, Some(file::Kind::Struct));
+
+        let (ty, diags) = decode_lenient(&b);
+        assert_eq!(ty.name(), Some("foo"));
+        assert_eq!(member_names(&ty), [Some("a"), Some("\u{fffd}b")]);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].location().unwrap().context, [Context::Member(1)]);
+    }
+
+    #[test]
+    fn truncated_members() {
+        let b = struct_record(3, &[5, 0]);
+
+        let err = decode(&b).unwrap_err();
+        let loc = err.location().unwrap();
+        assert!(matches!(err.root_cause(), EndOfInput));
+        assert_eq!(loc.context, [Context::Member(2)]);
+        assert_eq!(loc.offset, Some(36));
+
+        let (ty, diags) = decode_lenient(&b);
+        assert_eq!(member_names(&ty), [Some("a"), None]);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].location().unwrap().offset, Some(36));
+    }
 }
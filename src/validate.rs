@@ -0,0 +1,640 @@
+use core::fmt;
+use core::mem;
+
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    file::{IntEncoding, Kind, Linkage},
+    layout::{self, Layout},
+    table::TypeTable,
+    ty::{Member, Type},
+    Error,
+};
+
+/// A rule violation found by [`validate`], tied to the type that violates it.
+#[derive(Debug)]
+pub struct Problem {
+    pub type_id: u32,
+    pub issue: Issue,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}", self.type_id, self.issue)
+    }
+}
+
+#[derive(Debug)]
+pub enum Issue {
+    /// The type record can't be decoded.
+    Malformed(Error),
+    /// The type refers to a type id that doesn't exist.
+    InvalidTypeId { type_id: u32 },
+    /// A member refers to a type id that doesn't exist.
+    InvalidMemberType { member: usize, type_id: u32 },
+    /// A parameter refers to a type id that doesn't exist.
+    InvalidParamType { param: usize, type_id: u32 },
+    /// The kind requires a name.
+    MissingName,
+    /// The kind doesn't allow a name.
+    UnexpectedName,
+    /// The name isn't a valid C identifier.
+    InvalidName { name: &'static str },
+    /// A member name isn't a valid C identifier.
+    InvalidMemberName { member: usize },
+    /// A parameter name isn't a valid C identifier.
+    InvalidParamName { param: usize },
+    /// The int size isn't 1, 2, 4, 8 or 16 bytes.
+    InvalidIntSize { size: usize },
+    /// The int bits and offset don't fit in its size.
+    IntBitsOverflow {
+        bits_offset: usize,
+        nr_bits: usize,
+        size: usize,
+    },
+    /// The int encoding is a combination of flags.
+    InvalidIntEncoding { encoding: IntEncoding },
+    /// A member doesn't fit inside its struct or union.
+    MemberOutOfBounds { member: usize },
+    /// A non-bitfield member isn't byte aligned.
+    MemberNotByteAligned { member: usize },
+    /// Only the last parameter may be a variable argument.
+    MisplacedVarArg { param: usize },
+    /// The component index is out of the bounds of the tagged type.
+    ComponentIdxOutOfBounds { component_idx: i32, vlen: usize },
+    /// A function doesn't refer to a function prototype.
+    NotFuncProto { type_id: u32 },
+    /// The linkage isn't allowed for the kind.
+    InvalidLinkage { linkage: Linkage },
+    /// A chain of modifiers, typedefs, pointers or arrays loops back on itself.
+    Cycle,
+    /// A data section entry doesn't refer to a variable or function.
+    NotVariable { entry: usize, type_id: u32 },
+    /// A data section entry overlaps the previous entry.
+    DataSecOverlap { entry: usize },
+    /// A data section entry doesn't fit inside the data section.
+    DataSecOutOfBounds { entry: usize },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            Issue::Malformed(err) => write!(f, "malformed type, {}", err),
+            #[cfg(not(feature = "std"))]
+            Issue::Malformed(err) => write!(f, "malformed type, {:?}", err),
+            Issue::InvalidTypeId { type_id } => write!(f, "invalid type_id {}", type_id),
+            Issue::InvalidMemberType { member, type_id } => {
+                write!(f, "member {} has invalid type_id {}", member, type_id)
+            }
+            Issue::InvalidParamType { param, type_id } => {
+                write!(f, "param {} has invalid type_id {}", param, type_id)
+            }
+            Issue::MissingName => write!(f, "missing name"),
+            Issue::UnexpectedName => write!(f, "unexpected name"),
+            Issue::InvalidName { name } => write!(f, "invalid {} name", name),
+            Issue::InvalidMemberName { member } => write!(f, "member {} has invalid name", member),
+            Issue::InvalidParamName { param } => write!(f, "param {} has invalid name", param),
+            Issue::InvalidIntSize { size } => write!(f, "invalid int size {}", size),
+            Issue::IntBitsOverflow {
+                bits_offset,
+                nr_bits,
+                size,
+            } => write!(
+                f,
+                "int bits_offset={} nr_bits={} exceed size {}",
+                bits_offset, nr_bits, size
+            ),
+            Issue::InvalidIntEncoding { encoding } => {
+                write!(f, "invalid int encoding {:?}", encoding)
+            }
+            Issue::MemberOutOfBounds { member } => write!(f, "member {} out of bounds", member),
+            Issue::MemberNotByteAligned { member } => {
+                write!(f, "member {} is not byte aligned", member)
+            }
+            Issue::MisplacedVarArg { param } => {
+                write!(f, "param {} is a variable argument but not the last", param)
+            }
+            Issue::ComponentIdxOutOfBounds {
+                component_idx,
+                vlen,
+            } => write!(
+                f,
+                "component_idx {} out of bounds, vlen={}",
+                component_idx, vlen
+            ),
+            Issue::NotFuncProto { type_id } => {
+                write!(f, "type_id {} is not a function prototype", type_id)
+            }
+            Issue::InvalidLinkage { linkage } => write!(f, "invalid linkage {}", linkage),
+            Issue::Cycle => write!(f, "type reference loop detected"),
+            Issue::NotVariable { entry, type_id } => {
+                write!(f, "entry {} type_id {} is not a variable", entry, type_id)
+            }
+            Issue::DataSecOverlap { entry } => write!(f, "entry {} overlaps", entry),
+            Issue::DataSecOutOfBounds { entry } => write!(f, "entry {} out of bounds", entry),
+        }
+    }
+}
+
+/// Check the types against the rules the kernel enforces when loading BTF.
+///
+/// Returns every problem found, an empty list means the types are valid.
+pub fn validate(types: &TypeTable) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let layout = Layout::new(
+        types,
+        layout::guess_ptr_size(types).unwrap_or(mem::size_of::<usize>()),
+    );
+
+    for type_id in types.type_ids() {
+        let mut report = |issue| problems.push(Problem { type_id, issue });
+
+        match types.raw(type_id) {
+            Ok(ty) if ty.name_off != 0 && !kind_has_name(ty.kind()) => {
+                report(Issue::UnexpectedName)
+            }
+            _ => {}
+        }
+
+        match types.get(type_id) {
+            Ok(ty) => check_type(types, &layout, &ty, &mut report),
+            Err(err) => report(Issue::Malformed(err)),
+        }
+    }
+
+    check_cycles(types, &mut problems);
+
+    problems
+}
+
+fn kind_has_name(kind: Kind) -> bool {
+    !matches!(
+        kind,
+        Kind::Pointer
+            | Kind::Array
+            | Kind::Volatile
+            | Kind::Const
+            | Kind::Restrict
+            | Kind::FuncProto
+    )
+}
+
+fn check_type<F: FnMut(Issue)>(types: &TypeTable, layout: &Layout, ty: &Type, report: &mut F) {
+    match *ty {
+        Type::Void => {}
+        Type::Int {
+            size,
+            bits_offset,
+            nr_bits,
+            encoding,
+            ..
+        } => {
+            if !matches!(size, 1 | 2 | 4 | 8 | 16) {
+                report(Issue::InvalidIntSize { size });
+            }
+            if nr_bits > 128 || bits_offset + nr_bits > size * 8 {
+                report(Issue::IntBitsOverflow {
+                    bits_offset,
+                    nr_bits,
+                    size,
+                });
+            }
+            if encoding.bits().count_ones() > 1 {
+                report(Issue::InvalidIntEncoding { encoding });
+            }
+        }
+        Type::Ptr { type_id }
+        | Type::Volatile { type_id }
+        | Type::Const { type_id }
+        | Type::Restrict { type_id } => check_ref(types, type_id, report),
+        Type::Array {
+            type_id,
+            index_type_id,
+            ..
+        } => {
+            check_ref(types, type_id, report);
+            check_ref(types, index_type_id, report);
+        }
+        Type::Struct {
+            ref name,
+            size,
+            ref members,
+        }
+        | Type::Union {
+            ref name,
+            size,
+            ref members,
+        } => {
+            if name.as_deref().is_some_and(|s| !is_identifier(s)) {
+                report(Issue::InvalidName {
+                    name: if matches!(ty, Type::Union { .. }) {
+                        "union"
+                    } else {
+                        "struct"
+                    },
+                });
+            }
+
+            check_members(types, layout, size, members, report);
+        }
+        Type::Enum {
+            ref name,
+            ref values,
+            ..
+        }
+        | Type::Enum64 {
+            ref name,
+            ref values,
+            ..
+        } => {
+            if name.as_deref().is_some_and(|s| !is_identifier(s)) {
+                report(Issue::InvalidName { name: "enum" });
+            }
+            if values
+                .iter()
+                .any(|v| !v.name.as_deref().is_some_and(is_identifier))
+            {
+                report(Issue::InvalidName { name: "enum value" });
+            }
+        }
+        Type::Fwd { ref name, .. } => check_identifier(name, report),
+        Type::Typedef { ref name, type_id } => {
+            check_identifier(name, report);
+            check_ref(types, type_id, report);
+        }
+        Type::Func {
+            ref name,
+            type_id,
+            linkage,
+        } => {
+            check_identifier(name, report);
+
+            if !matches!(linkage, Linkage::Static | Linkage::Global) {
+                report(Issue::InvalidLinkage { linkage });
+            }
+
+            match types.kind(type_id) {
+                Ok(Kind::FuncProto) => {}
+                Ok(_) => report(Issue::NotFuncProto { type_id }),
+                Err(_) => report(Issue::InvalidTypeId { type_id }),
+            }
+        }
+        Type::FuncProto {
+            ret_type_id,
+            ref params,
+        } => {
+            check_ref(types, ret_type_id, report);
+
+            for (idx, p) in params.iter().enumerate() {
+                if p.is_variable_argument() {
+                    if idx + 1 != params.len() {
+                        report(Issue::MisplacedVarArg { param: idx });
+                    }
+                } else {
+                    if !types.contains(p.type_id) || p.type_id == 0 {
+                        report(Issue::InvalidParamType {
+                            param: idx,
+                            type_id: p.type_id,
+                        });
+                    }
+                    if p.name.as_deref().is_some_and(|s| !is_identifier(s)) {
+                        report(Issue::InvalidParamName { param: idx });
+                    }
+                }
+            }
+        }
+        Type::Variable {
+            ref name,
+            type_id,
+            linkage,
+        } => {
+            check_identifier(name, report);
+            check_ref(types, type_id, report);
+
+            if !matches!(linkage, Linkage::Static | Linkage::Global) {
+                report(Issue::InvalidLinkage { linkage });
+            }
+        }
+        Type::DataSec {
+            ref name,
+            size,
+            ref sections,
+        } => {
+            if name.is_empty() || !name.bytes().all(|b| b.is_ascii_graphic()) {
+                report(Issue::InvalidName { name: "datasec" });
+            }
+
+            let mut entries = sections.iter().enumerate().collect::<Vec<_>>();
+
+            entries.sort_by_key(|(_, s)| s.offset);
+
+            let mut end = 0;
+
+            for (idx, s) in entries {
+                match types.kind(s.type_id) {
+                    Ok(Kind::Variable) | Ok(Kind::Func) => {}
+                    _ => report(Issue::NotVariable {
+                        entry: idx,
+                        type_id: s.type_id,
+                    }),
+                }
+
+                if (s.offset as u64) < end {
+                    report(Issue::DataSecOverlap { entry: idx });
+                }
+
+                end = s.offset as u64 + s.size as u64;
+
+                if size != 0 && end > size as u64 {
+                    report(Issue::DataSecOutOfBounds { entry: idx });
+                }
+            }
+        }
+        Type::Float { ref name, .. } => {
+            if name.is_empty() {
+                report(Issue::MissingName);
+            }
+        }
+        Type::DeclTag {
+            ref name,
+            type_id,
+            component_idx,
+        } => {
+            if name.is_empty() {
+                report(Issue::MissingName);
+            }
+
+            match types.get(type_id) {
+                Ok(target) => {
+                    let vlen = match target {
+                        Type::Struct { ref members, .. } | Type::Union { ref members, .. } => {
+                            Some(members.len())
+                        }
+                        Type::Func { type_id, .. } => match types.get(type_id) {
+                            Ok(Type::FuncProto { params, .. }) => Some(params.len()),
+                            _ => Some(0),
+                        },
+                        Type::Variable { .. } | Type::Typedef { .. } => Some(0),
+                        _ => None,
+                    };
+
+                    match vlen {
+                        _ if component_idx == -1 => {}
+                        Some(vlen) if component_idx >= 0 && (component_idx as usize) < vlen => {}
+                        _ => report(Issue::ComponentIdxOutOfBounds {
+                            component_idx,
+                            vlen: vlen.unwrap_or_default(),
+                        }),
+                    }
+                }
+                Err(_) => report(Issue::InvalidTypeId { type_id }),
+            }
+        }
+        Type::TypeTag { ref name, type_id } => {
+            if name.is_empty() {
+                report(Issue::MissingName);
+            }
+
+            check_ref(types, type_id, report);
+        }
+    }
+}
+
+fn check_ref<F: FnMut(Issue)>(types: &TypeTable, type_id: u32, report: &mut F) {
+    if !types.contains(type_id) {
+        report(Issue::InvalidTypeId { type_id })
+    }
+}
+
+fn check_members<F: FnMut(Issue)>(
+    types: &TypeTable,
+    layout: &Layout,
+    size: usize,
+    members: &[Member],
+    report: &mut F,
+) {
+    let bits = size as u64 * 8;
+
+    for (idx, m) in members.iter().enumerate() {
+        if !types.contains(m.type_id) || m.type_id == 0 {
+            report(Issue::InvalidMemberType {
+                member: idx,
+                type_id: m.type_id,
+            });
+        }
+        if m.name.as_deref().is_some_and(|s| !is_identifier(s)) {
+            report(Issue::InvalidMemberName { member: idx });
+        }
+
+        if m.bitfield_size == 0 {
+            if m.bits_offset % 8 != 0 {
+                report(Issue::MemberNotByteAligned { member: idx });
+            }
+
+            // the size of a malformed member type is reported by its own checks
+            let member_size = layout.resolve_size(m.type_id).unwrap_or_default() as u64;
+
+            if m.bits_offset as u64 > bits || m.bits_offset as u64 / 8 + member_size > size as u64 {
+                report(Issue::MemberOutOfBounds { member: idx });
+            }
+        } else if m.bits_offset as u64 + m.bitfield_size as u64 > bits {
+            report(Issue::MemberOutOfBounds { member: idx });
+        }
+    }
+}
+
+fn check_identifier<F: FnMut(Issue)>(name: &str, report: &mut F) {
+    if name.is_empty() {
+        report(Issue::MissingName)
+    } else if !is_identifier(name) {
+        report(Issue::InvalidName { name: "identifier" })
+    }
+}
+
+/// Returns `true` if `s` is a valid C identifier.
+pub fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Report every chain of modifiers, typedefs, pointers or arrays that loops back on itself.
+fn check_cycles(types: &TypeTable, problems: &mut Vec<Problem>) {
+    const UNVISITED: u8 = 0;
+    const VISITING: u8 = 1;
+    const VISITED: u8 = 2;
+
+    let start = types.start_id();
+    let mut state = vec![UNVISITED; types.len()];
+    let mut path = Vec::new();
+
+    let next = |type_id: u32| -> Option<u32> {
+        let ty = types.raw(type_id).ok()?;
+
+        match ty.kind() {
+            Kind::Typedef
+            | Kind::Const
+            | Kind::Volatile
+            | Kind::Restrict
+            | Kind::TypeTag
+            | Kind::Pointer => Some(ty.type_id()),
+            Kind::Array => match types.get(type_id).ok()? {
+                Type::Array { type_id, .. } => Some(type_id),
+                _ => None,
+            },
+            _ => None,
+        }
+    };
+
+    for type_id in types.type_ids() {
+        let mut cur = type_id;
+
+        path.clear();
+
+        loop {
+            if cur < start || !types.contains(cur) {
+                break;
+            }
+
+            let idx = (cur - start) as usize;
+
+            match state[idx] {
+                VISITED => break,
+                VISITING => {
+                    problems.push(Problem {
+                        type_id: cur,
+                        issue: Issue::Cycle,
+                    });
+                    break;
+                }
+                _ => {}
+            }
+
+            state[idx] = VISITING;
+            path.push(idx);
+
+            match next(cur) {
+                Some(id) => cur = id,
+                None => break,
+            }
+        }
+
+        for &idx in &path {
+            state[idx] = VISITED;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::LittleEndian;
+
+    use super::*;
+    use crate::writer;
+
+    fn problems(types: &[Type]) -> Vec<(u32, String)> {
+        let b = writer::write::<LittleEndian>(types).unwrap();
+        let types = TypeTable::parse(untrusted::Input::from(&b)).unwrap();
+
+        validate(&types)
+            .into_iter()
+            .map(|p| (p.type_id, p.issue.to_string()))
+            .collect()
+    }
+
+    fn members(offsets: &[u32]) -> Vec<Member<'static>> {
+        offsets
+            .iter()
+            .map(|&bits_offset| Member {
+                name: None,
+                type_id: 1,
+                bits_offset,
+                bitfield_size: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn member_exceeds_size() {
+        let int = Type::Int {
+            name: "int".into(),
+            size: 4,
+            bits_offset: 0,
+            nr_bits: 32,
+            encoding: IntEncoding::SIGNED,
+        };
+
+        assert_eq!(
+            problems(&[
+                int.clone(),
+                Type::Struct {
+                    name: Some("s".into()),
+                    size: 8,
+                    members: members(&[0, 32]),
+                },
+                Type::Union {
+                    name: Some("u".into()),
+                    size: 4,
+                    members: members(&[0, 0]),
+                },
+            ]),
+            vec![]
+        );
+        assert_eq!(
+            problems(&[
+                int,
+                Type::Struct {
+                    name: Some("s".into()),
+                    size: 4,
+                    members: members(&[32, 16]),
+                },
+                Type::Union {
+                    name: Some("u".into()),
+                    size: 2,
+                    members: members(&[0]),
+                },
+            ]),
+            vec![
+                (2, "member 0 out of bounds".to_string()),
+                (2, "member 1 out of bounds".to_string()),
+                (3, "member 0 out of bounds".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn names_and_linkage() {
+        assert_eq!(
+            problems(&[
+                Type::Union {
+                    name: Some("1u".into()),
+                    size: 0,
+                    members: vec![],
+                },
+                Type::FuncProto {
+                    ret_type_id: 0,
+                    params: vec![],
+                },
+                Type::Func {
+                    name: "f".into(),
+                    type_id: 2,
+                    linkage: Linkage::Global,
+                },
+                Type::Func {
+                    name: "g".into(),
+                    type_id: 2,
+                    linkage: Linkage::Extern,
+                },
+            ]),
+            vec![
+                (1, "invalid union name".to_string()),
+                (4, "invalid linkage extern".to_string()),
+            ]
+        );
+    }
+}
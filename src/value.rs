@@ -0,0 +1,464 @@
+use core::mem;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "std")] {
+        use std::borrow::Cow;
+    } else {
+        use alloc::{borrow::Cow, vec::Vec};
+    }
+}
+
+use derive_more::IsVariant;
+
+#[cfg(feature = "serde")]
+use serde::{
+    ser::{SerializeMap, SerializeSeq},
+    Serialize, Serializer,
+};
+
+use crate::{
+    layout::{self, Layout},
+    table::TypeTable,
+    ty::{EnumValue, Member, Type},
+    Error::{self, *},
+};
+
+/// A value decoded from raw memory according to its BTF type.
+///
+/// With the `serde` feature, the value serializes as the plain data,
+/// e.g. a struct as a map of its fields and an enum as the name of its enumerator.
+#[derive(Debug, Clone, PartialEq, IsVariant)]
+pub enum Value<'a> {
+    Bool(bool),
+    Int(i128),
+    Uint(u128),
+    Float(f64),
+    /// The value of an enum, and the name of the matching enumerator if any.
+    Enum {
+        name: Option<Cow<'a, str>>,
+        value: EnumValue,
+    },
+    /// The fields of a struct or union, or the variables of a data section.
+    Struct(Vec<Field<'a>>),
+    Array(Vec<Value<'a>>),
+    /// The address held by a pointer.
+    Ptr(u64),
+    /// An array of characters, or a value without a Rust counterpart such as a `long double`.
+    Bytes(&'a [u8]),
+}
+
+impl<'a> Value<'a> {
+    /// The field of a struct with the name, looking into the anonymous structs and unions like C does.
+    pub fn field(&self, name: &str) -> Option<&Value<'a>> {
+        match self {
+            Value::Struct(fields) => fields.iter().find_map(|f| match f.name {
+                Some(ref n) if n == name => Some(&f.value),
+                Some(_) => None,
+                None => f.value.field(name),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The characters of a char array up to the first NUL, if they are valid UTF-8.
+    pub fn as_str(&self) -> Option<&'a str> {
+        match *self {
+            Value::Bytes(b) => {
+                let len = b.iter().position(|&c| c == 0).unwrap_or(b.len());
+
+                core::str::from_utf8(&b[..len]).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A field of a struct or union, `None` for an anonymous struct or union member.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field<'a> {
+    pub name: Option<Cow<'a, str>>,
+    pub value: Value<'a>,
+}
+
+/// Decode the value of `type_id` from the start of `data`, see [`Decoder`].
+pub fn decode<'a>(types: &TypeTable<'a>, type_id: u32, data: &'a [u8]) -> Result<Value<'a>, Error> {
+    Decoder::new(types).decode(type_id, data)
+}
+
+/// A decoder of raw memory, such as a map value or a ring buffer sample, into [`Value`] trees.
+///
+/// The integers are read in the byte order of the BTF, and bitfields are
+/// extracted from the bits of the struct they belong to.
+#[derive(Clone, Copy, Debug)]
+pub struct Decoder<'t, 'a> {
+    pub(crate) types: &'t TypeTable<'a>,
+    pub(crate) layout: Layout<'t, 'a>,
+}
+
+impl<'t, 'a> Decoder<'t, 'a> {
+    pub fn new(types: &'t TypeTable<'a>) -> Self {
+        let ptr_size = layout::guess_ptr_size(types).unwrap_or(mem::size_of::<usize>());
+
+        Decoder {
+            types,
+            layout: Layout::new(types, ptr_size),
+        }
+    }
+
+    /// Decode the pointers of a target whose pointers are `ptr_size` bytes.
+    ///
+    /// By default, the pointer size is guessed from the types, see [`layout::guess_ptr_size`].
+    pub fn ptr_size(mut self, ptr_size: usize) -> Self {
+        self.layout = Layout::new(self.types, ptr_size);
+        self
+    }
+
+    /// Decode the value of `type_id` from the start of `data`, which must hold the whole value.
+    pub fn decode(&self, type_id: u32, data: &'a [u8]) -> Result<Value<'a>, Error> {
+        self.value(type_id, data, &mut Vec::new())
+    }
+
+    fn value(&self, type_id: u32, data: &'a [u8], path: &mut Vec<u32>) -> Result<Value<'a>, Error> {
+        let type_id = self.layout.resolve_type(type_id)?;
+
+        match self.types.get(type_id)? {
+            Type::Int {
+                size,
+                bits_offset,
+                nr_bits,
+                encoding,
+                ..
+            } => {
+                let b = slice(data, 0, size)?;
+
+                if nr_bits > 128 {
+                    return Ok(Value::Bytes(b));
+                }
+
+                let raw = self.bits(b, bits_offset, nr_bits)?;
+
+                Ok(if encoding.is_bool() {
+                    Value::Bool(raw != 0)
+                } else if encoding.is_signed() {
+                    Value::Int(sign_extend(raw, nr_bits))
+                } else {
+                    Value::Uint(raw)
+                })
+            }
+            Type::Float { size, .. } => {
+                let b = slice(data, 0, size)?;
+
+                Ok(match size {
+                    4 => Value::Float(f32::from_bits(self.uint(b) as u32) as f64),
+                    8 => Value::Float(f64::from_bits(self.uint(b) as u64)),
+                    _ => Value::Bytes(b),
+                })
+            }
+            Type::Ptr { .. } => {
+                let b = slice(data, 0, self.layout.ptr_size())?;
+
+                Ok(Value::Ptr(self.uint(b) as u64))
+            }
+            ty @ Type::Enum { .. } | ty @ Type::Enum64 { .. } => {
+                let size = self.layout.resolve_size(type_id)?;
+                let raw = self.uint(slice(data, 0, size)?);
+
+                Ok(enum_value(ty, raw, size * 8))
+            }
+            Type::Array {
+                type_id: elem_type_id,
+                nr_elems,
+                ..
+            } => {
+                let elem_size = self.layout.resolve_size(elem_type_id)?;
+                let len = elem_size
+                    .checked_mul(nr_elems as usize)
+                    .ok_or(OutOfRange("array size", nr_elems as u64))?;
+                let b = slice(data, 0, len)?;
+
+                if elem_size == 1 && self.is_char(elem_type_id)? {
+                    return Ok(Value::Bytes(b));
+                }
+
+                (0..nr_elems as usize)
+                    .map(|i| self.value(elem_type_id, &b[i * elem_size..], path))
+                    .collect::<Result<Vec<_>, Error>>()
+                    .map(Value::Array)
+            }
+            Type::Struct { size, members, .. } | Type::Union { size, members, .. } => {
+                let b = slice(data, 0, size)?;
+
+                self.composite(type_id, path, |path| {
+                    members
+                        .into_iter()
+                        .map(|m| {
+                            let value = self.member(&m, b, path)?;
+
+                            Ok(Field {
+                                name: m.name,
+                                value,
+                            })
+                        })
+                        .collect()
+                })
+            }
+            Type::DataSec { size, sections, .. } => {
+                let b = slice(data, 0, size)?;
+
+                self.composite(type_id, path, |path| {
+                    sections
+                        .iter()
+                        .map(|s| {
+                            let name = self.types.name(s.type_id)?.map(Cow::Borrowed);
+                            let value = self.value(
+                                s.type_id,
+                                slice(b, s.offset as usize, s.size as usize)?,
+                                path,
+                            )?;
+
+                            Ok(Field { name, value })
+                        })
+                        .collect()
+                })
+            }
+            _ => Err(Unsized(self.types.kind(type_id)?)),
+        }
+    }
+
+    /// Decode the fields of a struct, union or data section, unless it contains itself.
+    fn composite<F>(&self, type_id: u32, path: &mut Vec<u32>, f: F) -> Result<Value<'a>, Error>
+    where
+        F: FnOnce(&mut Vec<u32>) -> Result<Vec<Field<'a>>, Error>,
+    {
+        if path.contains(&type_id) {
+            return Err(Cycle(type_id));
+        }
+
+        path.push(type_id);
+
+        let res = f(path);
+
+        path.pop();
+
+        res.map(Value::Struct)
+    }
+
+    fn member(&self, m: &Member, data: &'a [u8], path: &mut Vec<u32>) -> Result<Value<'a>, Error> {
+        let bits_offset = m.bits_offset as usize;
+
+        if m.bitfield_size == 0 {
+            if !bits_offset.is_multiple_of(8) {
+                return Err(Malformed("member not aligned on a byte"));
+            }
+
+            let off = bits_offset / 8;
+
+            return self.value(m.type_id, data.get(off..).ok_or(EndOfInput)?, path);
+        }
+
+        self.bitfield(m, data)
+    }
+
+    /// Decode a bitfield member of a struct or union, from the data of the struct or union.
+    pub(crate) fn bitfield(&self, m: &Member, data: &[u8]) -> Result<Value<'a>, Error> {
+        let bits_offset = m.bits_offset as usize;
+        let nr_bits = m.bitfield_size as usize;
+        let type_id = self.layout.resolve_type(m.type_id)?;
+        let ty = self.types.get(type_id)?;
+
+        // a bitfield may start in the middle of a byte and span over the next one
+        let off = bits_offset / 8;
+        let shift = bits_offset % 8;
+        let raw = self.bits(
+            slice(data, off, (shift + nr_bits).div_ceil(8))?,
+            shift,
+            nr_bits,
+        )?;
+
+        match ty {
+            Type::Int { encoding, .. } => Ok(if encoding.is_bool() {
+                Value::Bool(raw != 0)
+            } else if encoding.is_signed() {
+                Value::Int(sign_extend(raw, nr_bits))
+            } else {
+                Value::Uint(raw)
+            }),
+            ty @ Type::Enum { .. } | ty @ Type::Enum64 { .. } => Ok(enum_value(ty, raw, nr_bits)),
+            _ => Err(Expected("integer or enum bitfield")),
+        }
+    }
+
+    /// Returns `true` if the type is a character, whose arrays are strings.
+    pub(crate) fn is_char(&self, type_id: u32) -> Result<bool, Error> {
+        let type_id = self.layout.resolve_type(type_id)?;
+
+        Ok(match self.types.get(type_id)? {
+            Type::Int { name, encoding, .. } => encoding.is_char() || name == "char",
+            _ => false,
+        })
+    }
+
+    /// Extract `nr_bits` bits, starting `bits_offset` bits into `b`.
+    ///
+    /// The bits are numbered from the least significant bit for a little endian
+    /// BTF, and from the most significant bit for a big endian one.
+    fn bits(&self, b: &[u8], bits_offset: usize, nr_bits: usize) -> Result<u128, Error> {
+        let shift = self.shift(b, bits_offset, nr_bits)?;
+
+        Ok((self.uint(b) >> shift) & mask(nr_bits))
+    }
+
+    /// Replace `nr_bits` bits, starting `bits_offset` bits into `b`, with the low bits of `v`.
+    #[cfg(all(feature = "serde", feature = "std"))]
+    pub(crate) fn put_bits(
+        &self,
+        b: &mut [u8],
+        bits_offset: usize,
+        nr_bits: usize,
+        v: u128,
+    ) -> Result<(), Error> {
+        let shift = self.shift(b, bits_offset, nr_bits)?;
+        let mask = mask(nr_bits) << shift;
+        let raw = (self.uint(b) & !mask) | ((v << shift) & mask);
+
+        if self.types.is_le() {
+            for (i, c) in b.iter_mut().enumerate() {
+                *c = (raw >> (i * 8)) as u8;
+            }
+        } else {
+            for (i, c) in b.iter_mut().rev().enumerate() {
+                *c = (raw >> (i * 8)) as u8;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The shift of the bits from the least significant bit of `b`.
+    fn shift(&self, b: &[u8], bits_offset: usize, nr_bits: usize) -> Result<usize, Error> {
+        let total = b.len() * 8;
+
+        if nr_bits == 0 || bits_offset + nr_bits > total || total > 128 {
+            return Err(OutOfRange("bitfield size", nr_bits as u64));
+        }
+
+        Ok(if self.types.is_le() {
+            bits_offset
+        } else {
+            total - bits_offset - nr_bits
+        })
+    }
+
+    /// Read an unsigned integer of up to 16 bytes in the byte order of the BTF.
+    fn uint(&self, b: &[u8]) -> u128 {
+        if self.types.is_le() {
+            b.iter().rev().fold(0, |n, &c| n << 8 | c as u128)
+        } else {
+            b.iter().fold(0, |n, &c| n << 8 | c as u128)
+        }
+    }
+}
+
+fn slice(data: &[u8], off: usize, len: usize) -> Result<&[u8], Error> {
+    off.checked_add(len)
+        .and_then(|end| data.get(off..end))
+        .ok_or(EndOfInput)
+}
+
+fn mask(nr_bits: usize) -> u128 {
+    if nr_bits >= 128 {
+        u128::MAX
+    } else {
+        (1 << nr_bits) - 1
+    }
+}
+
+fn sign_extend(raw: u128, nr_bits: usize) -> i128 {
+    let shift = 128 - nr_bits.min(128);
+
+    ((raw << shift) as i128) >> shift
+}
+
+/// Match the raw bits of an enum, of `nr_bits` bits, with its enumerators.
+fn enum_value<'a>(ty: Type<'a>, raw: u128, nr_bits: usize) -> Value<'a> {
+    let (signed, values) = match ty {
+        Type::Enum { signed, values, .. } | Type::Enum64 { signed, values, .. } => (signed, values),
+        _ => unreachable!(),
+    };
+
+    let raw = if signed {
+        sign_extend(raw, nr_bits) as u64
+    } else {
+        raw as u64
+    };
+    let value = EnumValue::new(raw, signed);
+
+    // the enumerators of a 32 bits enum are sign extended the same way
+    let name = values
+        .into_iter()
+        .find(|v| v.val.as_u64() == raw)
+        .and_then(|v| v.name);
+
+    Value::Enum { name, value }
+}
+
+#[cfg(feature = "serde")]
+impl<'a> Serialize for Value<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match *self {
+            Value::Bool(b) => serializer.serialize_bool(b),
+            Value::Int(n) => match i64::try_from(n) {
+                Ok(n) => serializer.serialize_i64(n),
+                Err(_) => serializer.serialize_i128(n),
+            },
+            Value::Uint(n) => match u64::try_from(n) {
+                Ok(n) => serializer.serialize_u64(n),
+                Err(_) => serializer.serialize_u128(n),
+            },
+            Value::Float(n) => serializer.serialize_f64(n),
+            Value::Enum {
+                name: Some(ref name),
+                ..
+            } => serializer.serialize_str(name),
+            Value::Enum { value, .. } => value.serialize(serializer),
+            Value::Struct(ref fields) => {
+                let mut map = serializer.serialize_map(None)?;
+
+                serialize_fields(&mut map, fields)?;
+
+                map.end()
+            }
+            Value::Array(ref values) => {
+                let mut seq = serializer.serialize_seq(Some(values.len()))?;
+
+                for v in values {
+                    seq.serialize_element(v)?;
+                }
+
+                seq.end()
+            }
+            Value::Ptr(addr) => serializer.serialize_u64(addr),
+            Value::Bytes(b) => match self.as_str() {
+                Some(s) => serializer.serialize_str(s),
+                None => serializer.serialize_bytes(b),
+            },
+        }
+    }
+}
+
+/// The fields of the anonymous structs and unions are flattened into their parent, like C accesses them.
+#[cfg(feature = "serde")]
+fn serialize_fields<M: SerializeMap>(map: &mut M, fields: &[Field]) -> Result<(), M::Error> {
+    for f in fields {
+        match (&f.name, &f.value) {
+            (Some(name), value) => map.serialize_entry(name, value)?,
+            (None, Value::Struct(fields)) => serialize_fields(map, fields)?,
+            (None, _) => {}
+        }
+    }
+
+    Ok(())
+}
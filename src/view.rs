@@ -0,0 +1,695 @@
+use core::fmt;
+use core::iter::FusedIterator;
+use core::mem;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use derive_more::{Display, IsVariant};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    error::{Location, Section},
+    file::{self, Kind, ReadExt},
+    Error::{self, *},
+};
+
+/// A type decoded without allocating, borrowing its names from the string section.
+///
+/// Unlike [`crate::Type`], the members, values, params and entries are
+/// decoded lazily, when iterating over them.
+#[derive(Debug, Clone, IsVariant)]
+pub enum Type<'a> {
+    Void,
+    Int {
+        name: &'a str,
+        size: usize,
+        bits_offset: usize,
+        nr_bits: usize,
+        encoding: file::IntEncoding,
+    },
+    Ptr {
+        type_id: u32,
+    },
+    Array {
+        type_id: u32,
+        index_type_id: u32,
+        nr_elems: u32,
+    },
+    Struct {
+        name: Option<&'a str>,
+        size: usize,
+        members: Members<'a>,
+    },
+    Union {
+        name: Option<&'a str>,
+        size: usize,
+        members: Members<'a>,
+    },
+    Enum {
+        name: Option<&'a str>,
+        size: usize,
+        signed: bool,
+        values: Values<'a>,
+    },
+    Enum64 {
+        name: Option<&'a str>,
+        size: usize,
+        signed: bool,
+        values: Values<'a>,
+    },
+    Fwd {
+        name: &'a str,
+        fwd_kind: file::Kind,
+    },
+    Typedef {
+        name: &'a str,
+        type_id: u32,
+    },
+    Volatile {
+        type_id: u32,
+    },
+    Const {
+        type_id: u32,
+    },
+    Restrict {
+        type_id: u32,
+    },
+    Func {
+        name: &'a str,
+        type_id: u32,
+        linkage: file::Linkage,
+    },
+    FuncProto {
+        ret_type_id: u32,
+        params: Params<'a>,
+    },
+    Variable {
+        name: &'a str,
+        type_id: u32,
+        linkage: file::Linkage,
+    },
+    DataSec {
+        name: &'a str,
+        size: usize,
+        sections: Entries<'a>,
+    },
+    Float {
+        name: &'a str,
+        size: usize,
+    },
+    DeclTag {
+        name: &'a str,
+        type_id: u32,
+        component_idx: i32,
+    },
+    TypeTag {
+        name: &'a str,
+        type_id: u32,
+    },
+}
+
+impl<'a> Type<'a> {
+    pub fn name(&self) -> Option<&'a str> {
+        match *self {
+            Type::Void => None,
+            Type::Int { name, .. } => Some(name),
+            Type::Ptr { .. } => None,
+            Type::Array { .. } => None,
+            Type::Struct { name, .. } => name,
+            Type::Union { name, .. } => name,
+            Type::Enum { name, .. } => name,
+            Type::Enum64 { name, .. } => name,
+            Type::Fwd { name, .. } => Some(name),
+            Type::Typedef { name, .. } => Some(name),
+            Type::Volatile { .. } => None,
+            Type::Const { .. } => None,
+            Type::Restrict { .. } => None,
+            Type::Func { name, .. } => Some(name),
+            Type::FuncProto { .. } => None,
+            Type::Variable { name, .. } => Some(name),
+            Type::DataSec { name, .. } => Some(name),
+            Type::Float { name, .. } => Some(name),
+            Type::DeclTag { name, .. } => Some(name),
+            Type::TypeTag { name, .. } => Some(name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Member<'a> {
+    pub name: Option<&'a str>,
+    pub type_id: u32,
+    pub bits_offset: u32,
+    pub bitfield_size: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Enum<'a> {
+    pub name: Option<&'a str>,
+    pub val: EnumValue,
+}
+
+/// The value of an enumerator, signed if the enum is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(untagged))]
+pub enum EnumValue {
+    #[display(fmt = "{}", _0)]
+    Signed(i64),
+    #[display(fmt = "{}", _0)]
+    Unsigned(u64),
+}
+
+impl EnumValue {
+    /// Interpret the raw bits of a value, sign extended for a signed value.
+    pub fn new(raw: u64, signed: bool) -> Self {
+        if signed {
+            EnumValue::Signed(raw as i64)
+        } else {
+            EnumValue::Unsigned(raw)
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, EnumValue::Signed(_))
+    }
+
+    /// The raw bits of the value.
+    pub fn as_u64(&self) -> u64 {
+        match *self {
+            EnumValue::Signed(v) => v as u64,
+            EnumValue::Unsigned(v) => v,
+        }
+    }
+
+    pub fn as_i64(&self) -> i64 {
+        self.as_u64() as i64
+    }
+
+    /// Returns `true` if the value can be encoded in a 32 bits BTF_KIND_ENUM.
+    pub fn fits_u32(&self) -> bool {
+        match *self {
+            EnumValue::Signed(v) => i32::try_from(v).is_ok(),
+            EnumValue::Unsigned(v) => u32::try_from(v).is_ok(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Param<'a> {
+    pub name: Option<&'a str>,
+    pub type_id: u32,
+}
+
+impl<'a> Param<'a> {
+    pub fn is_variable_argument(&self) -> bool {
+        self.name.is_none() && self.type_id == 0
+    }
+}
+
+pub type Members<'a> = Records<'a, Member<'a>>;
+pub type Values<'a> = Records<'a, Enum<'a>>;
+pub type Params<'a> = Records<'a, Param<'a>>;
+pub type Entries<'a> = Records<'a, file::VarSectInfo>;
+
+/// A record following a type, which borrows its name from the string section.
+pub trait Record<'a> {
+    fn with_name(self, name: Option<&'a str>) -> Self;
+}
+
+impl<'a> Record<'a> for Member<'a> {
+    fn with_name(self, name: Option<&'a str>) -> Self {
+        Member { name, ..self }
+    }
+}
+
+impl<'a> Record<'a> for Enum<'a> {
+    fn with_name(self, name: Option<&'a str>) -> Self {
+        Enum { name, ..self }
+    }
+}
+
+impl<'a> Record<'a> for Param<'a> {
+    fn with_name(self, name: Option<&'a str>) -> Self {
+        Param { name, ..self }
+    }
+}
+
+impl<'a> Record<'a> for file::VarSectInfo {
+    fn with_name(self, _: Option<&'a str>) -> Self {
+        self
+    }
+}
+
+/// Decode a record without its name, returning the offset of the name instead.
+type Decode<'a, T> = fn(&mut untrusted::Reader<'a>, bool) -> Result<(u32, T), Error>;
+
+/// The `vlen` records following a type, decoded one at a time.
+///
+/// The records have a fixed size, so a record that can't be decoded,
+/// e.g. for an invalid name, doesn't stop the iteration.
+#[derive(Clone)]
+pub struct Records<'a, T> {
+    data: &'a [u8],
+    record_size: usize,
+    /// The offset of the next record from the start of the type.
+    offset: usize,
+    /// Some records are missing at the end of the input.
+    truncated: bool,
+    kflag: bool,
+    strs: file::Strings<'a>,
+    decode: Decode<'a, T>,
+}
+
+impl<'a, T> Records<'a, T> {
+    /// Read the records following `ty`.
+    ///
+    /// If `partial`, the records are read up to the end of the input,
+    /// and the iteration fails at the first missing record.
+    fn read<R>(
+        r: &mut untrusted::Reader<'a>,
+        ty: &file::Type,
+        strs: &file::Strings<'a>,
+        decode: Decode<'a, T>,
+        partial: bool,
+    ) -> Result<Self, Error> {
+        let record_size = mem::size_of::<R>();
+        let (data, truncated) = match r.read_bytes(ty.vlen() * record_size) {
+            Ok(data) => (data, false),
+            Err(err) if !partial => return Err(at(mem::size_of::<file::Type>())(err.into())),
+            Err(_) => {
+                let (data, _) = r.read_partial(|r| {
+                    while r.read_bytes(record_size).is_ok() {}
+
+                    Ok::<_, Error>(())
+                })?;
+
+                (data, true)
+            }
+        };
+
+        Ok(Records {
+            data: data.as_slice_less_safe(),
+            record_size,
+            offset: mem::size_of::<file::Type>(),
+            truncated,
+            kflag: ty.kflag(),
+            strs: *strs,
+            decode,
+        })
+    }
+
+    /// Decode the next record, leaving its name to be looked up by the caller.
+    ///
+    /// The errors are located at the record, relative to the start of the type.
+    pub(crate) fn next_unnamed(&mut self) -> Option<Result<(u32, T), Error>> {
+        let offset = self.offset;
+        let res = if !self.data.is_empty() {
+            let (record, rest) = self.data.split_at(self.record_size);
+            let (kflag, decode) = (self.kflag, self.decode);
+
+            self.data = rest;
+            self.offset += self.record_size;
+
+            untrusted::Input::from(record).read_all(EndOfInput, |r| decode(r, kflag))
+        } else if self.truncated {
+            self.truncated = false;
+
+            Err(EndOfInput)
+        } else {
+            return None;
+        };
+
+        Some(res.map_err(at(offset)))
+    }
+}
+
+impl<'a, T> fmt::Debug for Records<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let n = self.data.len() / self.record_size;
+
+        f.debug_struct("Records")
+            .field("len", &n)
+            .field("truncated", &self.truncated)
+            .finish()
+    }
+}
+
+impl<'a, T: Record<'a>> Iterator for Records<'a, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let strs = self.strs;
+
+        self.next_unnamed()
+            .map(|res| res.and_then(|(name_off, record)| Ok(record.with_name(strs.get(name_off)?))))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.data.len() / self.record_size + self.truncated as usize;
+
+        (n, Some(n))
+    }
+}
+
+impl<'a, T: Record<'a>> ExactSizeIterator for Records<'a, T> {}
+
+impl<'a, T: Record<'a>> FusedIterator for Records<'a, T> {}
+
+fn member<'a, O: ByteOrder>(
+    r: &mut untrusted::Reader<'a>,
+    kflag: bool,
+) -> Result<(u32, Member<'a>), Error> {
+    let m = file::Member::read::<O>(r)?;
+
+    Ok((
+        m.name_off,
+        if kflag {
+            Member {
+                name: None,
+                type_id: m.ty,
+                bits_offset: m.bit_offset(),
+                bitfield_size: m.bitfield_size(),
+            }
+        } else {
+            Member {
+                name: None,
+                type_id: m.ty,
+                bits_offset: m.offset,
+                bitfield_size: 0,
+            }
+        },
+    ))
+}
+
+fn enum_value<'a, O: ByteOrder>(
+    r: &mut untrusted::Reader<'a>,
+    kflag: bool,
+) -> Result<(u32, Enum<'a>), Error> {
+    let v = file::Enum::read::<O>(r)?;
+
+    Ok((
+        v.name_off,
+        Enum {
+            name: None,
+            val: if kflag {
+                EnumValue::Signed(v.val as i32 as i64)
+            } else {
+                EnumValue::Unsigned(v.val as u64)
+            },
+        },
+    ))
+}
+
+fn enum64_value<'a, O: ByteOrder>(
+    r: &mut untrusted::Reader<'a>,
+    kflag: bool,
+) -> Result<(u32, Enum<'a>), Error> {
+    let v = file::Enum64::read::<O>(r)?;
+
+    Ok((
+        v.name_off,
+        Enum {
+            name: None,
+            val: EnumValue::new(((v.val_hi32 as u64) << 32) + (v.val_lo32 as u64), kflag),
+        },
+    ))
+}
+
+fn param<'a, O: ByteOrder>(
+    r: &mut untrusted::Reader<'a>,
+    _: bool,
+) -> Result<(u32, Param<'a>), Error> {
+    let p = file::Param::read::<O>(r)?;
+
+    Ok((
+        p.name_off,
+        Param {
+            name: None,
+            type_id: p.ty,
+        },
+    ))
+}
+
+fn entry<'a, O: ByteOrder>(
+    r: &mut untrusted::Reader<'a>,
+    _: bool,
+) -> Result<(u32, file::VarSectInfo), Error> {
+    file::VarSectInfo::read::<O>(r).map(|v| (0, v))
+}
+
+/// An iterator over the types of a BTF file, which doesn't allocate.
+pub struct Types<'a> {
+    is_le: bool,
+    types: untrusted::Reader<'a>,
+    strs: file::Strings<'a>,
+    start_id: u32,
+    next_id: u32,
+    offset: usize,
+}
+
+impl<'a> Types<'a> {
+    pub fn parse(input: untrusted::Input<'a>) -> Result<Types<'a>, Error> {
+        file::parse(input).map(|f| Types::new(&f, f.strings(), 1))
+    }
+
+    /// Parse a split BTF, whose type ids and string offsets continue after those of `base`.
+    pub fn parse_split(
+        input: untrusted::Input<'a>,
+        base: &file::File<'a>,
+    ) -> Result<Types<'a>, Error> {
+        let start_id = base.type_count()? + 1;
+
+        file::parse(input)
+            .map(|f| Types::new(&f, file::Strings::split(base.strs, f.strs), start_id))
+    }
+
+    pub fn new(f: &file::File<'a>, strs: file::Strings<'a>, start_id: u32) -> Types<'a> {
+        Types {
+            is_le: f.is_le(),
+            types: untrusted::Reader::new(f.types),
+            strs,
+            start_id,
+            next_id: start_id,
+            offset: 0,
+        }
+    }
+
+    /// The type id of the first type, which is 1 unless this is a split BTF.
+    pub fn start_id(&self) -> u32 {
+        self.start_id
+    }
+
+    pub fn strings(&self) -> &file::Strings<'a> {
+        &self.strs
+    }
+}
+
+impl<'a> Iterator for Types<'a> {
+    type Item = Result<Type<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.types.at_end() {
+            return None;
+        }
+
+        let (type_id, start) = (self.next_id, self.offset);
+        let is_le = self.is_le;
+        let strs = &self.strs;
+
+        match self.types.read_partial(|r| {
+            if is_le {
+                read_type::<LittleEndian>(r, strs)
+            } else {
+                read_type::<BigEndian>(r, strs)
+            }
+        }) {
+            Ok((record, ty)) => {
+                self.next_id += 1;
+                self.offset += record.len();
+
+                Some(Ok(ty))
+            }
+            Err(err) => {
+                // the following records can't be located once a record failed to decode
+                self.types.skip_to_end();
+
+                Some(Err(err.locate(|loc: &mut Location| {
+                    loc.type_id = Some(type_id);
+                    loc.rebase(Section::Types, start);
+                })))
+            }
+        }
+    }
+}
+
+impl<'a> FusedIterator for Types<'a> {}
+
+/// Decode a type record, leaving its members, values, params or entries to be decoded lazily.
+///
+/// The location of an error is relative to the start of the record,
+/// the caller knows the type id and where the record starts.
+pub fn read_type<'a, O: ByteOrder>(
+    r: &mut untrusted::Reader<'a>,
+    strs: &file::Strings<'a>,
+) -> Result<Type<'a>, Error> {
+    let ty = file::Type::read::<O>(r)?;
+
+    strs.get(ty.name_off)
+        .and_then(|name| decode_type::<O>(r, &ty, name, strs, false))
+        .map_err(|err| {
+            err.locate(|loc| {
+                loc.kind = Some(ty.kind());
+            })
+        })
+}
+
+/// What a type of this kind must be named, if it can't be anonymous.
+pub(crate) fn required_name(kind: Kind) -> Option<&'static str> {
+    match kind {
+        Kind::Integer => Some("int name"),
+        Kind::Forward => Some("forward name"),
+        Kind::Typedef => Some("typedef name"),
+        Kind::Func => Some("func name"),
+        Kind::Variable => Some("var name"),
+        Kind::DataSection => Some("datasec name"),
+        Kind::Float => Some("float name"),
+        Kind::DeclTag => Some("decl_tag name"),
+        Kind::TypeTag => Some("type_tag name"),
+        _ => None,
+    }
+}
+
+/// Decode the rest of a type record, named `name`.
+///
+/// If `partial`, the records following the type are read up to the end of the input,
+/// see [`Records::next_unnamed`].
+pub(crate) fn decode_type<'a, O: ByteOrder>(
+    r: &mut untrusted::Reader<'a>,
+    ty: &file::Type,
+    name: Option<&'a str>,
+    strs: &file::Strings<'a>,
+    partial: bool,
+) -> Result<Type<'a>, Error> {
+    if let (None, Some(what)) = (name, required_name(ty.kind())) {
+        return Err(Expected(what));
+    }
+
+    let named = name.unwrap_or_default();
+
+    Ok(match ty.kind() {
+        Kind::Unknown => Type::Void,
+        Kind::Integer => {
+            let int = file::Int::read::<O>(r).map_err(at(mem::size_of::<file::Type>()))?;
+
+            Type::Int {
+                name: named,
+                size: ty.size(),
+                bits_offset: int.offset(),
+                nr_bits: int.bits(),
+                encoding: int.encoding(),
+            }
+        }
+        Kind::Pointer => Type::Ptr {
+            type_id: ty.type_id(),
+        },
+        Kind::Array => {
+            let array = file::Array::read::<O>(r).map_err(at(mem::size_of::<file::Type>()))?;
+
+            Type::Array {
+                type_id: array.ty,
+                index_type_id: array.index_ty,
+                nr_elems: array.nelems,
+            }
+        }
+        Kind::Struct => Type::Struct {
+            name,
+            size: ty.size(),
+            members: Records::read::<file::Member>(r, ty, strs, member::<O>, partial)?,
+        },
+        Kind::Union => Type::Union {
+            name,
+            size: ty.size(),
+            members: Records::read::<file::Member>(r, ty, strs, member::<O>, partial)?,
+        },
+        Kind::Enum => Type::Enum {
+            name,
+            size: ty.size(),
+            signed: ty.kflag(),
+            values: Records::read::<file::Enum>(r, ty, strs, enum_value::<O>, partial)?,
+        },
+        Kind::Enum64 => Type::Enum64 {
+            name,
+            size: ty.size(),
+            signed: ty.kflag(),
+            values: Records::read::<file::Enum64>(r, ty, strs, enum64_value::<O>, partial)?,
+        },
+        Kind::Forward => Type::Fwd {
+            name: named,
+            fwd_kind: if ty.kflag() {
+                Kind::Union
+            } else {
+                Kind::Struct
+            },
+        },
+        Kind::Typedef => Type::Typedef {
+            name: named,
+            type_id: ty.type_id(),
+        },
+        Kind::Volatile => Type::Volatile {
+            type_id: ty.type_id(),
+        },
+        Kind::Const => Type::Const {
+            type_id: ty.type_id(),
+        },
+        Kind::Restrict => Type::Restrict {
+            type_id: ty.type_id(),
+        },
+        Kind::Func => Type::Func {
+            name: named,
+            type_id: ty.type_id(),
+            linkage: file::Linkage::from(ty.vlen() as u32),
+        },
+        Kind::FuncProto => Type::FuncProto {
+            ret_type_id: ty.type_id(),
+            params: Records::read::<file::Param>(r, ty, strs, param::<O>, partial)?,
+        },
+        Kind::Variable => Type::Variable {
+            name: named,
+            type_id: ty.type_id(),
+            linkage: file::Var::read::<O>(r)
+                .map_err(at(mem::size_of::<file::Type>()))?
+                .linkage,
+        },
+        Kind::DataSection => Type::DataSec {
+            name: named,
+            size: ty.size(),
+            sections: Records::read::<file::VarSectInfo>(r, ty, strs, entry::<O>, partial)?,
+        },
+        Kind::Float => Type::Float {
+            name: named,
+            size: ty.size(),
+        },
+        Kind::DeclTag => Type::DeclTag {
+            name: named,
+            type_id: ty.type_id(),
+            component_idx: file::DeclTag::read::<O>(r)
+                .map_err(at(mem::size_of::<file::Type>()))?
+                .component_idx,
+        },
+        Kind::TypeTag => Type::TypeTag {
+            name: named,
+            type_id: ty.type_id(),
+        },
+        Kind::Other(kind) => return Err(UnknownKind(kind)),
+    })
+}
+
+/// Locate an error at `offset` from the start of the record.
+fn at(offset: usize) -> impl FnOnce(Error) -> Error {
+    move |err| {
+        err.locate(|loc| {
+            loc.offset.get_or_insert(offset);
+        })
+    }
+}
@@ -0,0 +1,625 @@
+use core::marker::PhantomData;
+use core::mem;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "std")] {
+        use std::borrow::Cow;
+        use std::collections::BTreeMap;
+    } else {
+        use alloc::{borrow::Cow, collections::BTreeMap, vec, vec::Vec};
+    }
+}
+
+use byteorder::ByteOrder;
+
+use crate::{
+    file::{self, Kind, WriteExt},
+    ty::{self, Type},
+    Error::{self, *},
+};
+
+/// A deduplicated string section, starting with the mandatory empty string.
+#[derive(Clone, Debug)]
+pub struct Strings<'a> {
+    buf: Vec<u8>,
+    offsets: BTreeMap<Cow<'a, str>, u32>,
+}
+
+impl<'a> Default for Strings<'a> {
+    fn default() -> Self {
+        Strings {
+            buf: vec![0],
+            offsets: BTreeMap::new(),
+        }
+    }
+}
+
+impl<'a> Strings<'a> {
+    pub fn add<S: Into<Cow<'a, str>>>(&mut self, s: S) -> u32 {
+        let s = s.into();
+
+        if s.is_empty() {
+            return 0;
+        }
+        if let Some(&off) = self.offsets.get(s.as_ref()) {
+            return off;
+        }
+
+        let off = self.buf.len() as u32;
+
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+        self.offsets.insert(s, off);
+
+        off
+    }
+
+    pub fn add_opt<S: Into<Cow<'a, str>>>(&mut self, s: Option<S>) -> u32 {
+        s.map_or(0, |s| self.add(s))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Remove the strings added after the first `len` bytes.
+    fn truncate(&mut self, len: usize) {
+        self.buf.truncate(len);
+        self.offsets.retain(|_, &mut off| (off as usize) < len);
+    }
+}
+
+/// Encodes a sequence of types into a `.BTF` blob with the byte order `O`.
+///
+/// Types are numbered in the order they are added, starting from 1.
+pub struct Writer<'a, O> {
+    types: Vec<u8>,
+    strs: Strings<'a>,
+    next_id: u32,
+    order: PhantomData<O>,
+}
+
+impl<'a, O: ByteOrder> Default for Writer<'a, O> {
+    fn default() -> Self {
+        Writer {
+            types: Vec::new(),
+            strs: Strings::default(),
+            next_id: 1,
+            order: PhantomData,
+        }
+    }
+}
+
+impl<'a, O: ByteOrder> Writer<'a, O> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a type and return its type id, the writer is left unchanged if it fails.
+    pub fn add_type(&mut self, ty: &Type<'a>) -> Result<u32, Error> {
+        write_type::<O>(&mut self.types, &mut self.strs, ty)?;
+
+        let type_id = self.next_id;
+        self.next_id += 1;
+
+        Ok(type_id)
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        let strs = self.strs.as_bytes();
+        let hdr = file::Header {
+            magic: file::Header::MAGIC,
+            version: file::Header::VERSION,
+            flags: 0,
+            len: mem::size_of::<file::Header>() as u32,
+            type_off: 0,
+            type_len: self.types.len() as u32,
+            str_off: self.types.len() as u32,
+            str_len: strs.len() as u32,
+        };
+
+        let mut w = Vec::with_capacity(hdr.len as usize + self.types.len() + strs.len());
+
+        hdr.write::<O>(&mut w);
+        w.extend_from_slice(&self.types);
+        w.extend_from_slice(strs);
+        w
+    }
+}
+
+/// Encode `types` into a `.BTF` blob with the byte order `O`.
+pub fn write<'a, O: ByteOrder>(types: &[Type<'a>]) -> Result<Vec<u8>, Error> {
+    let mut w = Writer::<O>::new();
+
+    for ty in types {
+        w.add_type(ty)?;
+    }
+
+    Ok(w.finish())
+}
+
+/// Append the record of `ty` to `w` and its names to `strs`,
+/// leaving both unchanged if it can't be encoded.
+pub fn write_type<'a, O: ByteOrder>(
+    w: &mut Vec<u8>,
+    strs: &mut Strings<'a>,
+    ty: &Type<'a>,
+) -> Result<(), Error> {
+    let (len, strs_len) = (w.len(), strs.buf.len());
+
+    encode_type::<O>(w, strs, ty).inspect_err(|_| {
+        w.truncate(len);
+        strs.truncate(strs_len);
+    })
+}
+
+fn encode_type<'a, O: ByteOrder>(
+    w: &mut Vec<u8>,
+    strs: &mut Strings<'a>,
+    ty: &Type<'a>,
+) -> Result<(), Error> {
+    let header = |name_off: u32, kind: Kind, kflag: bool, vlen: usize, size_or_type: u32| {
+        if vlen > u16::MAX as usize {
+            Err(OutOfRange("vlen", vlen as u64))
+        } else {
+            Ok(file::Type {
+                name_off,
+                info: file::Info::new(kind, kflag, vlen),
+                size_or_type,
+            })
+        }
+    };
+
+    match *ty {
+        Type::Void => header(0, Kind::Unknown, false, 0, 0)?.write::<O>(w),
+        Type::Int {
+            ref name,
+            size,
+            bits_offset,
+            nr_bits,
+            encoding,
+        } => {
+            header(strs.add(name.clone()), Kind::Integer, false, 0, size as u32)?.write::<O>(w);
+            file::Int::new(encoding, bits_offset, nr_bits).write::<O>(w);
+        }
+        Type::Ptr { type_id } => header(0, Kind::Pointer, false, 0, type_id)?.write::<O>(w),
+        Type::Array {
+            type_id,
+            index_type_id,
+            nr_elems,
+        } => {
+            header(0, Kind::Array, false, 0, 0)?.write::<O>(w);
+            file::Array {
+                ty: type_id,
+                index_ty: index_type_id,
+                nelems: nr_elems,
+            }
+            .write::<O>(w);
+        }
+        Type::Struct {
+            ref name,
+            size,
+            ref members,
+        } => write_members::<O>(w, strs, Kind::Struct, name.clone(), size, members)?,
+        Type::Union {
+            ref name,
+            size,
+            ref members,
+        } => write_members::<O>(w, strs, Kind::Union, name.clone(), size, members)?,
+        Type::Enum {
+            ref name,
+            size,
+            signed,
+            ref values,
+        } => {
+            if let Some(v) = values.iter().find(|v| !v.val.fits_u32()) {
+                return Err(OutOfRange("enum value", v.val.as_u64()));
+            }
+
+            header(
+                strs.add_opt(name.clone()),
+                Kind::Enum,
+                signed,
+                values.len(),
+                size as u32,
+            )?
+            .write::<O>(w);
+
+            for v in values {
+                file::Enum {
+                    name_off: strs.add_opt(v.name.clone()),
+                    val: v.val.as_u64() as u32,
+                }
+                .write::<O>(w);
+            }
+        }
+        Type::Enum64 {
+            ref name,
+            size,
+            signed,
+            ref values,
+        } => {
+            header(
+                strs.add_opt(name.clone()),
+                Kind::Enum64,
+                signed,
+                values.len(),
+                size as u32,
+            )?
+            .write::<O>(w);
+
+            for v in values {
+                file::Enum64 {
+                    name_off: strs.add_opt(v.name.clone()),
+                    val_lo32: v.val.as_u64() as u32,
+                    val_hi32: (v.val.as_u64() >> 32) as u32,
+                }
+                .write::<O>(w);
+            }
+        }
+        Type::Fwd { ref name, fwd_kind } => header(
+            strs.add(name.clone()),
+            Kind::Forward,
+            fwd_kind == Kind::Union,
+            0,
+            0,
+        )?
+        .write::<O>(w),
+        Type::Typedef { ref name, type_id } => {
+            header(strs.add(name.clone()), Kind::Typedef, false, 0, type_id)?.write::<O>(w)
+        }
+        Type::Volatile { type_id } => header(0, Kind::Volatile, false, 0, type_id)?.write::<O>(w),
+        Type::Const { type_id } => header(0, Kind::Const, false, 0, type_id)?.write::<O>(w),
+        Type::Restrict { type_id } => header(0, Kind::Restrict, false, 0, type_id)?.write::<O>(w),
+        Type::Func {
+            ref name,
+            type_id,
+            linkage,
+        } => header(
+            strs.add(name.clone()),
+            Kind::Func,
+            false,
+            u32::from(linkage) as usize,
+            type_id,
+        )?
+        .write::<O>(w),
+        Type::FuncProto {
+            ret_type_id,
+            ref params,
+        } => {
+            header(0, Kind::FuncProto, false, params.len(), ret_type_id)?.write::<O>(w);
+
+            for p in params {
+                file::Param {
+                    name_off: strs.add_opt(p.name.clone()),
+                    ty: p.type_id,
+                }
+                .write::<O>(w);
+            }
+        }
+        Type::Variable {
+            ref name,
+            type_id,
+            linkage,
+        } => {
+            header(strs.add(name.clone()), Kind::Variable, false, 0, type_id)?.write::<O>(w);
+            file::Var { linkage }.write::<O>(w);
+        }
+        Type::DataSec {
+            ref name,
+            size,
+            ref sections,
+        } => {
+            header(
+                strs.add(name.clone()),
+                Kind::DataSection,
+                false,
+                sections.len(),
+                size as u32,
+            )?
+            .write::<O>(w);
+
+            for s in sections {
+                s.write::<O>(w);
+            }
+        }
+        Type::Float { ref name, size } => {
+            header(strs.add(name.clone()), Kind::Float, false, 0, size as u32)?.write::<O>(w)
+        }
+        Type::DeclTag {
+            ref name,
+            type_id,
+            component_idx,
+        } => {
+            header(strs.add(name.clone()), Kind::DeclTag, false, 0, type_id)?.write::<O>(w);
+            file::DeclTag { component_idx }.write::<O>(w);
+        }
+        Type::TypeTag { ref name, type_id } => {
+            header(strs.add(name.clone()), Kind::TypeTag, false, 0, type_id)?.write::<O>(w)
+        }
+    }
+
+    Ok(())
+}
+
+fn write_members<'a, O: ByteOrder>(
+    w: &mut Vec<u8>,
+    strs: &mut Strings<'a>,
+    kind: Kind,
+    name: Option<Cow<'a, str>>,
+    size: usize,
+    members: &[ty::Member<'a>],
+) -> Result<(), Error> {
+    if members.len() > u16::MAX as usize {
+        return Err(OutOfRange("vlen", members.len() as u64));
+    }
+
+    let kflag = members.iter().any(|m| m.bitfield_size != 0);
+
+    file::Type {
+        name_off: strs.add_opt(name.clone()),
+        info: file::Info::new(kind, kflag, members.len()),
+        size_or_type: size as u32,
+    }
+    .write::<O>(w);
+
+    for m in members {
+        let offset = if kflag {
+            if m.bits_offset > 0x00ff_ffff {
+                return Err(OutOfRange("member bits_offset", m.bits_offset as u64));
+            }
+            if m.bitfield_size > 0xff {
+                return Err(OutOfRange("member bitfield_size", m.bitfield_size as u64));
+            }
+
+            (m.bitfield_size << 24) | m.bits_offset
+        } else {
+            m.bits_offset
+        };
+
+        file::Member {
+            name_off: strs.add_opt(m.name.clone()),
+            ty: m.type_id,
+            offset,
+        }
+        .write::<O>(w);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{BigEndian, LittleEndian};
+
+    use super::*;
+    use crate::{
+        file::{IntEncoding, Linkage, VarSectInfo},
+        ty::{Enum, EnumValue, Member, Param},
+    };
+
+    fn types() -> Vec<Type<'static>> {
+        vec![
+            Type::Int {
+                name: "int".into(),
+                size: 4,
+                bits_offset: 0,
+                nr_bits: 32,
+                encoding: IntEncoding::SIGNED,
+            },
+            Type::Ptr { type_id: 1 },
+            Type::Array {
+                type_id: 1,
+                index_type_id: 1,
+                nr_elems: 16,
+            },
+            Type::Struct {
+                name: Some("foo".into()),
+                size: 8,
+                members: vec![
+                    Member {
+                        name: Some("a".into()),
+                        type_id: 1,
+                        bits_offset: 0,
+                        bitfield_size: 0,
+                    },
+                    Member {
+                        name: Some("b".into()),
+                        type_id: 1,
+                        bits_offset: 32,
+                        bitfield_size: 0,
+                    },
+                ],
+            },
+            Type::Union {
+                name: None,
+                size: 4,
+                members: vec![
+                    Member {
+                        name: Some("x".into()),
+                        type_id: 1,
+                        bits_offset: 0,
+                        bitfield_size: 3,
+                    },
+                    Member {
+                        name: Some("y".into()),
+                        type_id: 1,
+                        bits_offset: 0,
+                        bitfield_size: 0,
+                    },
+                ],
+            },
+            Type::Enum {
+                name: Some("e".into()),
+                size: 4,
+                signed: true,
+                values: vec![
+                    Enum {
+                        name: Some("A".into()),
+                        val: EnumValue::Signed(-1),
+                    },
+                    Enum {
+                        name: Some("B".into()),
+                        val: EnumValue::Signed(2),
+                    },
+                ],
+            },
+            Type::Enum64 {
+                name: Some("e64".into()),
+                size: 8,
+                signed: false,
+                values: vec![Enum {
+                    name: Some("C".into()),
+                    val: EnumValue::Unsigned(1 << 40),
+                }],
+            },
+            Type::Fwd {
+                name: "bar".into(),
+                fwd_kind: Kind::Union,
+            },
+            Type::Typedef {
+                name: "foo_t".into(),
+                type_id: 4,
+            },
+            Type::Volatile { type_id: 1 },
+            Type::Const { type_id: 1 },
+            Type::Restrict { type_id: 2 },
+            Type::FuncProto {
+                ret_type_id: 1,
+                params: vec![
+                    Param {
+                        name: Some("p".into()),
+                        type_id: 2,
+                    },
+                    Param {
+                        name: None,
+                        type_id: 0,
+                    },
+                ],
+            },
+            Type::Func {
+                name: "f".into(),
+                type_id: 13,
+                linkage: Linkage::Global,
+            },
+            Type::Variable {
+                name: "v".into(),
+                type_id: 1,
+                linkage: Linkage::Static,
+            },
+            Type::DataSec {
+                name: ".data".into(),
+                size: 4,
+                sections: vec![VarSectInfo {
+                    type_id: 15,
+                    offset: 0,
+                    size: 4,
+                }],
+            },
+            Type::Float {
+                name: "double".into(),
+                size: 8,
+            },
+            Type::DeclTag {
+                name: "tag".into(),
+                type_id: 4,
+                component_idx: 1,
+            },
+            Type::TypeTag {
+                name: "user".into(),
+                type_id: 1,
+            },
+        ]
+    }
+
+    fn round_trip<O: ByteOrder>() {
+        let types = types();
+        let b = write::<O>(&types).unwrap();
+        let parsed = crate::parse(&b)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(parsed, types);
+        assert_eq!(write::<O>(&parsed).unwrap(), b);
+    }
+
+    #[test]
+    fn round_trip_le() {
+        round_trip::<LittleEndian>();
+    }
+
+    #[test]
+    fn round_trip_be() {
+        round_trip::<BigEndian>();
+    }
+
+    #[test]
+    fn failed_type_is_not_written() {
+        let mut w = Writer::<LittleEndian>::new();
+        let int = types().remove(0);
+
+        assert_eq!(w.add_type(&int).unwrap(), 1);
+
+        let bad = [
+            Type::Struct {
+                name: Some("bad".into()),
+                size: 4,
+                members: vec![Member {
+                    name: Some("a".into()),
+                    type_id: 1,
+                    bits_offset: 0,
+                    bitfield_size: 0x100,
+                }],
+            },
+            Type::Union {
+                name: None,
+                size: 4,
+                members: vec![Member {
+                    name: None,
+                    type_id: 1,
+                    bits_offset: 0x0100_0000,
+                    bitfield_size: 1,
+                }],
+            },
+            Type::FuncProto {
+                ret_type_id: 1,
+                params: vec![
+                    Param {
+                        name: None,
+                        type_id: 1,
+                    };
+                    0x10000
+                ],
+            },
+            Type::Enum {
+                name: None,
+                size: 8,
+                signed: false,
+                values: vec![
+                    Enum {
+                        name: Some("A".into()),
+                        val: EnumValue::Unsigned(1),
+                    },
+                    Enum {
+                        name: Some("B".into()),
+                        val: EnumValue::Unsigned(1 << 40),
+                    },
+                ],
+            },
+        ];
+
+        for ty in &bad {
+            assert!(w.add_type(ty).is_err());
+        }
+
+        assert_eq!(w.add_type(&Type::Ptr { type_id: 1 }).unwrap(), 2);
+
+        let mut expected = Writer::<LittleEndian>::new();
+
+        expected.add_type(&int).unwrap();
+        expected.add_type(&Type::Ptr { type_id: 1 }).unwrap();
+
+        assert_eq!(w.finish(), expected.finish());
+    }
+}